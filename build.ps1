@@ -10,6 +10,9 @@ Write-Host "Installing build targets..." -ForegroundColor Yellow
 rustup target add i686-pc-windows-msvc
 rustup target add x86_64-pc-windows-msvc
 
+# Record the commit being built in the library version string
+$env:TOMMIDILLAN_BUILD_HASH = (git rev-parse --short HEAD 2>$null)
+
 # Build 64-bit version
 Write-Host ""
 Write-Host "========================================" -ForegroundColor Cyan
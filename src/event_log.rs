@@ -0,0 +1,168 @@
+//! Binary log of received MIDI messages, for capturing a session and replaying it later
+//!
+//! A log file starts with the 8-byte magic `EVENT_LOG_MAGIC`, followed by one record per
+//! message:
+//!
+//! | bytes | content                                              |
+//! |-------|------------------------------------------------------|
+//! | 8     | timestamp in microseconds since logging started (LE) |
+//! | 2     | message length `n` (LE)                              |
+//! | n     | message bytes                                        |
+//!
+//! Logging to an existing log appends to it, so timestamps start again from 0 where each
+//! session begins.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// First bytes of every event log
+pub const EVENT_LOG_MAGIC: &[u8; 8] = b"MIDILOG1";
+
+/// Appends messages to a log file from a background thread, so the input callback only
+/// copies the message
+pub struct EventLogWriter {
+    sender: Sender<(u64, Vec<u8>)>,
+    thread: JoinHandle<io::Result<()>>,
+    started: Instant,
+}
+
+impl EventLogWriter {
+    /// Open (or create) a log for appending. Fails with `InvalidData` if the file exists
+    /// but is not an event log.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(EVENT_LOG_MAGIC)?;
+        } else {
+            let mut magic = [0u8; 8];
+            file.read_exact(&mut magic)?;
+            if &magic != EVENT_LOG_MAGIC {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "not a MIDI event log"));
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || write_records(BufWriter::new(file), receiver));
+        Ok(EventLogWriter { sender, thread, started: Instant::now() })
+    }
+
+    /// Queue a message for writing, timestamped now. Messages longer than 65535 bytes
+    /// cannot be recorded and are skipped.
+    pub fn log(&self, message: &[u8]) {
+        if message.len() > u16::MAX as usize {
+            return;
+        }
+        let timestamp_us = self.started.elapsed().as_micros() as u64;
+        let _ = self.sender.send((timestamp_us, message.to_vec()));
+    }
+
+    /// Write everything queued, close the file and report any write error
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.sender);
+        self.thread.join().unwrap_or_else(|_| Err(io::Error::other("event log writer panicked")))
+    }
+}
+
+/// Writer thread: flush whenever the queue runs empty, so a crash loses little
+fn write_records(mut file: BufWriter<File>, receiver: Receiver<(u64, Vec<u8>)>) -> io::Result<()> {
+    while let Ok(mut record) = receiver.recv() {
+        loop {
+            let (timestamp_us, message) = &record;
+            file.write_all(&timestamp_us.to_le_bytes())?;
+            file.write_all(&(message.len() as u16).to_le_bytes())?;
+            file.write_all(message)?;
+            match receiver.try_recv() {
+                Ok(next) => record = next,
+                Err(_) => break,
+            }
+        }
+        file.flush()?;
+    }
+    file.flush()
+}
+
+/// Records of a log, as (timestamp in microseconds, message)
+pub struct EventLogReader<R: BufRead> {
+    reader: R,
+}
+
+impl<R: BufRead> EventLogReader<R> {
+    /// Read a log from `reader`, checking the magic
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != EVENT_LOG_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a MIDI event log"));
+        }
+        Ok(EventLogReader { reader })
+    }
+
+    fn read_record(&mut self) -> io::Result<(u64, Vec<u8>)> {
+        let mut timestamp = [0u8; 8];
+        let mut length = [0u8; 2];
+        self.reader.read_exact(&mut timestamp)?;
+        self.reader.read_exact(&mut length)?;
+        let mut message = vec![0u8; u16::from_le_bytes(length) as usize];
+        self.reader.read_exact(&mut message)?;
+        Ok((u64::from_le_bytes(timestamp), message))
+    }
+}
+
+impl<R: BufRead> Iterator for EventLogReader<R> {
+    type Item = io::Result<(u64, Vec<u8>)>;
+
+    /// The next record; a record cut short (e.g. by a crash while logging) is an error
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.fill_buf() {
+            Ok([]) => None,
+            Ok(_) => Some(self.read_record()),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Open a log file for reading
+pub fn open_event_log(path: &Path) -> io::Result<EventLogReader<BufReader<File>>> {
+    EventLogReader::new(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_log_round_trip() {
+        let path = std::env::temp_dir().join(format!("labview_midi_{}_event.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        for _ in 0..2 {
+            let writer = EventLogWriter::create(&path).unwrap();
+            writer.log(&[0x90, 60, 100]);
+            writer.log(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+            writer.finish().unwrap();
+        }
+
+        let records: Vec<(u64, Vec<u8>)> = open_event_log(&path).unwrap().map(Result::unwrap).collect();
+        let messages: Vec<&[u8]> = records.iter().map(|(_, message)| message.as_slice()).collect();
+        assert_eq!(messages.len(), 4); // The second session was appended
+        assert_eq!(messages[0], [0x90, 60, 100]);
+        assert_eq!(messages[3], [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+        assert!(records[0].0 <= records[1].0);
+
+        // A truncated record is reported rather than silently dropped
+        let mut data = std::fs::read(&path).unwrap();
+        data.truncate(data.len() - 1);
+        let results: Vec<_> = EventLogReader::new(data.as_slice()).unwrap().collect();
+        assert_eq!(results.len(), 4);
+        assert!(results[3].is_err());
+
+        assert!(EventLogReader::new(&b"MThd\0\0\0\x06"[..]).is_err());
+        std::fs::write(&path, b"not a log").unwrap();
+        assert!(EventLogWriter::create(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
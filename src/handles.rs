@@ -0,0 +1,139 @@
+//! Handle allocation for the manager and MIDI file tables
+
+use std::collections::BTreeSet;
+
+/// Bits of a handle holding its slot; the bits above hold the slot's generation
+const SLOT_BITS: u32 = 20;
+const SLOT_MASK: i32 = (1 << SLOT_BITS) - 1;
+/// Generations wrap after this many reuses of one slot (the 11 bits left in a positive i32)
+const GENERATIONS: u16 = 1 << (31 - SLOT_BITS);
+
+/// Hands out positive handles, reusing released slots (lowest first) before minting new ones.
+/// A long-running application that keeps creating and destroying handles therefore stays
+/// within the slots it has live at once instead of counting towards `i32::MAX`.
+///
+/// Each release bumps the slot's generation, kept in the high bits of the handle, so a
+/// reused slot comes back as a different handle and a stale handle matches nothing. The
+/// first handle of every slot is the bare slot number (1, 2, 3, ...). Up to 2^20 handles
+/// can be live at once.
+#[derive(Debug)]
+pub struct HandleAllocator {
+    next: i32,
+    free: BTreeSet<i32>,
+    /// Current generation of every slot minted so far, indexed by slot
+    generations: Vec<u16>,
+}
+
+impl HandleAllocator {
+    pub const fn new() -> Self {
+        HandleAllocator {
+            next: 1,
+            free: BTreeSet::new(),
+            generations: Vec::new(),
+        }
+    }
+
+    pub fn allocate(&mut self) -> i32 {
+        let slot = match self.free.pop_first() {
+            Some(slot) => slot,
+            None => {
+                let slot = self.next;
+                self.next += 1;
+                slot
+            }
+        };
+        ((self.generation(slot) as i32) << SLOT_BITS) | slot
+    }
+
+    /// Make a handle available again. Handles that were never allocated, were already
+    /// released, or belong to an earlier generation of their slot are ignored.
+    pub fn release(&mut self, handle: i32) {
+        let slot = handle & SLOT_MASK;
+        if handle <= 0 || slot == 0 || slot >= self.next || self.free.contains(&slot) {
+            return;
+        }
+        let generation = (handle >> SLOT_BITS) as u16;
+        if generation != self.generation(slot) {
+            return;
+        }
+        self.set_generation(slot, (generation + 1) % GENERATIONS);
+        self.free.insert(slot);
+
+        // Give trailing free slots back to the counter so the free list stays short; their
+        // generations are kept for when the counter mints them again
+        while self.free.last() == Some(&(self.next - 1)) {
+            self.free.pop_last();
+            self.next -= 1;
+        }
+    }
+
+    /// Forget every handle and start again from 1
+    pub fn reset(&mut self) {
+        self.next = 1;
+        self.free.clear();
+        self.generations.clear();
+    }
+
+    fn generation(&self, slot: i32) -> u16 {
+        self.generations.get(slot as usize).copied().unwrap_or(0)
+    }
+
+    fn set_generation(&mut self, slot: i32, generation: u16) {
+        let slot = slot as usize;
+        if self.generations.len() <= slot {
+            self.generations.resize(slot + 1, 0);
+        }
+        self.generations[slot] = generation;
+    }
+}
+
+impl Default for HandleAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_released_slots_are_reused_lowest_first_with_a_new_generation() {
+        let mut handles = HandleAllocator::new();
+        assert_eq!((handles.allocate(), handles.allocate(), handles.allocate()), (1, 2, 3));
+
+        handles.release(2);
+        handles.release(1);
+        handles.release(2); // Double release is harmless
+        handles.release(7); // Never allocated
+        let reused_1 = handles.allocate();
+        let reused_2 = handles.allocate();
+        assert_eq!((reused_1 & SLOT_MASK, reused_2 & SLOT_MASK), (1, 2));
+        assert!(reused_1 != 1 && reused_2 != 2); // Stale handles no longer match
+        assert_eq!(handles.allocate(), 4);
+
+        handles.release(1); // Stale: slot 1 now belongs to reused_1
+        assert!(handles.free.is_empty());
+
+        handles.release(4);
+        handles.release(3);
+        assert_eq!(handles.allocate() & SLOT_MASK, 3); // Trailing slots went back to the counter
+        assert_ne!(handles.allocate(), 4); // ... keeping their generations
+
+        handles.reset();
+        assert_eq!(handles.allocate(), 1);
+    }
+
+    #[test]
+    fn test_generations_wrap_and_stay_positive() {
+        let mut handles = HandleAllocator::new();
+        let mut handle = handles.allocate();
+        for _ in 0..GENERATIONS {
+            handles.release(handle);
+            handle = handles.allocate();
+            assert!(handle > 0);
+            assert_eq!(handle & SLOT_MASK, 1);
+        }
+        assert_eq!(handle, 1);
+    }
+}
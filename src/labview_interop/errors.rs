@@ -7,12 +7,58 @@ pub type Result<T> = std::result::Result<T, LVInteropError>;
 #[derive(Debug, Clone)]
 pub enum LVInteropError {
     InternalError(InternalError),
+    /// Failure reported by the MIDI backend (midir / OS driver)
+    MidiBackend(String),
+    /// No MIDI device matches the requested index or name
+    DeviceNotFound(String),
+    /// The operation needs a connection that is not open
+    NotConnected(String),
+    /// A MIDI message failed validation
+    InvalidMessage(String),
+    /// The MIDI backend did not respond in time
+    Timeout(String),
+    /// A caller-supplied buffer length is above the configured maximum
+    LengthOutOfRange(String),
+    /// No MIDI device of the requested direction is present at all
+    NoDevices(String),
+    /// The port is already open on another handle in this process
+    PortInUse(String),
+    /// The operation is not available on this platform
+    NotSupported(String),
+    /// A device has an output port with the requested name but no input port
+    InputPortNotFound(String),
+    /// A device has an input port with the requested name but no output port
+    OutputPortNotFound(String),
+    /// A MIDI message is shorter than its status byte requires
+    TruncatedMessage(String),
+}
+
+impl LVInteropError {
+    /// Distinct negative return code for each error kind, for the FFI layer
+    pub fn code(&self) -> i32 {
+        match self {
+            LVInteropError::InternalError(_) => -1,
+            LVInteropError::DeviceNotFound(_) => -2,
+            LVInteropError::NotConnected(_) => -3,
+            LVInteropError::InvalidMessage(_) => -4,
+            LVInteropError::MidiBackend(_) => -5,
+            LVInteropError::Timeout(_) => -6,
+            LVInteropError::LengthOutOfRange(_) => -7,
+            LVInteropError::NoDevices(_) => -8,
+            LVInteropError::PortInUse(_) => -9,
+            LVInteropError::NotSupported(_) => -10,
+            LVInteropError::InputPortNotFound(_) => -11,
+            LVInteropError::OutputPortNotFound(_) => -12,
+            LVInteropError::TruncatedMessage(_) => -13,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum InternalError {
     NoLabviewApi(String),
     InvalidHandle,
+    InvalidReference,
     HandleCreationFailed,
 }
 
@@ -20,6 +66,18 @@ impl fmt::Display for LVInteropError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             LVInteropError::InternalError(e) => write!(f, "Internal error: {}", e),
+            LVInteropError::MidiBackend(s) => write!(f, "MIDI backend error: {}", s),
+            LVInteropError::DeviceNotFound(s) => write!(f, "Device not found: {}", s),
+            LVInteropError::NotConnected(s) => write!(f, "Not connected: {}", s),
+            LVInteropError::InvalidMessage(s) => write!(f, "Invalid MIDI message: {}", s),
+            LVInteropError::Timeout(s) => write!(f, "Timed out: {}", s),
+            LVInteropError::LengthOutOfRange(s) => write!(f, "Length out of range: {}", s),
+            LVInteropError::NoDevices(s) => write!(f, "No MIDI devices: {}", s),
+            LVInteropError::PortInUse(s) => write!(f, "Device already in use by this process: {}", s),
+            LVInteropError::NotSupported(s) => write!(f, "Not supported: {}", s),
+            LVInteropError::InputPortNotFound(s) => write!(f, "No input port: {}", s),
+            LVInteropError::OutputPortNotFound(s) => write!(f, "No output port: {}", s),
+            LVInteropError::TruncatedMessage(s) => write!(f, "Truncated MIDI message: {}", s),
         }
     }
 }
@@ -29,6 +87,7 @@ impl fmt::Display for InternalError {
         match self {
             InternalError::NoLabviewApi(s) => write!(f, "LabVIEW API not available: {}", s),
             InternalError::InvalidHandle => write!(f, "Invalid handle"),
+            InternalError::InvalidReference => write!(f, "Invalid LabVIEW reference (null refnum)"),
             InternalError::HandleCreationFailed => write!(f, "Handle creation failed"),
         }
     }
@@ -41,4 +100,29 @@ impl From<InternalError> for LVInteropError {
     fn from(e: InternalError) -> Self {
         LVInteropError::InternalError(e)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_distinct() {
+        let errors = [
+            LVInteropError::InternalError(InternalError::InvalidHandle),
+            LVInteropError::DeviceNotFound(String::new()),
+            LVInteropError::NotConnected(String::new()),
+            LVInteropError::InvalidMessage(String::new()),
+            LVInteropError::MidiBackend(String::new()),
+            LVInteropError::Timeout(String::new()),
+            LVInteropError::LengthOutOfRange(String::new()),
+            LVInteropError::NoDevices(String::new()),
+            LVInteropError::PortInUse(String::new()),
+            LVInteropError::NotSupported(String::new()),
+            LVInteropError::InputPortNotFound(String::new()),
+            LVInteropError::OutputPortNotFound(String::new()),
+            LVInteropError::TruncatedMessage(String::new()),
+        ];
+        let codes: Vec<i32> = errors.iter().map(LVInteropError::code).collect();
+        assert_eq!(codes, (1..=13).map(|code| -code).collect::<Vec<_>>());
+    }
+}
@@ -1,8 +1,9 @@
 //! LabVIEW runtime library integration
 
 use std::ffi::c_void;
-use std::sync::LazyLock;
+use std::sync::{Arc, RwLock};
 
+use dlopen2::raw::Library;
 use dlopen2::wrapper::{Container, WrapperApi};
 
 use crate::labview_interop::errors::{InternalError, LVInteropError, Result};
@@ -28,10 +29,36 @@ fn load_container<T: WrapperApi>() -> Result<Container<T>> {
     }
 }
 
-static SYNC_API: LazyLock<Result<Container<SyncApi>>> = LazyLock::new(load_container);
+/// Loaded on first use; `reload_sync_api` can replace it if that first load failed
+/// (e.g. because LabVIEW was not fully initialized yet)
+static SYNC_API: RwLock<Option<Result<Arc<Container<SyncApi>>>>> = RwLock::new(None);
 
-pub fn sync_api() -> Result<&'static Container<SyncApi>> {
-    SYNC_API.as_ref().map_err(|e| e.clone())
+pub fn sync_api() -> Result<Arc<Container<SyncApi>>> {
+    if let Some(cached) = SYNC_API.read().unwrap().as_ref() {
+        return cached.clone();
+    }
+    SYNC_API.write().unwrap()
+        .get_or_insert_with(|| load_container().map(Arc::new))
+        .clone()
+}
+
+/// Load the runtime container again and cache it. A failed reload does not replace a
+/// container that is already loaded; callers holding the old container keep it alive.
+pub fn reload_sync_api() -> Result<()> {
+    let reloaded = load_container().map(Arc::new);
+    let mut cached = SYNC_API.write().unwrap();
+    match reloaded {
+        Ok(container) => {
+            *cached = Some(Ok(container));
+            Ok(())
+        }
+        Err(e) => {
+            if !matches!(*cached, Some(Ok(_))) {
+                *cached = Some(Err(e.clone()));
+            }
+            Err(e)
+        }
+    }
 }
 
 #[derive(WrapperApi)]
@@ -42,4 +69,138 @@ pub struct SyncApi {
 
     #[dlopen2_name = "Occur"]
     occur: unsafe extern "C" fn(occurrence: MagicCookie) -> LVStatusCode,
-}
\ No newline at end of file
+}
+
+/// Version of the loaded LabVIEW runtime, or None if no runtime is loaded or its version
+/// cannot be determined. The runtime module's own version resource is used where the platform
+/// has one (Windows); otherwise the version is read from the module's path.
+pub fn runtime_version() -> Option<String> {
+    let path = runtime_module_path()?;
+    module_file_version(&path).or_else(|| version_from_module_path(&path))
+}
+
+/// Path of the library that provides the LabVIEW runtime exports (the LabVIEW executable
+/// itself, or the lvrt run-time engine). None if the runtime cannot be found.
+pub fn runtime_module_path() -> Option<String> {
+    let library = Library::open_self().or_else(|_| Library::open(LVRT_PATH)).ok()?;
+    let address: *const c_void = unsafe { library.symbol("PostLVUserEvent").ok()? };
+    module_path_of(address)
+}
+
+#[cfg(unix)]
+fn module_path_of(address: *const c_void) -> Option<String> {
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    if unsafe { libc::dladdr(address, &mut info) } == 0 || info.dli_fname.is_null() {
+        return None;
+    }
+    let path = unsafe { std::ffi::CStr::from_ptr(info.dli_fname) };
+    Some(path.to_string_lossy().into_owned())
+}
+
+#[cfg(windows)]
+fn module_path_of(address: *const c_void) -> Option<String> {
+    const GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS: u32 = 0x4;
+    const GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT: u32 = 0x2;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetModuleHandleExW(flags: u32, module_name: *const u16, module: *mut *mut c_void) -> i32;
+        fn GetModuleFileNameW(module: *mut c_void, filename: *mut u16, size: u32) -> u32;
+    }
+
+    let mut module = std::ptr::null_mut();
+    let flags = GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT;
+    if unsafe { GetModuleHandleExW(flags, address as *const u16, &mut module) } == 0 {
+        return None;
+    }
+
+    let mut buffer = [0u16; 1024];
+    let length = unsafe { GetModuleFileNameW(module, buffer.as_mut_ptr(), buffer.len() as u32) };
+    if length == 0 {
+        return None;
+    }
+    Some(String::from_utf16_lossy(&buffer[..length as usize]))
+}
+
+/// File version ("major.minor.build") from a module's version resource
+#[cfg(windows)]
+fn module_file_version(path: &str) -> Option<String> {
+    #[link(name = "version")]
+    extern "system" {
+        fn GetFileVersionInfoSizeW(filename: *const u16, handle: *mut u32) -> u32;
+        fn GetFileVersionInfoW(filename: *const u16, handle: u32, length: u32, data: *mut c_void) -> i32;
+        fn VerQueryValueW(block: *const c_void, sub_block: *const u16, buffer: *mut *mut c_void, length: *mut u32) -> i32;
+    }
+
+    let filename: Vec<u16> = path.encode_utf16().chain(Some(0)).collect();
+    let size = unsafe { GetFileVersionInfoSizeW(filename.as_ptr(), std::ptr::null_mut()) };
+    if size == 0 {
+        return None;
+    }
+    let mut data = vec![0u8; size as usize];
+    if unsafe { GetFileVersionInfoW(filename.as_ptr(), 0, size, data.as_mut_ptr().cast()) } == 0 {
+        return None;
+    }
+
+    // The root block is a VS_FIXEDFILEINFO: signature, structure version, then the file
+    // version as two DWORDs (major << 16 | minor, build << 16 | revision)
+    let root = [u16::from(b'\\'), 0];
+    let mut info = std::ptr::null_mut();
+    let mut length = 0u32;
+    if unsafe { VerQueryValueW(data.as_ptr().cast(), root.as_ptr(), &mut info, &mut length) } == 0
+        || info.is_null()
+        || (length as usize) < 4 * std::mem::size_of::<u32>()
+    {
+        return None;
+    }
+    let fields = info as *const u32;
+    let (most, least) = unsafe { (fields.add(2).read_unaligned(), fields.add(3).read_unaligned()) };
+    Some(format!("{}.{}.{}", most >> 16, most & 0xFFFF, least >> 16))
+}
+
+#[cfg(not(windows))]
+fn module_file_version(_path: &str) -> Option<String> {
+    None
+}
+
+/// LabVIEW version encoded in the runtime's install path: the year of a "LabVIEW 2021" or
+/// "LabVIEW Run-Time/2021" directory, or the soname version of "liblvrt.so.21.0".
+pub fn version_from_module_path(path: &str) -> Option<String> {
+    if let Some(index) = path.find("liblvrt.so.") {
+        let version = &path[index + "liblvrt.so.".len()..];
+        if version.starts_with(|c: char| c.is_ascii_digit()) {
+            return Some(version.to_string());
+        }
+    }
+
+    path.split(['/', '\\', ' '])
+        .find(|part| part.len() == 4 && part.starts_with("20") && part.chars().all(|c| c.is_ascii_digit()))
+        .map(|year| year.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_from_module_path() {
+        assert_eq!(version_from_module_path("/usr/local/lib64/liblvrt.so.23.0.0").as_deref(), Some("23.0.0"));
+        assert_eq!(
+            version_from_module_path(r"C:\Program Files\National Instruments\LabVIEW 2021\LabVIEW.exe").as_deref(),
+            Some("2021")
+        );
+        assert_eq!(
+            version_from_module_path(r"C:\Program Files\National Instruments\Shared\LabVIEW Run-Time\2019\lvrt.dll").as_deref(),
+            Some("2019")
+        );
+        assert_eq!(version_from_module_path("/usr/lib/liblvrt.so"), None);
+    }
+
+    #[test]
+    fn test_runtime_version_without_a_runtime() {
+        // Test binaries are not loaded by LabVIEW
+        if runtime_module_path().is_none() {
+            assert_eq!(runtime_version(), None);
+        }
+    }
+}
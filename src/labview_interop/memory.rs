@@ -2,6 +2,8 @@
 
 use std::fmt::Debug;
 
+use crate::labview_interop::errors::{InternalError, Result};
+
 /// Magic cookie type used for various reference types in the memory manager.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(transparent)]
@@ -15,4 +17,91 @@ impl MagicCookie {
     pub fn as_raw(&self) -> u32 {
         self.0
     }
-}
\ No newline at end of file
+
+    /// LabVIEW never hands out a zero cookie, so zero marks an invalid reference.
+    pub fn is_null(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// A LabVIEW handle (a pointer to a pointer to the data) as received over FFI.
+///
+/// The handle is validated before every access so a null handle from LabVIEW
+/// becomes an error rather than a crash.
+// Not used by the current API, which only passes scalars, strings and flat buffers; kept
+// for functions that take LabVIEW arrays or strings by handle
+#[allow(dead_code)]
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct LVHandle<T>(*mut *mut T);
+
+#[allow(dead_code)]
+impl<T> LVHandle<T> {
+    /// Wrap a raw handle received from LabVIEW
+    ///
+    /// # Safety
+    ///
+    /// The handle must be null or point to a readable data pointer for as long as the
+    /// `LVHandle` is used.
+    pub unsafe fn from_raw(handle: *mut *mut T) -> Self {
+        LVHandle(handle)
+    }
+
+    pub fn as_raw(&self) -> *mut *mut T {
+        self.0
+    }
+
+    /// True if the handle itself is null. The data pointer it refers to is only read by
+    /// `as_ref`/`as_mut`, which also reject a null data pointer.
+    pub fn is_null(&self) -> bool {
+        self.0.is_null()
+    }
+
+    /// Get a reference to the data behind the handle.
+    ///
+    /// # Safety
+    ///
+    /// A non-null data pointer must point to valid, initialized data owned by LabVIEW
+    /// for the lifetime of the returned reference.
+    pub unsafe fn as_ref(&self) -> Result<&T> {
+        if self.is_null() || (*self.0).is_null() {
+            return Err(InternalError::InvalidHandle.into());
+        }
+        Ok(&**self.0)
+    }
+
+    /// Get a mutable reference to the data behind the handle.
+    ///
+    /// # Safety
+    ///
+    /// As for [`LVHandle::as_ref`], and no other reference to the data may exist.
+    pub unsafe fn as_mut(&mut self) -> Result<&mut T> {
+        if self.is_null() || (*self.0).is_null() {
+            return Err(InternalError::InvalidHandle.into());
+        }
+        Ok(&mut **self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lv_handle_rejects_null_pointers() {
+        let null_handle = unsafe { LVHandle::<i32>::from_raw(std::ptr::null_mut()) };
+        assert!(null_handle.is_null());
+        assert!(unsafe { null_handle.as_ref() }.is_err());
+
+        let mut null_data: *mut i32 = std::ptr::null_mut();
+        let handle = unsafe { LVHandle::from_raw(&mut null_data) };
+        assert!(!handle.is_null());
+        assert!(unsafe { handle.as_ref() }.is_err());
+
+        let mut value = 42;
+        let mut data: *mut i32 = &mut value;
+        let mut handle = unsafe { LVHandle::from_raw(&mut data) };
+        unsafe { *handle.as_mut().unwrap() += 1 };
+        assert_eq!(unsafe { handle.as_ref() }.ok(), Some(&43));
+    }
+}
@@ -3,12 +3,21 @@
 use std::ffi::c_void;
 use std::marker::PhantomData;
 
-use crate::labview_interop::errors::Result;
+use crate::labview_interop::errors::{InternalError, Result};
 use crate::labview_interop::labview::sync_api;
 use crate::labview_interop::memory::MagicCookie;
+use crate::labview_interop::types::LVStatusCode;
 
 type LVUserEventRef = MagicCookie;
 
+/// Data that can be posted to a LabVIEW user event.
+///
+/// # Safety
+///
+/// Implementors must be `#[repr(C)]` plain data whose layout matches the LabVIEW
+/// cluster the user event was created with; LabVIEW copies it byte for byte.
+pub unsafe trait LVPostable: Copy + Send + 'static {}
+
 /// Representation of a LabVIEW user event reference with type data.
 #[derive(Copy, Clone)]
 #[repr(transparent)]
@@ -28,6 +37,9 @@ impl<T> LVUserEvent<T> {
 
     /// Generate the user event with the provided data.
     pub fn post(&self, data: &mut T) -> Result<()> {
+        if self.reference.is_null() {
+            return Err(InternalError::InvalidReference.into());
+        }
         let api = sync_api()?;
         let mg_err = unsafe {
             api.post_lv_user_event(self.reference, data as *mut T as *mut c_void)
@@ -36,6 +48,22 @@ impl<T> LVUserEvent<T> {
     }
 }
 
+impl<T: LVPostable> LVUserEvent<T> {
+    /// Post a copy of `data`, leaving the caller's value untouched.
+    pub fn post_value(&self, data: T) -> Result<()> {
+        let mut data = data;
+        self.post(&mut data)
+    }
+}
+
+/// Post `data` to a raw user event reference, returning a LabVIEW status code for FFI callers.
+pub fn post_user_event<T: LVPostable>(user_event_ref: u32, data: T) -> LVStatusCode {
+    match LVUserEvent::<T>::from_raw(user_event_ref).post_value(data) {
+        Ok(_) => LVStatusCode::SUCCESS,
+        Err(_) => LVStatusCode::ARG_ERROR,
+    }
+}
+
 /// A LabVIEW occurrence which can be used to provide synchronization
 #[derive(Clone, Copy)]
 #[repr(transparent)]
@@ -1,8 +1,42 @@
+//! Cross-platform MIDI for LabVIEW, built as a shared library exporting a C API
+//! (the `midi_*` functions), and usable from Rust through [`MidiManager`] without the C
+//! API's global handle tables:
+//!
+//! ```no_run
+//! use TomMIDIllan::{MidiManager, MidiMessage};
+//!
+//! let mut manager = MidiManager::new();
+//! manager.connect_output(0)?;
+//! manager.send(&MidiMessage::NoteOn { channel: 0, note: 60, velocity: 100 })?;
+//! # Ok::<(), TomMIDIllan::LVInteropError>(())
+//! ```
+//!
+//! Rust callers can have incoming messages pushed to a callback instead of polling
+//! [`MidiManager::receive_message`]:
+//!
+//! ```no_run
+//! use TomMIDIllan::MidiManager;
+//!
+//! let mut manager = MidiManager::new();
+//! manager.connect_input_with_callback(0, |message: &[u8], timestamp_us: u64| {
+//!     println!("{} us: {:02X?}", timestamp_us, message);
+//! })?;
+//! # Ok::<(), TomMIDIllan::LVInteropError>(())
+//! ```
+//!
+//! The callback runs on the MIDI backend's input thread for as long as the manager
+//! stays connected.
+
 #![allow(non_snake_case)]
 
+#[macro_use]
+mod log;
 mod midi;
 mod lv_midi;
 mod labview_interop;
+mod handles;
+mod thread_priority;
+mod event_log;
 
 // Add MIDI file support modules
 
@@ -11,6 +45,14 @@ pub mod lv_midi_file;
 
 
 
+// The Rust-facing API; the `midi_*` C functions are wrappers around it
+pub use midi::{
+    is_midi1_message, set_zero_velocity_as_note_off, validate_message, DeviceInfo, FilterMode, LatencyClass,
+    MessageFilter, MidiManager, MidiMessage, MmcCommand, PortDirection,
+};
+pub use labview_interop::errors::{LVInteropError, Result};
+pub use event_log::{open_event_log, EventLogReader, EventLogWriter};
+
 // Re-export LabVIEW MIDI functions publicly so the test binary can use them
 pub use lv_midi::*;
 
@@ -19,24 +61,403 @@ pub use lv_midi_file::*;
 
 // Helper function to convert MIDI note number to note name
 pub fn get_note_name(note: u8) -> String {
-    let notes = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    get_note_name_ex(note, false)
+}
+
+// Helper function to convert MIDI note number to note name, spelling
+// accidentals as flats ("Db4") or sharps ("C#4")
+pub fn get_note_name_ex(note: u8, use_flats: bool) -> String {
+    let sharps = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    let flats = ["C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B"];
+    let notes = if use_flats { flats } else { sharps };
     let octave = (note / 12) as i32 - 1; // MIDI note 60 = C4
     let note_index = (note % 12) as usize;
     format!("{}{}", notes[note_index], octave)
 }
 
-// Helper function to get control change names
+/// Longest note name, e.g. "C#-1" (or "C#20" for out-of-range notes)
+pub const MAX_NOTE_NAME_LEN: usize = 4;
+
+// Note name built into a fixed array instead of a String, for callers that convert many
+// notes at a high rate. Returns the name bytes (ASCII) and how many of them are used.
+pub fn note_name_bytes(note: u8, use_flats: bool) -> ([u8; MAX_NOTE_NAME_LEN], usize) {
+    let sharps: [&[u8]; 12] = [b"C", b"C#", b"D", b"D#", b"E", b"F", b"F#", b"G", b"G#", b"A", b"A#", b"B"];
+    let flats: [&[u8]; 12] = [b"C", b"Db", b"D", b"Eb", b"E", b"F", b"Gb", b"G", b"Ab", b"A", b"Bb", b"B"];
+    let pitch = if use_flats { flats } else { sharps }[(note % 12) as usize];
+
+    let mut name = [0u8; MAX_NOTE_NAME_LEN];
+    name[..pitch.len()].copy_from_slice(pitch);
+    let mut len = pitch.len();
+    match note / 12 {
+        0 => {
+            name[len..len + 2].copy_from_slice(b"-1");
+            len += 2;
+        }
+        octave @ 1..=10 => {
+            name[len] = b'0' + octave - 1; // MIDI note 60 = C4
+            len += 1;
+        }
+        octave => {
+            // Only reachable for notes above 127
+            name[len] = b'0' + (octave - 1) / 10;
+            name[len + 1] = b'0' + (octave - 1) % 10;
+            len += 2;
+        }
+    }
+    (name, len)
+}
+
+/// Concert pitch: the frequency of A4 (MIDI note 69) in Hz
+pub const DEFAULT_A4_HZ: f64 = 440.0;
+
+/// Frequency of a MIDI note in Hz, in equal temperament with A4 (note 69) tuned to `a4_hz`
+pub fn note_to_frequency(note: u8, a4_hz: f64) -> f64 {
+    a4_hz * 2f64.powf((note as f64 - 69.0) / 12.0)
+}
+
+/// The MIDI note (0-127) nearest to a positive frequency, and how far the frequency is from
+/// it in cents (-50 to +50, or beyond for frequencies outside the MIDI note range)
+pub fn frequency_to_nearest_note(hz: f64, a4_hz: f64) -> (u8, f64) {
+    let semitones = 69.0 + 12.0 * (hz / a4_hz).log2();
+    let note = semitones.round().clamp(0.0, 127.0);
+    (note as u8, (semitones - note) * 100.0)
+}
+
+// Standard MIDI control change assignments, indexed by controller number (0-127)
+const CONTROL_NAMES: [&str; 128] = [
+    // 0-31: coarse (MSB) controllers
+    "Bank Select", "Modulation", "Breath Controller", "Undefined",
+    "Foot Controller", "Portamento Time", "Data Entry", "Volume",
+    "Balance", "Undefined", "Pan", "Expression",
+    "Effect Control 1", "Effect Control 2", "Undefined", "Undefined",
+    "General Purpose 1", "General Purpose 2", "General Purpose 3", "General Purpose 4",
+    "Undefined", "Undefined", "Undefined", "Undefined",
+    "Undefined", "Undefined", "Undefined", "Undefined",
+    "Undefined", "Undefined", "Undefined", "Undefined",
+    // 32-63: fine (LSB) counterparts of 0-31
+    "Bank Select LSB", "Modulation LSB", "Breath Controller LSB", "Undefined LSB",
+    "Foot Controller LSB", "Portamento Time LSB", "Data Entry LSB", "Volume LSB",
+    "Balance LSB", "Undefined LSB", "Pan LSB", "Expression LSB",
+    "Effect Control 1 LSB", "Effect Control 2 LSB", "Undefined LSB", "Undefined LSB",
+    "General Purpose 1 LSB", "General Purpose 2 LSB", "General Purpose 3 LSB", "General Purpose 4 LSB",
+    "Undefined LSB", "Undefined LSB", "Undefined LSB", "Undefined LSB",
+    "Undefined LSB", "Undefined LSB", "Undefined LSB", "Undefined LSB",
+    "Undefined LSB", "Undefined LSB", "Undefined LSB", "Undefined LSB",
+    // 64-69: switches
+    "Sustain Pedal", "Portamento", "Sostenuto", "Soft Pedal",
+    "Legato Footswitch", "Hold 2",
+    // 70-79: sound controllers
+    "Sound Variation", "Timbre/Harmonic Intensity", "Release Time", "Attack Time",
+    "Brightness", "Decay Time", "Vibrato Rate", "Vibrato Depth",
+    "Vibrato Delay", "Sound Controller 10",
+    // 80-95
+    "General Purpose 5", "General Purpose 6", "General Purpose 7", "General Purpose 8",
+    "Portamento Control", "Undefined", "Undefined", "Undefined",
+    "High Resolution Velocity Prefix", "Undefined", "Undefined", "Reverb Depth",
+    "Tremolo Depth", "Chorus Depth", "Celeste Depth", "Phaser Depth",
+    // 96-101: data increment and parameter numbers
+    "Data Increment", "Data Decrement", "NRPN LSB", "NRPN MSB",
+    "RPN LSB", "RPN MSB",
+    // 102-119
+    "Undefined", "Undefined", "Undefined", "Undefined", "Undefined", "Undefined",
+    "Undefined", "Undefined", "Undefined", "Undefined", "Undefined", "Undefined",
+    "Undefined", "Undefined", "Undefined", "Undefined", "Undefined", "Undefined",
+    // 120-127: channel mode messages
+    "All Sound Off", "Reset All Controllers", "Local Control", "All Notes Off",
+    "Omni Off", "Omni On", "Mono On", "Poly On",
+];
+
+// Helper function to get control change names, e.g. 7 -> "Volume".
+// Controller numbers are masked to 7 bits.
 pub fn get_control_name(controller: u8) -> &'static str {
+    CONTROL_NAMES[(controller & 0x7F) as usize]
+}
+
+// Helper function to look up a controller number by name (case-insensitive),
+// e.g. "volume" -> 7. Undefined controllers have no name and give None.
+pub fn get_control_number(name: &str) -> Option<u8> {
+    let name = name.trim();
+    CONTROL_NAMES
+        .iter()
+        .position(|candidate| !candidate.starts_with("Undefined") && candidate.eq_ignore_ascii_case(name))
+        .map(|index| index as u8)
+}
+
+// General MIDI Level 1 sound set, indexed by program number (0-127)
+const GM_PROGRAMS: [&str; 128] = [
+    // Piano
+    "Acoustic Grand Piano", "Bright Acoustic Piano", "Electric Grand Piano", "Honky-tonk Piano",
+    "Electric Piano 1", "Electric Piano 2", "Harpsichord", "Clavinet",
+    // Chromatic Percussion
+    "Celesta", "Glockenspiel", "Music Box", "Vibraphone",
+    "Marimba", "Xylophone", "Tubular Bells", "Dulcimer",
+    // Organ
+    "Drawbar Organ", "Percussive Organ", "Rock Organ", "Church Organ",
+    "Reed Organ", "Accordion", "Harmonica", "Tango Accordion",
+    // Guitar
+    "Acoustic Guitar (nylon)", "Acoustic Guitar (steel)", "Electric Guitar (jazz)", "Electric Guitar (clean)",
+    "Electric Guitar (muted)", "Overdriven Guitar", "Distortion Guitar", "Guitar Harmonics",
+    // Bass
+    "Acoustic Bass", "Electric Bass (finger)", "Electric Bass (pick)", "Fretless Bass",
+    "Slap Bass 1", "Slap Bass 2", "Synth Bass 1", "Synth Bass 2",
+    // Strings
+    "Violin", "Viola", "Cello", "Contrabass",
+    "Tremolo Strings", "Pizzicato Strings", "Orchestral Harp", "Timpani",
+    // Ensemble
+    "String Ensemble 1", "String Ensemble 2", "Synth Strings 1", "Synth Strings 2",
+    "Choir Aahs", "Voice Oohs", "Synth Voice", "Orchestra Hit",
+    // Brass
+    "Trumpet", "Trombone", "Tuba", "Muted Trumpet",
+    "French Horn", "Brass Section", "Synth Brass 1", "Synth Brass 2",
+    // Reed
+    "Soprano Sax", "Alto Sax", "Tenor Sax", "Baritone Sax",
+    "Oboe", "English Horn", "Bassoon", "Clarinet",
+    // Pipe
+    "Piccolo", "Flute", "Recorder", "Pan Flute",
+    "Blown Bottle", "Shakuhachi", "Whistle", "Ocarina",
+    // Synth Lead
+    "Lead 1 (square)", "Lead 2 (sawtooth)", "Lead 3 (calliope)", "Lead 4 (chiff)",
+    "Lead 5 (charang)", "Lead 6 (voice)", "Lead 7 (fifths)", "Lead 8 (bass + lead)",
+    // Synth Pad
+    "Pad 1 (new age)", "Pad 2 (warm)", "Pad 3 (polysynth)", "Pad 4 (choir)",
+    "Pad 5 (bowed)", "Pad 6 (metallic)", "Pad 7 (halo)", "Pad 8 (sweep)",
+    // Synth Effects
+    "FX 1 (rain)", "FX 2 (soundtrack)", "FX 3 (crystal)", "FX 4 (atmosphere)",
+    "FX 5 (brightness)", "FX 6 (goblins)", "FX 7 (echoes)", "FX 8 (sci-fi)",
+    // Ethnic
+    "Sitar", "Banjo", "Shamisen", "Koto",
+    "Kalimba", "Bagpipe", "Fiddle", "Shanai",
+    // Percussive
+    "Tinkle Bell", "Agogo", "Steel Drums", "Woodblock",
+    "Taiko Drum", "Melodic Tom", "Synth Drum", "Reverse Cymbal",
+    // Sound Effects
+    "Guitar Fret Noise", "Breath Noise", "Seashore", "Bird Tweet",
+    "Telephone Ring", "Helicopter", "Applause", "Gunshot",
+];
+
+// Helper function to get the General MIDI instrument name for a program number,
+// e.g. 0 -> "Acoustic Grand Piano". Program numbers are masked to 7 bits.
+pub fn get_gm_program_name(program: u8) -> &'static str {
+    GM_PROGRAMS[(program & 0x7F) as usize]
+}
+
+// Helper function to name General MIDI percussion notes (channel 10), e.g. 36 -> "Bass Drum 1"
+pub fn get_drum_name(note: u8) -> &'static str {
+    const GM_DRUMS: [&str; 47] = [
+        "Acoustic Bass Drum", "Bass Drum 1", "Side Stick", "Acoustic Snare",
+        "Hand Clap", "Electric Snare", "Low Floor Tom", "Closed Hi-Hat",
+        "High Floor Tom", "Pedal Hi-Hat", "Low Tom", "Open Hi-Hat",
+        "Low-Mid Tom", "Hi-Mid Tom", "Crash Cymbal 1", "High Tom",
+        "Ride Cymbal 1", "Chinese Cymbal", "Ride Bell", "Tambourine",
+        "Splash Cymbal", "Cowbell", "Crash Cymbal 2", "Vibraslap",
+        "Ride Cymbal 2", "Hi Bongo", "Low Bongo", "Mute Hi Conga",
+        "Open Hi Conga", "Low Conga", "High Timbale", "Low Timbale",
+        "High Agogo", "Low Agogo", "Cabasa", "Maracas",
+        "Short Whistle", "Long Whistle", "Short Guiro", "Long Guiro",
+        "Claves", "Hi Wood Block", "Low Wood Block", "Mute Cuica",
+        "Open Cuica", "Mute Triangle", "Open Triangle",
+    ];
+    match note {
+        35..=81 => GM_DRUMS[(note - 35) as usize],
+        _ => "Unknown Percussion",
+    }
+}
+
+// Helper function to name channel-mode messages (CC 120-127); None for ordinary controllers
+pub fn get_channel_mode_name(controller: u8) -> Option<&'static str> {
     match controller {
-        1 => "Modulation",
-        7 => "Volume",
-        10 => "Pan",
-        11 => "Expression",
-        64 => "Sustain Pedal",
-        65 => "Portamento",
-        66 => "Sostenuto",
-        67 => "Soft Pedal",
-        _ => "Other"
+        120 => Some("All Sound Off"),
+        121 => Some("Reset All Controllers"),
+        122 => Some("Local Control"),
+        123 => Some("All Notes Off"),
+        124 => Some("Omni Off"),
+        125 => Some("Omni On"),
+        126 => Some("Mono On"),
+        127 => Some("Poly On"),
+        _ => None,
+    }
+}
+
+/// Manufacturer ID at the start of a SysEx message: one byte, or three bytes starting with 0x00
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManufacturerId {
+    Single(u8),
+    /// The two bytes following the 0x00 prefix
+    Extended(u8, u8),
+}
+
+impl ManufacturerId {
+    /// The ID bytes as one number, e.g. 0x41 for Roland or 0x2029 for Novation (00 20 29)
+    pub fn value(&self) -> u32 {
+        match *self {
+            ManufacturerId::Single(id) => id as u32,
+            ManufacturerId::Extended(high, low) => (high as u32) << 8 | low as u32,
+        }
+    }
+
+    /// Number of bytes the ID occupies in the message (1 or 3)
+    pub fn byte_count(&self) -> usize {
+        match self {
+            ManufacturerId::Single(_) => 1,
+            ManufacturerId::Extended(..) => 3,
+        }
+    }
+
+    /// Vendor name for common manufacturers (and the universal IDs), otherwise "Unknown"
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ManufacturerId::Single(0x01) => "Sequential Circuits",
+            ManufacturerId::Single(0x04) => "Moog",
+            ManufacturerId::Single(0x07) => "Kurzweil",
+            ManufacturerId::Single(0x0F) => "Ensoniq",
+            ManufacturerId::Single(0x10) => "Oberheim",
+            ManufacturerId::Single(0x18) => "E-mu",
+            ManufacturerId::Single(0x40) => "Kawai",
+            ManufacturerId::Single(0x41) => "Roland",
+            ManufacturerId::Single(0x42) => "Korg",
+            ManufacturerId::Single(0x43) => "Yamaha",
+            ManufacturerId::Single(0x44) => "Casio",
+            ManufacturerId::Single(0x47) => "Akai",
+            ManufacturerId::Single(0x7D) => "Non-Commercial",
+            ManufacturerId::Single(0x7E) => "Universal Non-Real Time",
+            ManufacturerId::Single(0x7F) => "Universal Real Time",
+            ManufacturerId::Extended(0x00, 0x0E) => "Alesis",
+            ManufacturerId::Extended(0x20, 0x29) => "Novation",
+            ManufacturerId::Extended(0x20, 0x32) => "Behringer",
+            ManufacturerId::Extended(0x20, 0x3C) => "Elektron",
+            ManufacturerId::Extended(0x20, 0x6B) => "Arturia",
+            ManufacturerId::Extended(0x21, 0x09) => "Native Instruments",
+            _ => "Unknown",
+        }
+    }
+}
+
+// Helper function to read the manufacturer ID following 0xF0 in a SysEx message.
+// None if the data is not SysEx or is too short to hold the ID.
+pub fn sysex_manufacturer(data: &[u8]) -> Option<ManufacturerId> {
+    match data {
+        [0xF0, 0x00, high, low, ..] if *high < 0x80 && *low < 0x80 => Some(ManufacturerId::Extended(*high, *low)),
+        [0xF0, 0x00, ..] => None,
+        [0xF0, id, ..] if *id < 0x80 => Some(ManufacturerId::Single(*id)),
+        _ => None,
+    }
+}
+
+/// Chord qualities recognized by `detect_chord`; the value is the `quality_code` posted to LabVIEW
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordQuality {
+    Unrecognized = 0,
+    Major = 1,
+    Minor = 2,
+    Diminished = 3,
+    Augmented = 4,
+    Dominant7 = 5,
+    Major7 = 6,
+    Minor7 = 7,
+    HalfDiminished7 = 8,
+    Diminished7 = 9,
+}
+
+// Intervals above the root (bit n = n semitones) of each recognized chord
+const CHORD_TEMPLATES: [(u16, ChordQuality); 9] = [
+    (1 << 0 | 1 << 4 | 1 << 7, ChordQuality::Major),
+    (1 << 0 | 1 << 3 | 1 << 7, ChordQuality::Minor),
+    (1 << 0 | 1 << 3 | 1 << 6, ChordQuality::Diminished),
+    (1 << 0 | 1 << 4 | 1 << 8, ChordQuality::Augmented),
+    (1 << 0 | 1 << 4 | 1 << 7 | 1 << 10, ChordQuality::Dominant7),
+    (1 << 0 | 1 << 4 | 1 << 7 | 1 << 11, ChordQuality::Major7),
+    (1 << 0 | 1 << 3 | 1 << 7 | 1 << 10, ChordQuality::Minor7),
+    (1 << 0 | 1 << 3 | 1 << 6 | 1 << 10, ChordQuality::HalfDiminished7),
+    (1 << 0 | 1 << 3 | 1 << 6 | 1 << 9, ChordQuality::Diminished7),
+];
+
+// Helper function to name the chord formed by a set of held notes (in any octave or
+// voicing). Returns the root pitch class (0 = C .. 11 = B) and quality, or None if the notes
+// are not exactly a triad or seventh chord. Symmetric chords (augmented, diminished seventh)
+// are named from the lowest note.
+pub fn detect_chord(notes: &[u8]) -> Option<(u8, ChordQuality)> {
+    let bass = notes.iter().min()? % 12;
+    let pitch_classes = notes.iter().fold(0u16, |mask, note| mask | 1 << (note % 12));
+
+    let roots = std::iter::once(bass).chain((0..12).filter(|root| *root != bass));
+    for root in roots.filter(|root| pitch_classes & (1 << root) != 0) {
+        let intervals = ((pitch_classes >> root) | (pitch_classes << (12 - root))) & 0x0FFF;
+        if let Some((_, quality)) = CHORD_TEMPLATES.iter().find(|(template, _)| *template == intervals) {
+            return Some((root, *quality));
+        }
+    }
+    None
+}
+
+// Helper function to build a human-readable description of a raw MIDI message,
+// e.g. "Note On  Ch:1  C4  Vel:100"
+pub fn describe_message(message: &[u8]) -> String {
+    let status = match message.first() {
+        Some(&status) => status,
+        None => return "Empty Message".to_string(),
+    };
+
+    if status < 0x80 {
+        return format!("Data (Running Status)  Raw: {:02X?}", message);
+    }
+
+    if status >= 0xF0 {
+        return match status {
+            0xF0 => format!("System Exclusive  {} bytes", message.len()),
+            0xF1 => "MTC Quarter Frame".to_string(),
+            0xF2 => "Song Position".to_string(),
+            0xF3 => "Song Select".to_string(),
+            0xF6 => "Tune Request".to_string(),
+            0xF7 => "End of Exclusive".to_string(),
+            0xF8 => "Timing Clock".to_string(),
+            0xFA => "Start".to_string(),
+            0xFB => "Continue".to_string(),
+            0xFC => "Stop".to_string(),
+            0xFE => "Active Sensing".to_string(),
+            0xFF => "System Reset".to_string(),
+            _ => format!("Unknown  Raw: {:02X?}", message),
+        };
+    }
+
+    let channel = (status & 0x0F) + 1; // MIDI channels are 1-16, not 0-15
+    let data_len = match status & 0xF0 {
+        0xC0 | 0xD0 => 1,
+        _ => 2,
+    };
+    let name = match status & 0xF0 {
+        0x80 => "Note Off",
+        0x90 if message.get(2) == Some(&0) => "Note Off",
+        0x90 => "Note On",
+        0xA0 => "Poly Aftertouch",
+        0xB0 => "Control Change",
+        0xC0 => "Program Change",
+        0xD0 => "Channel Aftertouch",
+        _ => "Pitch Bend",
+    };
+
+    if message.len() < 1 + data_len {
+        return format!("{}  Ch:{}  (incomplete)", name, channel);
+    }
+
+    let data1 = message[1];
+    match status & 0xF0 {
+        0x80 | 0x90 => {
+            format!("{}  Ch:{}  {}  Vel:{}", name, channel, get_note_name(data1), message[2])
+        }
+        0xA0 => {
+            format!("{}  Ch:{}  {}  Pressure:{}", name, channel, get_note_name(data1), message[2])
+        }
+        0xB0 => match get_channel_mode_name(data1) {
+            Some(mode) => format!("Channel Mode  Ch:{}  {}  Val:{}", channel, mode, message[2]),
+            None => format!("{}  Ch:{}  CC{} ({})  Val:{}", name, channel, data1, get_control_name(data1), message[2]),
+        },
+        0xC0 => format!("{}  Ch:{}  Program:{}", name, channel, data1),
+        0xD0 => format!("{}  Ch:{}  Pressure:{}", name, channel, data1),
+        _ => {
+            let bend_value = ((message[2] as u16) << 7) | (data1 as u16);
+            format!("{}  Ch:{}  Value:{}", name, channel, bend_value)
+        }
     }
 }
 
@@ -44,10 +465,123 @@ pub fn get_control_name(controller: u8) -> &'static str {
 #[cfg(test)]
 mod tests {
     use crate::midi::MidiManager;
-    use crate::{get_note_name, get_control_name}; // Import the helper functions
+    use crate::{detect_chord, frequency_to_nearest_note, note_to_frequency, sysex_manufacturer, ChordQuality, ManufacturerId};
+    use crate::{get_note_name, get_note_name_ex, note_name_bytes, get_control_name, get_control_number, get_drum_name, get_gm_program_name, describe_message}; // Import the helper functions
     use std::thread;
     use std::time::Duration;
 
+    #[test]
+    fn test_note_names() {
+        assert_eq!(get_note_name(60), "C4");
+        assert_eq!(get_note_name(61), "C#4");
+        assert_eq!(get_note_name_ex(61, true), "Db4");
+        assert_eq!(get_note_name_ex(70, true), "Bb4");
+        assert_eq!(get_note_name_ex(0, true), "C-1");
+
+        for note in 0..=255 {
+            for use_flats in [false, true] {
+                let (name, len) = note_name_bytes(note, use_flats);
+                assert_eq!(&name[..len], get_note_name_ex(note, use_flats).as_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn test_note_frequency() {
+        assert_eq!(note_to_frequency(69, 440.0), 440.0);
+        assert!((note_to_frequency(60, 440.0) - 261.6256).abs() < 1e-4);
+        assert_eq!(note_to_frequency(57, 432.0), 216.0);
+
+        assert_eq!(frequency_to_nearest_note(440.0, 440.0), (69, 0.0));
+        let (note, cents) = frequency_to_nearest_note(445.0, 440.0);
+        assert_eq!(note, 69);
+        assert!((cents - 19.56).abs() < 0.01);
+        let (note, cents) = frequency_to_nearest_note(256.0, 440.0); // Closer to C4 than B3
+        assert_eq!(note, 60);
+        assert!(cents < 0.0);
+        assert_eq!(frequency_to_nearest_note(1.0, 440.0).0, 0); // Below the MIDI range
+    }
+
+    #[test]
+    fn test_detect_chord() {
+        assert_eq!(detect_chord(&[60, 64, 67]), Some((0, ChordQuality::Major)));
+        assert_eq!(detect_chord(&[64, 67, 72]), Some((0, ChordQuality::Major))); // First inversion
+        assert_eq!(detect_chord(&[57, 60, 64, 69]), Some((9, ChordQuality::Minor))); // Doubled root
+        assert_eq!(detect_chord(&[67, 71, 74, 77]), Some((7, ChordQuality::Dominant7)));
+        assert_eq!(detect_chord(&[71, 74, 77, 81]), Some((11, ChordQuality::HalfDiminished7)));
+        assert_eq!(detect_chord(&[62, 65, 68, 71]), Some((2, ChordQuality::Diminished7))); // Named from the bass
+        assert_eq!(detect_chord(&[60, 62, 64]), None);
+        assert_eq!(detect_chord(&[60, 67]), None);
+        assert_eq!(detect_chord(&[]), None);
+    }
+
+    #[test]
+    fn test_sysex_manufacturer() {
+        let roland = sysex_manufacturer(&[0xF0, 0x41, 0x10, 0x42, 0x12, 0xF7]).unwrap();
+        assert_eq!(roland, ManufacturerId::Single(0x41));
+        assert_eq!((roland.value(), roland.byte_count(), roland.name()), (0x41, 1, "Roland"));
+
+        let novation = sysex_manufacturer(&[0xF0, 0x00, 0x20, 0x29, 0x02, 0xF7]).unwrap();
+        assert_eq!((novation.value(), novation.byte_count(), novation.name()), (0x2029, 3, "Novation"));
+
+        assert_eq!(sysex_manufacturer(&[0xF0, 0x22, 0xF7]).map(|id| id.name()), Some("Unknown"));
+        assert_eq!(sysex_manufacturer(&[0xF0, 0x00, 0x20]), None); // Truncated extended ID
+        assert_eq!(sysex_manufacturer(&[0x90, 0x41, 0x10]), None);
+        assert_eq!(sysex_manufacturer(&[0xF0]), None);
+    }
+
+    #[test]
+    fn test_control_names() {
+        assert_eq!(get_control_name(0), "Bank Select");
+        assert_eq!(get_control_name(7), "Volume");
+        assert_eq!(get_control_name(39), "Volume LSB");
+        assert_eq!(get_control_name(64), "Sustain Pedal");
+        assert_eq!(get_control_name(91), "Reverb Depth");
+        assert_eq!(get_control_name(123), "All Notes Off");
+        assert_eq!(get_control_name(3), "Undefined");
+
+        assert_eq!(get_control_number("Volume"), Some(7));
+        assert_eq!(get_control_number(" chorus depth "), Some(93));
+        assert_eq!(get_control_number("RPN MSB"), Some(101));
+        assert_eq!(get_control_number("Undefined"), None);
+        assert_eq!(get_control_number("Flux Capacitor"), None);
+        for controller in 0..128u8 {
+            if let Some(number) = get_control_number(get_control_name(controller)) {
+                assert_eq!(number, controller);
+            }
+        }
+    }
+
+    #[test]
+    fn test_drum_names() {
+        assert_eq!(get_drum_name(35), "Acoustic Bass Drum");
+        assert_eq!(get_drum_name(36), "Bass Drum 1");
+        assert_eq!(get_drum_name(42), "Closed Hi-Hat");
+        assert_eq!(get_drum_name(81), "Open Triangle");
+        assert_eq!(get_drum_name(34), "Unknown Percussion");
+        assert_eq!(get_drum_name(82), "Unknown Percussion");
+    }
+
+    #[test]
+    fn test_gm_program_names() {
+        assert_eq!(get_gm_program_name(0), "Acoustic Grand Piano");
+        assert_eq!(get_gm_program_name(40), "Violin");
+        assert_eq!(get_gm_program_name(127), "Gunshot");
+        assert_eq!(get_gm_program_name(128), "Acoustic Grand Piano");
+    }
+
+    #[test]
+    fn test_describe_message() {
+        assert_eq!(describe_message(&[0x90, 60, 100]), "Note On  Ch:1  C4  Vel:100");
+        assert_eq!(describe_message(&[0x91, 60, 0]), "Note Off  Ch:2  C4  Vel:0");
+        assert_eq!(describe_message(&[0xB0, 7, 90]), "Control Change  Ch:1  CC7 (Volume)  Val:90");
+        assert_eq!(describe_message(&[0xB2, 122, 0]), "Channel Mode  Ch:3  Local Control  Val:0");
+        assert_eq!(describe_message(&[0xE0, 0x00, 0x40]), "Pitch Bend  Ch:1  Value:8192");
+        assert_eq!(describe_message(&[0xC3, 5]), "Program Change  Ch:4  Program:5");
+        assert_eq!(describe_message(&[0x90, 60]), "Note On  Ch:1  (incomplete)");
+        assert_eq!(describe_message(&[0xF8]), "Timing Clock");
+    }
+
     #[test]
     fn test_midi_devices() {
         let manager = MidiManager::new();
@@ -79,6 +613,38 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(unix)]
+    #[ignore] // Needs ALSA or CoreMIDI; run with: cargo test test_callback_receive -- --ignored --nocapture
+    fn test_callback_receive() {
+        use midir::os::unix::VirtualOutput;
+        use std::sync::mpsc;
+
+        // A virtual output port stands in for a hardware keyboard
+        let port_name = format!("Callback Example {}", std::process::id());
+        let mut keyboard = midir::MidiOutput::new("Callback Example")
+            .unwrap()
+            .create_virtual(&port_name)
+            .unwrap();
+
+        let mut manager = MidiManager::new();
+        let device_index = manager.list_input_devices().unwrap()
+            .iter()
+            .position(|name| name.contains(&port_name))
+            .expect("virtual port not listed");
+
+        // Messages are pushed to the callback as they arrive; no polling needed
+        let (sender, receiver) = mpsc::channel();
+        manager.connect_input_with_callback(device_index, move |message: &[u8], timestamp: u64| {
+            let _ = sender.send((message.to_vec(), timestamp));
+        }).unwrap();
+
+        keyboard.send(&MidiManager::note_on(0, 60, 100)).unwrap();
+        let (message, timestamp) = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        println!("Received {:02X?} at {} us", message, timestamp);
+        assert_eq!(message, MidiManager::note_on(0, 60, 100));
+    }
+
     #[test]
     #[ignore] // Run with: cargo test test_piano_listener -- --ignored --nocapture
     fn test_piano_listener() {
@@ -117,8 +683,11 @@ mod tests {
         let start_time = std::time::Instant::now();
         let listen_duration = Duration::from_secs(30);
 
+        let mut buffer = [0u8; 256];
+
         while start_time.elapsed() < listen_duration {
-            if let Some(message) = manager.receive_message() {
+            if let Some(length) = manager.receive_message(&mut buffer).filter(|&length| length <= buffer.len()) {
+                let message = &buffer[..length];
                 // Filter out spam messages
                 if message.len() == 1 {
                     match message[0] {
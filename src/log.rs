@@ -0,0 +1,51 @@
+//! Diagnostic logging routed to a host-supplied callback.
+//! LabVIEW does not show stderr, so messages go to the callback set with
+//! `midi_set_log_callback` and only fall back to stderr when none is registered.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::sync::{Mutex, OnceLock};
+
+/// Severity passed to the log callback
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Error = 0,
+    Warning = 1,
+    Info = 2,
+}
+
+/// Receives a log level and a NUL-terminated message that is only valid for the duration of the call.
+/// May be called from MIDI callback threads.
+pub type LogCallback = extern "C" fn(level: c_int, message: *const c_char);
+
+static LOG_CALLBACK: OnceLock<Mutex<Option<LogCallback>>> = OnceLock::new();
+
+fn get_log_callback() -> &'static Mutex<Option<LogCallback>> {
+    LOG_CALLBACK.get_or_init(|| Mutex::new(None))
+}
+
+/// Install (or with `None`, remove) the log callback
+pub fn set_log_callback(callback: Option<LogCallback>) {
+    *get_log_callback().lock().unwrap() = callback;
+}
+
+/// Deliver a message to the log callback, or to stderr if none is set
+pub fn write_log(level: LogLevel, message: &str) {
+    // Copy the pointer out so the callback never runs under the lock
+    let callback = *get_log_callback().lock().unwrap();
+    match callback {
+        Some(callback) => {
+            let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+            callback(level as c_int, message.as_ptr());
+        }
+        None => eprintln!("{}", message),
+    }
+}
+
+/// Log a formatted message at the given `LogLevel` variant, e.g. `log!(Info, "Connected to {}", name)`
+macro_rules! log {
+    ($level:ident, $($arg:tt)*) => {
+        $crate::log::write_log($crate::log::LogLevel::$level, &format!($($arg)*))
+    };
+}
@@ -1,30 +1,482 @@
-use crate::midi::MidiManager;
-use crate::labview_interop::sync::LVUserEvent;
+use crate::handles::HandleAllocator;
+use crate::midi_file::{close_all_midi_files, get_midi_file, register_midi_file, MidiFile};
+use crate::midi::{find_invalid_byte, is_midi1_message, set_connect_retries, set_dedupe_names, set_port_ignore_list, set_sort_devices, sanitize_device_name, set_zero_velocity_as_note_off, validate_message, with_connect_retries, zero_velocity_as_note_off, FilterMode, MessageFilter, MidiManager, MidiMessage, MmcCommand, PortDirection};
+use crate::{describe_message, detect_chord, frequency_to_nearest_note, get_control_number, get_drum_name, get_gm_program_name, get_note_name_ex, note_name_bytes, note_to_frequency, sysex_manufacturer, DEFAULT_A4_HZ};
+use crate::log::{set_log_callback, LogCallback};
+use crate::event_log::{open_event_log, EventLogWriter};
+use crate::thread_priority::{set_current_thread_priority, MAX_THREAD_PRIORITY, THREAD_PRIORITY_SUPPORTED};
+use crate::labview_interop::sync::{post_user_event, LVPostable, LVUserEvent};
+use crate::labview_interop::labview::{reload_sync_api, runtime_version};
+use crate::labview_interop::errors::{LVInteropError, Result};
 use crate::labview_interop::types::LVStatusCode;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_int, c_uchar};
-use std::collections::HashMap;
-use std::sync::{Mutex, OnceLock};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Once, OnceLock};
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-// Global storage for MIDI managers (thread-safe)
-static MIDI_MANAGERS: OnceLock<Mutex<HashMap<i32, MidiManager>>> = OnceLock::new();
-static NEXT_HANDLE: OnceLock<Mutex<i32>> = OnceLock::new();
+// Global storage for MIDI contexts (thread-safe); the plain FFI functions use the default one
+static DEFAULT_CONTEXT: OnceLock<Arc<MidiContext>> = OnceLock::new();
+static CONTEXTS: OnceLock<Mutex<HashMap<i32, Arc<MidiContext>>>> = OnceLock::new();
+static NEXT_CONTEXT_HANDLE: OnceLock<Mutex<i32>> = OnceLock::new();
+static RECORDINGS: OnceLock<Mutex<HashMap<i32, Recording>>> = OnceLock::new();
+static PLAYERS: OnceLock<Mutex<HashMap<i32, Player>>> = OnceLock::new();
+static CC_RAMPS: OnceLock<Mutex<HashMap<RampKey, CcRamp>>> = OnceLock::new();
+static ACTIVE_SENSING: OnceLock<Mutex<HashMap<i32, ActiveSensing>>> = OnceLock::new();
+static MAX_BUFFER_LENGTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_BUFFER_LENGTH);
+static STRICT_MODE: AtomicBool = AtomicBool::new(false);
+static SCHEDULED_SENDS: ScheduledSends = ScheduledSends::new();
+
+/// Default upper bound on message and buffer lengths passed in from LabVIEW
+pub const DEFAULT_MAX_BUFFER_LENGTH: usize = 64 * 1024;
+
+/// An independent set of managers and listeners with its own handle counter, so two
+/// callers in one process (e.g. two LabVIEW application instances) cannot see or close
+/// each other's handles. Recordings, playback and CC ramps only work in the default context.
+pub struct MidiContext {
+    managers: Mutex<HashMap<i32, MidiManager>>,
+    listeners: Mutex<HashMap<i32, Arc<EventListener>>>,
+    handles: Mutex<HandleAllocator>,
+}
+
+impl MidiContext {
+    pub fn new() -> Self {
+        MidiContext {
+            managers: Mutex::new(HashMap::new()),
+            listeners: Mutex::new(HashMap::new()),
+            handles: Mutex::new(HandleAllocator::new()),
+        }
+    }
+
+    fn next_handle(&self) -> i32 {
+        self.handles.lock().unwrap().allocate()
+    }
+
+    /// Return a handle for reuse once nothing refers to it any more
+    fn release_handle(&self, handle: c_int) {
+        // The per-handle side tables only hold default-context handles
+        if std::ptr::eq(self, default_context().as_ref()) {
+            clear_handle_state(handle);
+        }
+        self.handles.lock().unwrap().release(handle);
+    }
+
+    fn create_manager(&self) -> c_int {
+        let handle = self.next_handle();
+        self.managers.lock().unwrap().insert(handle, MidiManager::new());
+        handle
+    }
+
+    fn destroy_manager(&self, handle: c_int) -> c_int {
+        // Active sensing only runs on default-context handles. Stop it before the handle can
+        // be reused, and without holding the manager lock its thread sends under.
+        if std::ptr::eq(self, default_context().as_ref()) {
+            stop_active_sensing(handle);
+        }
+
+        let (manager, listener) = {
+            let mut managers = self.managers.lock().unwrap();
+            let listener = self.listeners.lock().unwrap().remove(&handle);
+            (managers.remove(&handle), listener)
+        };
+
+        // Drop outside the locks: closing the connection joins midir's thread (releasing the
+        // callback's reference to the listener), then dropping the listener joins its own thread
+        let found = manager.is_some();
+        drop(manager);
+        drop(listener);
+
+        if !found {
+            return -1;
+        }
+        self.release_handle(handle);
+        0
+    }
+
+    fn connect_input(&self, handle: c_int, device_index: c_int) -> c_int {
+        if let Err(e) = check_device_index(device_index) {
+            return e.code();
+        }
+        self.connect_with_retries(handle, |manager| manager.try_connect_input(device_index as usize))
+    }
+
+    /// Run connection attempts on a handle's manager with the configured retries, taking the
+    /// managers lock only for each attempt so the backoff doesn't stall other handles.
+    /// Returns 0, -1 for an unknown handle, or the last attempt's error code.
+    fn connect_with_retries(&self, handle: c_int, mut connect: impl FnMut(&mut MidiManager) -> Result<()>) -> c_int {
+        let result = with_connect_retries(|| match self.managers.lock().unwrap().get_mut(&handle) {
+            Some(manager) => connect(manager).map(Some),
+            None => Ok(None),
+        });
+        match result {
+            Ok(Some(())) => 0,
+            Ok(None) => -1,
+            Err(e) => e.code(),
+        }
+    }
+
+    /// Create a manager with both an input and an output connected, returning its handle
+    fn open_duplex(&self, input_index: usize, output_index: usize) -> c_int {
+        let mut manager = MidiManager::new();
+        if let Err(e) = manager.connect_input(input_index).and_then(|_| manager.connect_output(output_index)) {
+            return e.code();
+        }
+
+        let handle = self.next_handle();
+        self.managers.lock().unwrap().insert(handle, manager);
+        handle
+    }
+
+    fn connect_first(&self, handle: c_int, input: bool) -> c_int {
+        self.connect_with_retries(handle, |manager| {
+            if input {
+                manager.try_connect_first_input()
+            } else {
+                manager.try_connect_first_output()
+            }
+        })
+    }
+
+    fn connect_output(&self, handle: c_int, device_index: c_int) -> c_int {
+        if let Err(e) = check_device_index(device_index) {
+            return e.code();
+        }
+        self.connect_with_retries(handle, |manager| manager.try_connect_output(device_index as usize))
+    }
+
+    /// Wait for the handle's next output slot. The manager lock is released while sleeping so
+    /// other handles (and input polling) are not blocked. Returns false for an unknown handle.
+    fn wait_for_output_slot(&self, handle: c_int) -> bool {
+        let rate_limiter = match self.managers.lock().unwrap().get(&handle) {
+            Some(manager) => Arc::clone(manager.rate_limiter()),
+            None => return false,
+        };
+
+        let delay = rate_limiter.reserve();
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+        true
+    }
+
+    /// Send a message on a handle, honouring its output rate limit
+    fn send_message(&self, handle: c_int, message: &[u8]) -> c_int {
+        if !self.wait_for_output_slot(handle) {
+            return -1;
+        }
+
+        let mut managers = self.managers.lock().unwrap();
+        match managers.get_mut(&handle) {
+            Some(manager) => {
+                match manager.send_message(message) {
+                    Ok(_) => 0,
+                    Err(e) => e.code(),
+                }
+            }
+            None => -1,
+        }
+    }
+
+    /// Returns 1 with `message_length` set if a message was read, 0 if none is waiting.
+    /// The manager lock is only held to look up the input queue, so handles polled from
+    /// different loops do not contend with each other.
+    fn receive_message(&self, handle: c_int, buffer: &mut [u8], message_length: &mut c_int) -> c_int {
+        let input_ring = match self.managers.lock().unwrap().get(&handle) {
+            Some(manager) => manager.input_ring().cloned(),
+            None => return -1,
+        };
+
+        match input_ring.and_then(|ring| ring.pop_into(buffer)) {
+            Some(length) => {
+                if length > buffer.len() {
+                    return -1;
+                }
+
+                *message_length = length as c_int;
+                1
+            }
+            None => 0,
+        }
+    }
+
+    /// Drain a handle's input queue into `buffer` (see `MessageRing::drain_into`).
+    /// Returns 0 with the totals set, or -1 for an unknown handle.
+    fn receive_all(&self, handle: c_int, buffer: &mut [u8], total_length: &mut c_int, count: &mut c_int) -> c_int {
+        let input_ring = match self.managers.lock().unwrap().get(&handle) {
+            Some(manager) => manager.input_ring().cloned(),
+            None => return -1,
+        };
+
+        let (written, messages) = input_ring.map_or((0, 0), |ring| ring.drain_into(buffer));
+        *total_length = written as c_int;
+        *count = messages as c_int;
+        0
+    }
+
+    /// Number of input messages dropped because the handle's queue was full, or -1 for an
+    /// unknown handle
+    fn dropped_input_count(&self, handle: c_int) -> c_int {
+        match self.managers.lock().unwrap().get(&handle) {
+            Some(manager) => manager.input_ring()
+                .map_or(0, |ring| ring.dropped().min(c_int::MAX as u64) as c_int),
+            None => -1,
+        }
+    }
+
+    fn connect_with_user_event(&self, device_index: c_int, user_event_ref: u32, filter: Vec<u8>) -> c_int {
+        if let Err(e) = check_device_index(device_index) {
+            return e.code();
+        }
+
+        // Create MIDI manager
+        let mut manager = MidiManager::new();
+        manager.filter().set_status_bytes(filter);
+
+        // Create the listener shared with midir's callback
+        let user_event = LVUserEvent::<MidiEventData>::from_raw(user_event_ref);
+        let listener = Arc::new(EventListener::new(user_event, manager.filter().clone()));
+
+        // Create the callback that will be called directly by midir
+        let callback = {
+            let listener = listener.clone();
+            move |message: &[u8], _timestamp: u64| listener.process(message)
+        };
+
+        // Connect with the callback
+        match manager.connect_input_with_callback(device_index as usize, callback) {
+            Ok(_) => {
+                // Store the manager to keep the connection alive
+                let handle = self.next_handle();
+                let mut managers = self.managers.lock().unwrap();
+                managers.insert(handle, manager);
+                self.listeners.lock().unwrap().insert(handle, listener);
+                handle
+            }
+            Err(e) => e.code(),
+        }
+    }
+
+    /// Reopen a listener's input device so it starts from a fresh connection, keeping its
+    /// User Event, filter and other settings
+    fn restart_listener(&self, handle: c_int) -> c_int {
+        let listener = match self.listeners.lock().unwrap().get(&handle) {
+            Some(listener) => Arc::clone(listener),
+            None => return -1,
+        };
+
+        // Take the manager out of the map so no lock is held while the old connection closes
+        // (joining midir's thread) and the new one opens; the handle reads as unknown meanwhile
+        let mut manager = match self.managers.lock().unwrap().remove(&handle) {
+            Some(manager) => manager,
+            None => return -1,
+        };
+
+        let reapplied = Arc::clone(&listener);
+        reapplied.reapply_priority();
+        let callback = move |message: &[u8], _timestamp: u64| reapplied.process(message);
+        let result = match manager.reconnect_input_with_callback(callback) {
+            Ok(_) => 0,
+            Err(e) => e.code(),
+        };
+
+        // Put it back unless the listener was destroyed in the meantime. That destroy found
+        // no manager, so finish it here: drop the manager outside the locks and free the handle.
+        let destroyed = {
+            let mut managers = self.managers.lock().unwrap();
+            if self.listeners.lock().unwrap().contains_key(&handle) {
+                managers.insert(handle, manager);
+                None
+            } else {
+                Some(manager)
+            }
+        };
+        if let Some(manager) = destroyed {
+            drop(manager);
+            self.release_handle(handle);
+            return -1;
+        }
+        result
+    }
+
+    /// Close every manager and stop every listener, then reset the handle counter
+    fn shutdown(&self) {
+        // Take everything out of the maps first so no lock is held while connections close
+        let managers = std::mem::take(&mut *self.managers.lock().unwrap());
+        let listeners = std::mem::take(&mut *self.listeners.lock().unwrap());
+
+        // Closing the connections stops (and joins) midir's callback threads
+        drop(managers);
+        for listener in listeners.values() {
+            listener.stop();
+        }
+        drop(listeners);
+
+        self.handles.lock().unwrap().reset();
+    }
+}
+
+impl Default for MidiContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A MIDI file being streamed to an output device on a background thread
+struct Player {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Player {
+    /// Ask the playback thread to stop and wait for it to silence the output
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+/// Thread sending Active Sensing on a handle's output
+struct ActiveSensing {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl ActiveSensing {
+    /// Wake the thread from its wait and wait for it to exit, so nothing more is sent
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.thread().unpark();
+        let _ = self.thread.join();
+    }
+}
+
+/// In-flight CC ramps are keyed by (handle, channel, controller)
+type RampKey = (i32, u8, u8);
+
+/// Thread sending the steps of a CC ramp
+struct CcRamp {
+    cancelled: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl CcRamp {
+    /// Wake the thread from its wait and wait for it to exit, so no further step is sent
+    fn cancel(self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.thread.thread().unpark();
+        let _ = self.thread.join();
+    }
+}
+
+/// Raw messages with their arrival timestamps in microseconds
+type TimestampedMessages = Vec<(u64, Vec<u8>)>;
+
+/// Live input being captured into a MIDI file
+struct Recording {
+    messages: Arc<Mutex<TimestampedMessages>>,
+    ticks_per_quarter: u16,
+    tempo_us_per_quarter: u32,
+}
+
+fn default_context() -> &'static Arc<MidiContext> {
+    DEFAULT_CONTEXT.get_or_init(|| Arc::new(MidiContext::new()))
+}
+
+fn get_contexts() -> &'static Mutex<HashMap<i32, Arc<MidiContext>>> {
+    CONTEXTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Look up a context by handle; 0 is the default context used by the plain FFI functions
+fn get_context(context: c_int) -> Option<Arc<MidiContext>> {
+    match context {
+        0 => Some(Arc::clone(default_context())),
+        _ => get_contexts().lock().unwrap().get(&context).cloned(),
+    }
+}
 
 fn get_midi_managers() -> &'static Mutex<HashMap<i32, MidiManager>> {
-    MIDI_MANAGERS.get_or_init(|| Mutex::new(HashMap::new()))
+    &default_context().managers
+}
+
+fn get_event_listeners() -> &'static Mutex<HashMap<i32, Arc<EventListener>>> {
+    &default_context().listeners
+}
+
+fn get_recordings() -> &'static Mutex<HashMap<i32, Recording>> {
+    RECORDINGS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn get_players() -> &'static Mutex<HashMap<i32, Player>> {
+    PLAYERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn get_cc_ramps() -> &'static Mutex<HashMap<RampKey, CcRamp>> {
+    CC_RAMPS.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
-fn get_next_handle_mutex() -> &'static Mutex<i32> {
-    NEXT_HANDLE.get_or_init(|| Mutex::new(1))
+fn get_active_sensing() -> &'static Mutex<HashMap<i32, ActiveSensing>> {
+    ACTIVE_SENSING.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
 fn get_next_handle() -> i32 {
-    let mut handle = get_next_handle_mutex().lock().unwrap();
+    default_context().next_handle()
+}
+
+fn release_handle(handle: c_int) {
+    default_context().release_handle(handle);
+}
+
+/// Stop and forget everything the per-handle side tables (active sensing, CC ramps,
+/// scheduled sends, recordings) hold for a handle being released
+fn clear_handle_state(handle: c_int) {
+    stop_active_sensing(handle);
+    let ramps: Vec<CcRamp> = {
+        let mut ramps = get_cc_ramps().lock().unwrap();
+        let keys: Vec<RampKey> = ramps.keys().filter(|(ramp_handle, _, _)| *ramp_handle == handle).copied().collect();
+        keys.iter().filter_map(|key| ramps.remove(key)).collect()
+    };
+    for ramp in ramps {
+        ramp.cancel();
+    }
+    SCHEDULED_SENDS.cancel_handle(handle);
+    get_recordings().lock().unwrap().remove(&handle);
+}
+
+fn get_next_context_handle() -> i32 {
+    let mut handle = NEXT_CONTEXT_HANDLE.get_or_init(|| Mutex::new(1)).lock().unwrap();
     let current = *handle;
     *handle += 1;
     current
 }
 
+/// Reject a caller-supplied length above the configured maximum before a slice is built
+/// from it, so a garbage length from a buggy caller cannot describe a bogus memory region
+fn check_max_length(length: usize) -> Result<()> {
+    let max_length = MAX_BUFFER_LENGTH.load(Ordering::Relaxed);
+    if length > max_length {
+        return Err(LVInteropError::LengthOutOfRange(format!(
+            "{} bytes exceeds the {} byte limit", length, max_length
+        )));
+    }
+    Ok(())
+}
+
+/// Copy a status-byte filter passed from LabVIEW (a null or empty array means no filter)
+fn filter_from_raw(filter_array: *const c_uchar, array_size: c_int) -> Result<Vec<u8>> {
+    if array_size <= 0 || filter_array.is_null() {
+        return Ok(Vec::new());
+    }
+    check_max_length(array_size as usize)?;
+
+    let filter_slice = unsafe {
+        std::slice::from_raw_parts(filter_array, array_size as usize)
+    };
+    Ok(filter_slice.to_vec())
+}
+
 // ========== DEVICE DISCOVERY ==========
 
 /// Get the number of MIDI input devices
@@ -47,6 +499,36 @@ pub extern "C" fn midi_get_output_device_count() -> c_int {
     }
 }
 
+/// Get the number of MIDI input devices, giving up after `timeout_ms` if the driver blocks.
+/// Returns the count, or a negative error code (-6 on timeout).
+#[no_mangle]
+pub extern "C" fn midi_get_input_device_count_timeout(timeout_ms: c_int) -> c_int {
+    if timeout_ms < 0 {
+        return -1;
+    }
+
+    let manager = MidiManager::new();
+    match manager.list_input_devices_timeout(Duration::from_millis(timeout_ms as u64)) {
+        Ok(devices) => devices.len() as c_int,
+        Err(e) => e.code(),
+    }
+}
+
+/// Get the number of MIDI output devices, giving up after `timeout_ms` if the driver blocks.
+/// Returns the count, or a negative error code (-6 on timeout).
+#[no_mangle]
+pub extern "C" fn midi_get_output_device_count_timeout(timeout_ms: c_int) -> c_int {
+    if timeout_ms < 0 {
+        return -1;
+    }
+
+    let manager = MidiManager::new();
+    match manager.list_output_devices_timeout(Duration::from_millis(timeout_ms as u64)) {
+        Ok(devices) => devices.len() as c_int,
+        Err(e) => e.code(),
+    }
+}
+
 /// Get the name of a MIDI input device
 #[no_mangle]
 pub extern "C" fn midi_get_input_device_name(
@@ -65,25 +547,8 @@ pub extern "C" fn midi_get_input_device_name(
                 return -1;
             }
             
-            let device_name = &devices[device_index as usize];
-            let c_string = match CString::new(device_name.clone()) {
-                Ok(s) => s,
-                Err(_) => return -1,
-            };
-            
-            let name_bytes = c_string.as_bytes_with_nul();
-            if name_bytes.len() > buffer_size as usize {
-                return -1;
-            }
-            
-            unsafe {
-                std::ptr::copy_nonoverlapping(
-                    name_bytes.as_ptr() as *const c_char,
-                    buffer,
-                    name_bytes.len(),
-                );
-            }
-            0
+            // Listed names are sanitized, so every device's name fits in a C string
+            write_c_string(&devices[device_index as usize], buffer, buffer_size)
         }
         Err(_) => -1,
     }
@@ -107,422 +572,3164 @@ pub extern "C" fn midi_get_output_device_name(
                 return -1;
             }
             
-            let device_name = &devices[device_index as usize];
-            let c_string = match CString::new(device_name.clone()) {
-                Ok(s) => s,
-                Err(_) => return -1,
-            };
-            
-            let name_bytes = c_string.as_bytes_with_nul();
-            if name_bytes.len() > buffer_size as usize {
-                return -1;
-            }
-            
-            unsafe {
-                std::ptr::copy_nonoverlapping(
-                    name_bytes.as_ptr() as *const c_char,
-                    buffer,
-                    name_bytes.len(),
-                );
-            }
-            0
+            // Listed names are sanitized, so every device's name fits in a C string
+            write_c_string(&devices[device_index as usize], buffer, buffer_size)
         }
         Err(_) => -1,
     }
 }
 
-// ========== CONNECTION MANAGEMENT ==========
-
-/// Create a new MIDI manager instance
+/// Get the buffer size (including the null terminator) needed for an input device name
 #[no_mangle]
-pub extern "C" fn midi_create_manager() -> c_int {
-    let handle = get_next_handle();
+pub extern "C" fn midi_get_input_device_name_len(device_index: c_int) -> c_int {
     let manager = MidiManager::new();
-    
-    let mut managers = get_midi_managers().lock().unwrap();
-    managers.insert(handle, manager);
-    handle
-}
-
-/// Destroy a MIDI manager instance
-#[no_mangle]
-pub extern "C" fn midi_destroy_manager(handle: c_int) -> c_int {
-    let mut managers = get_midi_managers().lock().unwrap();
-    match managers.remove(&handle) {
-        Some(_) => 0,
-        None => -1,
-    }
-}
-
-/// Connect to a MIDI input device
-#[no_mangle]
-pub extern "C" fn midi_connect_input(handle: c_int, device_index: c_int) -> c_int {
-    let mut managers = get_midi_managers().lock().unwrap();
-    match managers.get_mut(&handle) {
-        Some(manager) => {
-            match manager.connect_input(device_index as usize) {
-                Ok(_) => 0,
-                Err(_) => -1,
+    match manager.list_input_devices() {
+        Ok(devices) => {
+            if device_index < 0 || device_index >= devices.len() as c_int {
+                return -1;
             }
+            c_string_len(&devices[device_index as usize])
         }
-        None => -1,
+        Err(_) => -1,
     }
 }
 
-/// Connect to a MIDI output device
+/// Get the buffer size (including the null terminator) needed for an output device name
 #[no_mangle]
-pub extern "C" fn midi_connect_output(handle: c_int, device_index: c_int) -> c_int {
-    let mut managers = get_midi_managers().lock().unwrap();
-    match managers.get_mut(&handle) {
-        Some(manager) => {
-            match manager.connect_output(device_index as usize) {
-                Ok(_) => 0,
-                Err(_) => -1,
+pub extern "C" fn midi_get_output_device_name_len(device_index: c_int) -> c_int {
+    let manager = MidiManager::new();
+    match manager.list_output_devices() {
+        Ok(devices) => {
+            if device_index < 0 || device_index >= devices.len() as c_int {
+                return -1;
             }
+            c_string_len(&devices[device_index as usize])
         }
-        None => -1,
+        Err(_) => -1,
     }
 }
 
-// ========== MIDI COMMUNICATION ==========
-
-/// Send a MIDI message
+/// Get the backend's identifier for a MIDI input device, which can be more durable than the
+/// index or name for persisting a selection (the name is returned if the backend has none).
+/// Returns 0 on success or -1 if the index is invalid or the buffer is too small.
 #[no_mangle]
-pub extern "C" fn midi_send_message(
-    handle: c_int,
-    message: *const c_uchar,
-    message_length: c_int,
+pub extern "C" fn midi_get_input_port_id(
+    device_index: c_int,
+    buffer: *mut c_char,
+    buffer_size: c_int,
 ) -> c_int {
-    if message.is_null() || message_length <= 0 {
+    if device_index < 0 {
         return -1;
     }
+    match MidiManager::new().input_port_id(device_index as usize) {
+        Ok(id) => write_c_string(&id, buffer, buffer_size),
+        Err(_) => -1,
+    }
+}
 
-    let message_slice = unsafe {
-        std::slice::from_raw_parts(message, message_length as usize)
-    };
-
-    let mut managers = get_midi_managers().lock().unwrap();
-    match managers.get_mut(&handle) {
-        Some(manager) => {
-            match manager.send_message(message_slice) {
-                Ok(_) => 0,
-                Err(_) => -1,
-            }
-        }
-        None => -1,
+/// Size of the null-terminated C string a getter would write for `s`, or -1 if
+/// the string cannot be represented (interior null byte)
+pub(crate) fn c_string_len(s: &str) -> c_int {
+    match CString::new(s) {
+        Ok(c_string) => c_string.as_bytes_with_nul().len() as c_int,
+        Err(_) => -1,
     }
 }
 
-/// Receive a MIDI message (non-blocking)
-#[no_mangle]
-pub extern "C" fn midi_receive_message(
-    handle: c_int,
-    buffer: *mut c_uchar,
-    buffer_size: c_int,
-    message_length: *mut c_int,
-) -> c_int {
-    if buffer.is_null() || message_length.is_null() || buffer_size <= 0 {
+/// Copy `s` into a caller-provided buffer as a null-terminated C string.
+/// Returns 0 on success or -1 if the buffer is invalid or too small.
+pub(crate) fn write_c_string(s: &str, buffer: *mut c_char, buffer_size: c_int) -> c_int {
+    if buffer.is_null() || buffer_size <= 0 {
         return -1;
     }
 
-    let managers = get_midi_managers().lock().unwrap();
-    match managers.get(&handle) {
+    let c_string = match CString::new(s) {
+        Ok(s) => s,
+        Err(_) => return -1,
+    };
+    
+    let bytes = c_string.as_bytes_with_nul();
+    if bytes.len() > buffer_size as usize {
+        return -1;
+    }
+    
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            bytes.as_ptr() as *const c_char,
+            buffer,
+            bytes.len(),
+        );
+    }
+    0
+}
+
+/// Device capabilities for LabVIEW (the name is read with the device name getters)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MidiDeviceInfo {
+    pub is_input: i32,
+    pub is_output: i32,
+    pub is_virtual: i32,
+}
+
+/// Get capabilities of a MIDI device. `direction` is 0 for input devices, 1 for output devices.
+#[no_mangle]
+pub extern "C" fn midi_get_device_info(
+    direction: c_int,
+    device_index: c_int,
+    info: *mut MidiDeviceInfo,
+) -> c_int {
+    if info.is_null() || device_index < 0 {
+        return -1;
+    }
+
+    let direction = match direction {
+        0 => PortDirection::Input,
+        1 => PortDirection::Output,
+        _ => return -1,
+    };
+
+    let manager = MidiManager::new();
+    match manager.describe_device(direction, device_index as usize) {
+        Ok(device_info) => {
+            unsafe {
+                *info = MidiDeviceInfo {
+                    is_input: device_info.has_input as i32,
+                    is_output: device_info.has_output as i32,
+                    is_virtual: device_info.is_virtual as i32,
+                };
+            }
+            0
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Replace the list of name patterns hidden from device enumeration, one pattern per line.
+/// Ports whose names contain a pattern (case-insensitive) are skipped by the device counts,
+/// names and connections, so device indices refer to the remaining ports.
+/// The default hides "Midi Through" and this library's own ports; an empty string shows every port.
+#[no_mangle]
+pub extern "C" fn midi_set_port_ignore_list(patterns: *const c_char) -> c_int {
+    if patterns.is_null() {
+        return -1;
+    }
+
+    let patterns = match unsafe { CStr::from_ptr(patterns) }.to_str() {
+        Ok(patterns) => patterns,
+        Err(_) => return -1,
+    };
+    set_port_ignore_list(patterns.lines().map(|pattern| pattern.trim().to_string()).collect());
+    0
+}
+
+/// Number repeated device names in the device lists and names ("USB MIDI", "USB MIDI" become
+/// "USB MIDI #1", "USB MIDI #2") so identical devices can be told apart. Numbering is by device
+/// index, and connecting by index is unaffected. Off by default.
+#[no_mangle]
+pub extern "C" fn midi_set_dedupe_names(enabled: c_uchar) -> c_int {
+    set_dedupe_names(enabled != 0);
+    0
+}
+
+/// Report Note On with velocity 0 as Note Off (message type 0) in `midi_parse_message`,
+/// listener events and MIDI files loaded afterwards. On by default, which is the standard
+/// MIDI meaning; pass 0 to see the raw Note On (message type 1, velocity 0) instead.
+#[no_mangle]
+pub extern "C" fn midi_set_zero_velocity_as_note_off(enabled: c_uchar) -> c_int {
+    set_zero_velocity_as_note_off(enabled != 0);
+    0
+}
+
+/// List devices sorted by name rather than in the backend's order, which can vary between
+/// calls on some backends; identically named devices are ordered by port id. Device counts,
+/// names and connecting by index all use the sorted order (with duplicate numbering from
+/// `midi_set_dedupe_names` following it), so an index keeps referring to the same device
+/// within a session as long as no device is added or removed. Plugging devices in or out
+/// still shifts indices: to remember a selection across sessions, save the device name (or
+/// `midi_get_input_port_id`) and look up its index. Off by default.
+#[no_mangle]
+pub extern "C" fn midi_set_sort_devices(enabled: c_uchar) -> c_int {
+    set_sort_devices(enabled != 0);
+    0
+}
+
+// ========== CONNECTION MANAGEMENT ==========
+
+/// Create a new MIDI manager instance
+#[no_mangle]
+pub extern "C" fn midi_create_manager() -> c_int {
+    default_context().create_manager()
+}
+
+/// Destroy a MIDI manager instance
+#[no_mangle]
+pub extern "C" fn midi_destroy_manager(handle: c_int) -> c_int {
+    default_context().destroy_manager(handle)
+}
+
+/// Reject a negative device index from LabVIEW up front with -2 (`DeviceNotFound`), rather
+/// than relying on its `usize` wrap-around happening to be out of range
+fn check_device_index(device_index: c_int) -> Result<()> {
+    if device_index < 0 {
+        return Err(LVInteropError::DeviceNotFound(format!("Device index {} is negative", device_index)));
+    }
+    Ok(())
+}
+
+/// Connect to a MIDI input device.
+/// Returns 0 on success or a negative error code (see `LVInteropError::code`);
+/// a negative device index gives -2 (`DeviceNotFound`).
+#[no_mangle]
+pub extern "C" fn midi_connect_input(handle: c_int, device_index: c_int) -> c_int {
+    default_context().connect_input(handle, device_index)
+}
+
+/// Connect to a MIDI output device.
+/// Returns 0 on success or a negative error code (see `LVInteropError::code`);
+/// a negative device index gives -2 (`DeviceNotFound`).
+#[no_mangle]
+pub extern "C" fn midi_connect_output(handle: c_int, device_index: c_int) -> c_int {
+    default_context().connect_output(handle, device_index)
+}
+
+/// Retry failed input/output connections (e.g. a USB interface that is still starting up) up to
+/// `retries` more times, waiting `delay_ms` before the first retry and doubling the wait after
+/// each. Only backend errors are retried, not an invalid device index. Each retry is logged as a
+/// warning through the log callback. Other handles keep working while a connect waits to
+/// retry. The default is no retries.
+#[no_mangle]
+pub extern "C" fn midi_set_connect_retries(retries: c_int, delay_ms: c_int) -> c_int {
+    if retries < 0 || delay_ms < 0 {
+        return -1;
+    }
+    set_connect_retries(retries as u32, delay_ms as u32);
+    0
+}
+
+/// Connect to the first MIDI input device, without enumerating first.
+/// Returns 0 on success, -8 (`NoDevices`) if no input device is present,
+/// or another negative error code (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_connect_first_input(handle: c_int) -> c_int {
+    default_context().connect_first(handle, true)
+}
+
+/// Connect to the first MIDI output device, without enumerating first.
+/// Returns 0 on success, -8 (`NoDevices`) if no output device is present,
+/// or another negative error code (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_connect_first_output(handle: c_int) -> c_int {
+    default_context().connect_first(handle, false)
+}
+
+/// Open the input and output of a round-trip device in one call: both ports named `name`
+/// (as listed by the device name getters) are connected on a new manager handle, which is
+/// used like one from `midi_create_manager` and closed with `midi_disconnect`.
+/// Returns the handle, -11 (`InputPortNotFound`) or -12 (`OutputPortNotFound`)
+/// if only one direction has a port with the name, -2 (`DeviceNotFound`) if neither has,
+/// -1 for a null or non-UTF-8 name, or another negative error code (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_open_duplex_by_name(name: *const c_char) -> c_int {
+    if name.is_null() {
+        return -1;
+    }
+    let name = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(name) => name,
+        Err(_) => return -1,
+    };
+
+    let manager = MidiManager::new();
+    match (manager.find_input_device(name), manager.find_output_device(name)) {
+        (Err(e), _) | (_, Err(e)) => e.code(),
+        (Ok(Some(input_index)), Ok(Some(output_index))) => default_context().open_duplex(input_index, output_index),
+        (Ok(None), Ok(Some(_))) => LVInteropError::InputPortNotFound(format!("No MIDI input named {}", name)).code(),
+        (Ok(Some(_)), Ok(None)) => LVInteropError::OutputPortNotFound(format!("No MIDI output named {}", name)).code(),
+        (Ok(None), Ok(None)) => LVInteropError::DeviceNotFound(format!("No MIDI port named {}", name)).code(),
+    }
+}
+
+/// Get a best-effort latency class for the output connected on a handle:
+/// 0 unknown (also when nothing is connected), 1 hardware, 2 virtual/software port.
+/// Returns -1 for an invalid handle.
+#[no_mangle]
+pub extern "C" fn midi_get_output_latency_hint(handle: c_int) -> c_int {
+    let managers = get_midi_managers().lock().unwrap();
+    match managers.get(&handle) {
+        Some(manager) => manager.output_latency_hint() as c_int,
+        None => -1,
+    }
+}
+
+/// Get the name of the MIDI input device connected on a handle
+#[no_mangle]
+pub extern "C" fn midi_get_connected_input_name(
+    handle: c_int,
+    buffer: *mut c_char,
+    buffer_size: c_int,
+) -> c_int {
+    let managers = get_midi_managers().lock().unwrap();
+    match managers.get(&handle).and_then(|manager| manager.connected_input_name()) {
+        Some(port_name) => write_c_string(&sanitize_device_name(port_name), buffer, buffer_size),
+        None => -1,
+    }
+}
+
+/// Get the name of the MIDI output device connected on a handle
+#[no_mangle]
+pub extern "C" fn midi_get_connected_output_name(
+    handle: c_int,
+    buffer: *mut c_char,
+    buffer_size: c_int,
+) -> c_int {
+    let managers = get_midi_managers().lock().unwrap();
+    match managers.get(&handle).and_then(|manager| manager.connected_output_name()) {
+        Some(port_name) => write_c_string(&sanitize_device_name(port_name), buffer, buffer_size),
+        None => -1,
+    }
+}
+
+/// Reconnect to the last connected MIDI output device (matched by name).
+/// Returns 0 on success or a negative error code (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_reconnect_output(handle: c_int) -> c_int {
+    let mut managers = get_midi_managers().lock().unwrap();
+    match managers.get_mut(&handle) {
+        Some(manager) => {
+            match manager.reconnect_output() {
+                Ok(_) => 0,
+                Err(e) => e.code(),
+            }
+        }
+        None => -1,
+    }
+}
+
+/// Stop every listener, recording and player, close all managers, contexts and MIDI files,
+/// and reset the handle counters. Safe to call repeatedly and while listeners are active.
+#[no_mangle]
+pub extern "C" fn midi_shutdown_all() -> c_int {
+    // Take everything out of the maps first so no lock is held while connections close
+    let contexts = std::mem::take(&mut *get_contexts().lock().unwrap());
+    let recordings = std::mem::take(&mut *get_recordings().lock().unwrap());
+    let players = std::mem::take(&mut *get_players().lock().unwrap());
+
+    let ramps = std::mem::take(&mut *get_cc_ramps().lock().unwrap());
+    SCHEDULED_SENDS.clear();
+    let active_sensing = std::mem::take(&mut *get_active_sensing().lock().unwrap());
+    for sensing in active_sensing.into_values() {
+        sensing.stop();
+    }
+
+    for ramp in ramps.into_values() {
+        ramp.cancel();
+    }
+    for player in players.into_values() {
+        player.stop();
+    }
+
+    drop(recordings);
+    default_context().shutdown();
+    for context in contexts.into_values() {
+        context.shutdown();
+    }
+
+    close_all_midi_files();
+    0
+}
+
+// ========== CONTEXTS ==========
+
+/// Create an independent context with its own managers, listeners and handle numbers.
+/// Context handles are passed to the `midi_ctx_*` functions; 0 refers to the default context.
+#[no_mangle]
+pub extern "C" fn midi_context_create() -> c_int {
+    let handle = get_next_context_handle();
+    get_contexts().lock().unwrap().insert(handle, Arc::new(MidiContext::new()));
+    handle
+}
+
+/// Close every manager and listener in a context and destroy it
+#[no_mangle]
+pub extern "C" fn midi_context_destroy(context: c_int) -> c_int {
+    let removed = get_contexts().lock().unwrap().remove(&context);
+    match removed {
+        Some(context) => {
+            context.shutdown();
+            0
+        }
+        None => -1,
+    }
+}
+
+/// `midi_create_manager` in the given context
+#[no_mangle]
+pub extern "C" fn midi_ctx_create_manager(context: c_int) -> c_int {
+    match get_context(context) {
+        Some(context) => context.create_manager(),
+        None => -1,
+    }
+}
+
+/// `midi_destroy_manager` in the given context
+#[no_mangle]
+pub extern "C" fn midi_ctx_destroy_manager(context: c_int, handle: c_int) -> c_int {
+    match get_context(context) {
+        Some(context) => context.destroy_manager(handle),
+        None => -1,
+    }
+}
+
+/// `midi_connect_input` in the given context
+#[no_mangle]
+pub extern "C" fn midi_ctx_connect_input(context: c_int, handle: c_int, device_index: c_int) -> c_int {
+    match get_context(context) {
+        Some(context) => context.connect_input(handle, device_index),
+        None => -1,
+    }
+}
+
+/// `midi_connect_output` in the given context
+#[no_mangle]
+pub extern "C" fn midi_ctx_connect_output(context: c_int, handle: c_int, device_index: c_int) -> c_int {
+    match get_context(context) {
+        Some(context) => context.connect_output(handle, device_index),
+        None => -1,
+    }
+}
+
+/// `midi_send_message` in the given context
+#[no_mangle]
+pub extern "C" fn midi_ctx_send_message(
+    context: c_int,
+    handle: c_int,
+    message: *const c_uchar,
+    message_length: c_int,
+) -> c_int {
+    if message.is_null() || message_length <= 0 {
+        return -1;
+    }
+    if let Err(e) = check_max_length(message_length as usize) {
+        return e.code();
+    }
+
+    let message_slice = unsafe {
+        std::slice::from_raw_parts(message, message_length as usize)
+    };
+
+    match get_context(context) {
+        Some(context) => context.send_message(handle, message_slice),
+        None => -1,
+    }
+}
+
+/// `midi_receive_message` in the given context
+#[no_mangle]
+pub extern "C" fn midi_ctx_receive_message(
+    context: c_int,
+    handle: c_int,
+    buffer: *mut c_uchar,
+    buffer_size: c_int,
+    message_length: *mut c_int,
+) -> c_int {
+    if buffer.is_null() || message_length.is_null() || buffer_size <= 0 {
+        return -1;
+    }
+    if let Err(e) = check_max_length(buffer_size as usize) {
+        return e.code();
+    }
+
+    let (buffer_slice, message_length) = unsafe {
+        (std::slice::from_raw_parts_mut(buffer, buffer_size as usize), &mut *message_length)
+    };
+
+    match get_context(context) {
+        Some(context) => context.receive_message(handle, buffer_slice, message_length),
+        None => -1,
+    }
+}
+
+/// `midi_connect_with_user_event` in the given context
+#[no_mangle]
+pub extern "C" fn midi_ctx_connect_with_user_event(
+    context: c_int,
+    device_index: c_int,
+    user_event_ref: u32,
+    filter_array: *const c_uchar,
+    array_size: c_int,
+) -> c_int {
+    let filter = match filter_from_raw(filter_array, array_size) {
+        Ok(filter) => filter,
+        Err(e) => return e.code(),
+    };
+
+    match get_context(context) {
+        Some(context) => context.connect_with_user_event(device_index, user_event_ref, filter),
+        None => -1,
+    }
+}
+
+// ========== MIDI COMMUNICATION ==========
+
+/// Wait for the handle's next output slot in the default context
+fn wait_for_output_slot(handle: c_int) -> bool {
+    default_context().wait_for_output_slot(handle)
+}
+
+/// Cap the number of messages sent per second on a handle's output (0 = unlimited).
+/// Sends over the cap sleep in the calling thread until their slot comes up; there is
+/// no output queue, so a LabVIEW loop sending faster than the cap is slowed down to it.
+#[no_mangle]
+pub extern "C" fn midi_set_output_rate_limit(handle: c_int, messages_per_sec: c_int) -> c_int {
+    if messages_per_sec < 0 {
+        return -1;
+    }
+
+    let managers = get_midi_managers().lock().unwrap();
+    match managers.get(&handle) {
+        Some(manager) => {
+            manager.rate_limiter().set_rate(messages_per_sec as u32);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Set the largest message or buffer length (in bytes) accepted from the caller.
+/// Longer lengths are rejected with the `LengthOutOfRange` error code (-7) instead of being
+/// trusted; the default is 64 KiB.
+#[no_mangle]
+pub extern "C" fn midi_set_max_buffer_length(max_length: c_int) -> c_int {
+    if max_length <= 0 {
+        return -1;
+    }
+
+    MAX_BUFFER_LENGTH.store(max_length as usize, Ordering::Relaxed);
+    0
+}
+
+/// Send a MIDI message.
+/// Returns 0 on success or a negative error code (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_send_message(
+    handle: c_int,
+    message: *const c_uchar,
+    message_length: c_int,
+) -> c_int {
+    if message.is_null() || message_length <= 0 {
+        return -1;
+    }
+    if let Err(e) = check_max_length(message_length as usize) {
+        return e.code();
+    }
+
+    let message_slice = unsafe {
+        std::slice::from_raw_parts(message, message_length as usize)
+    };
+
+    send_paced(handle, message_slice)
+}
+
+/// Send a message on a handle, honouring its output rate limit
+fn send_paced(handle: c_int, message: &[u8]) -> c_int {
+    default_context().send_message(handle, message)
+}
+
+/// Send several messages under a single lock, e.g. the notes of a chord.
+/// `messages` holds the messages back to back and `lengths` the length of each one.
+/// The batch is paced as one send by the output rate limit, then sent without gaps.
+/// Returns `count` on success, the index of the first message that failed, or -1 for bad arguments.
+#[no_mangle]
+pub extern "C" fn midi_send_messages(
+    handle: c_int,
+    messages: *const c_uchar,
+    lengths: *const c_int,
+    count: c_int,
+) -> c_int {
+    if messages.is_null() || lengths.is_null() || count <= 0 {
+        return -1;
+    }
+
+    if let Err(e) = check_max_length(count as usize) {
+        return e.code();
+    }
+
+    let lengths_slice = unsafe {
+        std::slice::from_raw_parts(lengths, count as usize)
+    };
+    if lengths_slice.iter().any(|&length| length <= 0) {
+        return -1;
+    }
+    let total_length: usize = lengths_slice.iter().map(|&length| length as usize).sum();
+    if let Err(e) = check_max_length(total_length) {
+        return e.code();
+    }
+    let messages_slice = unsafe {
+        std::slice::from_raw_parts(messages, total_length)
+    };
+
+    if !wait_for_output_slot(handle) {
+        return -1;
+    }
+
+    let mut managers = get_midi_managers().lock().unwrap();
+    let manager = match managers.get_mut(&handle) {
+        Some(manager) => manager,
+        None => return -1,
+    };
+
+    let mut offset = 0;
+    for (index, &length) in lengths_slice.iter().enumerate() {
+        let message = &messages_slice[offset..offset + length as usize];
+        if manager.send_message(message).is_err() {
+            return index as c_int;
+        }
+        offset += length as usize;
+    }
+    count
+}
+
+/// Send a MIDI message and report how long the backend took to accept it (in microseconds).
+/// Returns 0 on success or a negative error code (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_send_message_timed(
+    handle: c_int,
+    message: *const c_uchar,
+    message_length: c_int,
+    send_micros: *mut u64,
+) -> c_int {
+    if message.is_null() || send_micros.is_null() || message_length <= 0 {
+        return -1;
+    }
+    if let Err(e) = check_max_length(message_length as usize) {
+        return e.code();
+    }
+
+    let message_slice = unsafe {
+        std::slice::from_raw_parts(message, message_length as usize)
+    };
+
+    if !wait_for_output_slot(handle) {
+        return -1;
+    }
+
+    let mut managers = get_midi_managers().lock().unwrap();
+    match managers.get_mut(&handle) {
+        Some(manager) => {
+            match manager.send_message_timed(message_slice) {
+                Ok(elapsed) => {
+                    unsafe {
+                        *send_micros = elapsed.as_micros() as u64;
+                    }
+                    0
+                }
+                Err(e) => e.code(),
+            }
+        }
+        None => -1,
+    }
+}
+
+/// Validate a MIDI message before sending it.
+/// Returns 0 if valid, -1 for a null/empty argument or a length above the maximum, or -(2 + index)
+/// where `index` is the position of the first bad byte (a missing byte is reported at the
+/// position it should occupy).
+#[no_mangle]
+pub extern "C" fn midi_validate_message(message: *const c_uchar, message_length: c_int) -> c_int {
+    // The error codes would collide with the bad-byte positions, so an oversized length is -1 here
+    if message.is_null() || message_length <= 0 || check_max_length(message_length as usize).is_err() {
+        return -1;
+    }
+
+    let message_slice = unsafe {
+        std::slice::from_raw_parts(message, message_length as usize)
+    };
+
+    match find_invalid_byte(message_slice) {
+        Some((index, _)) => -2 - index as c_int,
+        None => 0,
+    }
+}
+
+/// Receive a MIDI message (non-blocking).
+/// Incoming messages wait in a queue of `RECEIVE_RING_CAPACITY` bytes (64 KB). If the queue
+/// fills because it is polled too slowly, new messages are dropped rather than queued;
+/// `midi_get_dropped_input_count` reports how many.
+#[no_mangle]
+pub extern "C" fn midi_receive_message(
+    handle: c_int,
+    buffer: *mut c_uchar,
+    buffer_size: c_int,
+    message_length: *mut c_int,
+) -> c_int {
+    if buffer.is_null() || message_length.is_null() || buffer_size <= 0 {
+        return -1;
+    }
+    if let Err(e) = check_max_length(buffer_size as usize) {
+        return e.code();
+    }
+
+    let (buffer_slice, message_length) = unsafe {
+        (std::slice::from_raw_parts_mut(buffer, buffer_size as usize), &mut *message_length)
+    };
+
+    default_context().receive_message(handle, buffer_slice, message_length)
+}
+
+/// Receive every queued message in one call, for loops that poll slower than messages arrive.
+/// Messages are packed back to back into `buffer`, each framed as a 2-byte little-endian
+/// length followed by that many message bytes:
+///
+/// | offset | bytes | content                      |
+/// |--------|-------|------------------------------|
+/// | 0      | 2     | length `n` of message 1 (LE) |
+/// | 2      | n     | message 1                    |
+/// | 2 + n  | 2     | length of message 2, ...     |
+///
+/// `out_total_len` receives the number of bytes written and `out_count` the number of messages.
+/// Messages that do not fit stay queued for the next call; a single message longer than the
+/// whole buffer is discarded. Returns 0 on success or a negative error code
+/// (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_receive_all(
+    handle: c_int,
+    buffer: *mut c_uchar,
+    buffer_size: c_int,
+    out_total_len: *mut c_int,
+    out_count: *mut c_int,
+) -> c_int {
+    if buffer.is_null() || buffer_size <= 0 || out_total_len.is_null() || out_count.is_null() {
+        return -1;
+    }
+    if let Err(e) = check_max_length(buffer_size as usize) {
+        return e.code();
+    }
+
+    let (buffer_slice, total_length, count) = unsafe {
+        (std::slice::from_raw_parts_mut(buffer, buffer_size as usize), &mut *out_total_len, &mut *out_count)
+    };
+
+    default_context().receive_all(handle, buffer_slice, total_length, count)
+}
+
+/// Get the number of input messages dropped since the input was connected because they
+/// arrived while the handle's receive queue was full (see `midi_receive_message`).
+/// Returns the count (0 with no input connected) or -1 for an unknown handle.
+#[no_mangle]
+pub extern "C" fn midi_get_dropped_input_count(handle: c_int) -> c_int {
+    default_context().dropped_input_count(handle)
+}
+
+/// Check whether a handle's input device is still present.
+/// Returns 1 if alive, 0 if the device appears lost (or no input is connected), -1 for an unknown handle.
+#[no_mangle]
+pub extern "C" fn midi_input_is_alive(handle: c_int) -> c_int {
+    let managers = get_midi_managers().lock().unwrap();
+    match managers.get(&handle) {
+        Some(manager) => manager.input_is_alive() as c_int,
+        None => -1,
+    }
+}
+
+/// Close a handle's input connection but keep the handle, e.g. to connect another device
+/// later. Returns 0, -3 if no input was connected, or -1 for an unknown handle.
+#[no_mangle]
+pub extern "C" fn midi_close_input(handle: c_int) -> c_int {
+    let mut managers = get_midi_managers().lock().unwrap();
+    match managers.get_mut(&handle) {
+        Some(manager) => match manager.close_input() {
+            Ok(_) => 0,
+            Err(e) => e.code(),
+        },
+        None => -1,
+    }
+}
+
+/// Close a handle's output connection but keep the handle.
+/// Returns 0, -3 if no output was connected, or -1 for an unknown handle.
+#[no_mangle]
+pub extern "C" fn midi_close_output(handle: c_int) -> c_int {
+    let mut managers = get_midi_managers().lock().unwrap();
+    match managers.get_mut(&handle) {
+        Some(manager) => match manager.close_output() {
+            Ok(_) => 0,
+            Err(e) => e.code(),
+        },
+        None => -1,
+    }
+}
+
+/// Disconnect and cleanup a MIDI connection. For a listener this waits for its coalescing
+/// thread to flush held-back values, which takes no longer than a post to LabVIEW.
+#[no_mangle]
+pub extern "C" fn midi_disconnect(handle: c_int) -> c_int {
+    default_context().destroy_manager(handle)
+}
+
+// ========== CC RAMPS ==========
+
+/// Interpolated CC values for a ramp, excluding the starting value. The last value is always `to`.
+fn cc_ramp_values(from: u8, to: u8, steps: u32) -> Vec<u8> {
+    let steps = steps.max(1);
+    (1..=steps)
+        .map(|step| {
+            let value = from as f64 + (to as f64 - from as f64) * step as f64 / steps as f64;
+            value.round() as u8
+        })
+        .collect()
+}
+
+/// Cancel the ramp running on a controller, if any, waiting for its thread to exit
+fn cancel_cc_ramp(key: RampKey) -> bool {
+    let ramp = get_cc_ramps().lock().unwrap().remove(&key);
+    match ramp {
+        Some(ramp) => {
+            ramp.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Upper bound on the `steps` of a CC ramp. A 7-bit controller has only 128 values, so more
+/// steps would only resend the same values.
+const MAX_CC_RAMP_STEPS: c_int = 128;
+
+/// Ramp a controller from `from` to `to` over `duration_ms`, sending `from` immediately and then
+/// `steps` evenly spaced values ending exactly on `to`. Values are clamped to 0–127 and `steps`
+/// to at most 128. Runs on a background thread; a new ramp on the same controller replaces
+/// the old one.
+#[no_mangle]
+pub extern "C" fn midi_send_cc_ramp(
+    handle: c_int,
+    channel: c_uchar,
+    controller: c_uchar,
+    from: c_int,
+    to: c_int,
+    duration_ms: c_int,
+    steps: c_int,
+) -> c_int {
+    if channel > 15 || controller > 127 || duration_ms < 0 || steps < 1 {
+        return -1;
+    }
+    if !get_midi_managers().lock().unwrap().contains_key(&handle) {
+        return -1;
+    }
+
+    let steps = steps.min(MAX_CC_RAMP_STEPS);
+    let from = from.clamp(0, 127) as u8;
+    let to = to.clamp(0, 127) as u8;
+    let values = cc_ramp_values(from, to, steps as u32);
+    let step_interval = Duration::from_millis(duration_ms as u64) / steps as u32;
+
+    // Stop the old ramp before the new one sends anything
+    let key = (handle, channel, controller);
+    cancel_cc_ramp(key);
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let mut ramps = get_cc_ramps().lock().unwrap();
+    let thread = {
+        let cancelled = Arc::clone(&cancelled);
+        thread::spawn(move || {
+            let start = Instant::now();
+            let mut result = send_paced(handle, &MidiManager::control_change(channel, controller, from));
+
+            for (step, value) in values.into_iter().enumerate() {
+                if result != 0 || cancelled.load(Ordering::Relaxed) {
+                    break;
+                }
+                // Parking may wake early (or be woken by a cancel), so wait against the deadline
+                let due = step_interval * (step as u32 + 1);
+                while let Some(delay) = due.checked_sub(start.elapsed()).filter(|delay| !delay.is_zero()) {
+                    if cancelled.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    thread::park_timeout(delay);
+                }
+                if cancelled.load(Ordering::Relaxed) {
+                    break;
+                }
+                result = send_paced(handle, &MidiManager::control_change(channel, controller, value));
+            }
+
+            // Only remove our own entry; a replacement ramp may already be registered
+            let mut ramps = get_cc_ramps().lock().unwrap();
+            if ramps.get(&key).is_some_and(|current| Arc::ptr_eq(&current.cancelled, &cancelled)) {
+                ramps.remove(&key);
+            }
+        })
+    };
+    // Registered before the lock is released, so the thread cannot finish and look for its
+    // entry first. A ramp registered by a concurrent call in between is replaced.
+    let replaced = ramps.insert(key, CcRamp { cancelled, thread });
+    drop(ramps);
+    if let Some(replaced) = replaced {
+        replaced.cancel();
+    }
+
+    0
+}
+
+/// Cancel an in-flight CC ramp. Returns 0 if a ramp was cancelled, -1 if none was running.
+#[no_mangle]
+pub extern "C" fn midi_cancel_cc_ramp(handle: c_int, channel: c_uchar, controller: c_uchar) -> c_int {
+    if cancel_cc_ramp((handle, channel, controller)) {
+        0
+    } else {
+        -1
+    }
+}
+
+// ========== SCHEDULED SENDS ==========
+
+/// Queue key (due time, cue id) and the handle and message to send
+type ScheduledQueue = BTreeMap<(Instant, i32), (c_int, Vec<u8>)>;
+
+/// Messages waiting to be sent by the timer thread, keyed by due time and then cue id so
+/// they fire in time order (and in scheduling order when due together)
+struct ScheduledSends {
+    queue: Mutex<ScheduledQueue>,
+    wakeup: Condvar,
+    next_cue: AtomicI32,
+    timer: Once,
+}
+
+impl ScheduledSends {
+    const fn new() -> Self {
+        ScheduledSends {
+            queue: Mutex::new(BTreeMap::new()),
+            wakeup: Condvar::new(),
+            next_cue: AtomicI32::new(1),
+            timer: Once::new(),
+        }
+    }
+
+    /// Queue a message for a handle and return its cue id. The timer thread starts on first use.
+    fn schedule(&'static self, handle: c_int, delay: Duration, message: Vec<u8>) -> i32 {
+        self.timer.call_once(|| {
+            thread::spawn(move || self.run());
+        });
+
+        let cue = self.next_cue.fetch_add(1, Ordering::Relaxed);
+        self.queue.lock().unwrap().insert((Instant::now() + delay, cue), (handle, message));
+        self.wakeup.notify_one();
+        cue
+    }
+
+    fn cancel(&self, cue: i32) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        let key = queue.keys().find(|(_, queued_cue)| *queued_cue == cue).copied();
+        key.and_then(|key| queue.remove(&key)).is_some()
+    }
+
+    /// Drop every cue queued for a handle
+    fn cancel_handle(&self, handle: c_int) {
+        self.queue.lock().unwrap().retain(|_, (queued_handle, _)| *queued_handle != handle);
+    }
+
+    fn clear(&self) {
+        self.queue.lock().unwrap().clear();
+    }
+
+    /// Timer thread: sleep until the earliest cue is due (or a new one arrives), then send it
+    /// outside the lock so scheduling and cancelling never wait on the output
+    fn run(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            let due = match queue.first_key_value() {
+                Some((&(due, _), _)) => due,
+                None => {
+                    queue = self.wakeup.wait(queue).unwrap();
+                    continue;
+                }
+            };
+
+            let now = Instant::now();
+            if due > now {
+                queue = self.wakeup.wait_timeout(queue, due - now).unwrap().0;
+                continue;
+            }
+
+            if let Some((_, (handle, message))) = queue.pop_first() {
+                drop(queue);
+                send_paced(handle, &message);
+                queue = self.queue.lock().unwrap();
+            }
+        }
+    }
+}
+
+/// Send a message on a handle after `delay_ms`, from a background timer thread (paced by the
+/// handle's output rate limit like any other send). Cues fire in time order.
+/// Returns a cue id for `midi_cancel_scheduled`, or a negative error code.
+#[no_mangle]
+pub extern "C" fn midi_schedule_send(
+    handle: c_int,
+    delay_ms: c_int,
+    message: *const c_uchar,
+    message_length: c_int,
+) -> c_int {
+    if message.is_null() || message_length <= 0 || delay_ms < 0 {
+        return -1;
+    }
+    if let Err(e) = check_max_length(message_length as usize) {
+        return e.code();
+    }
+    if !get_midi_managers().lock().unwrap().contains_key(&handle) {
+        return -1;
+    }
+
+    let message_slice = unsafe {
+        std::slice::from_raw_parts(message, message_length as usize)
+    };
+    if let Err(e) = validate_message(message_slice) {
+        return LVInteropError::InvalidMessage(e).code();
+    }
+
+    SCHEDULED_SENDS.schedule(handle, Duration::from_millis(delay_ms as u64), message_slice.to_vec())
+}
+
+/// Cancel a scheduled send. Returns 0 if it was cancelled, -1 if it already fired or is unknown.
+#[no_mangle]
+pub extern "C" fn midi_cancel_scheduled(cue_id: c_int) -> c_int {
+    if SCHEDULED_SENDS.cancel(cue_id) {
+        0
+    } else {
+        -1
+    }
+}
+
+// ========== ACTIVE SENSING ==========
+
+/// Stop the Active Sensing thread of a handle, if any
+fn stop_active_sensing(handle: c_int) -> bool {
+    let sensing = get_active_sensing().lock().unwrap().remove(&handle);
+    match sensing {
+        Some(sensing) => {
+            sensing.stop();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Send Active Sensing (0xFE) on a handle's output every `interval_ms`, for synths that mute
+/// when it stops arriving (the MIDI spec allows at most 300 ms between messages). Runs on a
+/// background thread alongside normal sends until disabled, the manager is destroyed or a
+/// send fails (e.g. the output was closed); enabling it again changes the interval.
+/// Off by default.
+#[no_mangle]
+pub extern "C" fn midi_enable_active_sensing(handle: c_int, interval_ms: c_int) -> c_int {
+    if interval_ms <= 0 || !get_midi_managers().lock().unwrap().contains_key(&handle) {
+        return -1;
+    }
+
+    let interval = Duration::from_millis(interval_ms as u64);
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = {
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || {
+            let mut next_send = Instant::now() + interval;
+            while !stop.load(Ordering::Relaxed) {
+                // Parking may wake early, so wait against the deadline
+                let now = Instant::now();
+                if now < next_send {
+                    thread::park_timeout(next_send - now);
+                    continue;
+                }
+                // Stop for good once the output is gone rather than retrying forever
+                if send_paced(handle, &[0xFE]) != 0 {
+                    break;
+                }
+                next_send += interval;
+            }
+        })
+    };
+
+    let previous = get_active_sensing().lock().unwrap().insert(handle, ActiveSensing { stop, thread });
+    if let Some(previous) = previous {
+        previous.stop();
+    }
+    0
+}
+
+/// Stop sending Active Sensing on a handle. Returns 0, or -1 if it was not enabled.
+#[no_mangle]
+pub extern "C" fn midi_disable_active_sensing(handle: c_int) -> c_int {
+    if stop_active_sensing(handle) {
+        0
+    } else {
+        -1
+    }
+}
+
+// ========== HELPER FUNCTIONS ==========
+
+/// Enable or disable strict mode for the message creators. By default out-of-range values
+/// are masked into range (note 200 becomes note 72); in strict mode the creators return -1.
+#[no_mangle]
+pub extern "C" fn midi_set_strict_mode(enabled: c_uchar) -> c_int {
+    STRICT_MODE.store(enabled != 0, Ordering::Relaxed);
+    0
+}
+
+type ChannelMessageBuilder = fn(u8, u8, u8) -> Vec<u8>;
+type CheckedChannelMessageBuilder = fn(u8, u8, u8) -> Result<Vec<u8>>;
+
+/// Build a channel message with the masking builder, or the validating one in strict mode
+fn build_channel_message(
+    build: ChannelMessageBuilder,
+    try_build: CheckedChannelMessageBuilder,
+    channel: u8,
+    data1: u8,
+    data2: u8,
+) -> Result<Vec<u8>> {
+    if STRICT_MODE.load(Ordering::Relaxed) {
+        try_build(channel, data1, data2)
+    } else {
+        Ok(build(channel, data1, data2))
+    }
+}
+
+/// Create a Note On message
+#[no_mangle]
+pub extern "C" fn midi_create_note_on(
+    channel: c_uchar,
+    note: c_uchar,
+    velocity: c_uchar,
+    buffer: *mut c_uchar,
+) -> c_int {
+    if buffer.is_null() {
+        return -1;
+    }
+
+    let message = match build_channel_message(MidiManager::note_on, MidiManager::try_note_on, channel, note, velocity) {
+        Ok(message) => message,
+        Err(_) => return -1,
+    };
+    unsafe {
+        std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, 3);
+    }
+    3
+}
+
+/// Create a Note Off message
+#[no_mangle]
+pub extern "C" fn midi_create_note_off(
+    channel: c_uchar,
+    note: c_uchar,
+    velocity: c_uchar,
+    buffer: *mut c_uchar,
+) -> c_int {
+    if buffer.is_null() {
+        return -1;
+    }
+
+    let message = match build_channel_message(MidiManager::note_off, MidiManager::try_note_off, channel, note, velocity) {
+        Ok(message) => message,
+        Err(_) => return -1,
+    };
+    unsafe {
+        std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, 3);
+    }
+    3
+}
+
+/// Create a true Note Off (0x8n) with velocity 0, as opposed to Note On with velocity 0
+#[no_mangle]
+pub extern "C" fn midi_create_note_off_zero(
+    channel: c_uchar,
+    note: c_uchar,
+    buffer: *mut c_uchar,
+) -> c_int {
+    if buffer.is_null() {
+        return -1;
+    }
+
+    let message = match build_channel_message(MidiManager::note_off, MidiManager::try_note_off, channel, note, 0) {
+        Ok(message) => message,
+        Err(_) => return -1,
+    };
+    unsafe {
+        std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, 3);
+    }
+    3
+}
+
+/// Create a Control Change message
+#[no_mangle]
+pub extern "C" fn midi_create_control_change(
+    channel: c_uchar,
+    controller: c_uchar,
+    value: c_uchar,
+    buffer: *mut c_uchar,
+) -> c_int {
+    if buffer.is_null() {
+        return -1;
+    }
+
+    let message = match build_channel_message(MidiManager::control_change, MidiManager::try_control_change, channel, controller, value) {
+        Ok(message) => message,
+        Err(_) => return -1,
+    };
+    unsafe {
+        std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, 3);
+    }
+    3
+}
+
+/// Create a Song Position Pointer message (F2 lsb msb).
+/// `position` is in MIDI beats (sixteenth notes) from the start of the song, 0-16383.
+#[no_mangle]
+pub extern "C" fn midi_create_song_position(position: c_int, buffer: *mut c_uchar) -> c_int {
+    if buffer.is_null() || !(0..=0x3FFF).contains(&position) {
+        return -1;
+    }
+
+    let message = MidiManager::song_position_pointer(position as u16);
+    unsafe {
+        std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, 3);
+    }
+    3
+}
+
+/// Create a Song Select message (F3 song)
+#[no_mangle]
+pub extern "C" fn midi_create_song_select(song: c_uchar, buffer: *mut c_uchar) -> c_int {
+    if buffer.is_null() {
+        return -1;
+    }
+
+    let message = MidiManager::song_select(song);
+    unsafe {
+        std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, 2);
+    }
+    2
+}
+
+/// Send a MIDI Machine Control command to all devices as F0 7F 7F 06 <command> F7.
+/// Commands: 1 Stop, 2 Play, 3 Deferred Play, 4 Fast Forward, 5 Rewind,
+/// 6 Record Strobe (punch in), 7 Record Exit (punch out), 9 Pause.
+#[no_mangle]
+pub extern "C" fn midi_send_mmc(handle: c_int, command: c_uchar) -> c_int {
+    let command = match MmcCommand::from_byte(command) {
+        Some(command) => command,
+        None => return -1,
+    };
+
+    send_paced(handle, &MidiManager::mmc(0x7F, command))
+}
+
+// ========== MIDI MESSAGE PARSING ==========
+
+/// Parse a MIDI message into its components. `message_type` is numbered as documented
+/// on `MidiEventData`. Returns 0, -13 (`TruncatedMessage`) if the message is shorter than
+/// its status byte requires (3 bytes for note, aftertouch, CC and pitch bend, 2 for program
+/// change and channel pressure), or another negative error code (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_parse_message(
+    message: *const c_uchar,
+    message_length: c_int,
+    message_type: *mut c_uchar,
+    channel: *mut c_uchar,
+    note_or_controller: *mut c_uchar,
+    velocity_or_value: *mut c_uchar,
+) -> c_int {
+    if message.is_null() || message_type.is_null() || channel.is_null() || 
+       note_or_controller.is_null() || velocity_or_value.is_null() || message_length < 1 {
+        return -1;
+    }
+    if let Err(e) = check_max_length(message_length as usize) {
+        return e.code();
+    }
+
+    let message_slice = unsafe {
+        std::slice::from_raw_parts(message, message_length as usize)
+    };
+
+    if message_slice.is_empty() {
+        return -1;
+    }
+
+    // A truncated message must not be reported as a real event with zeroed data
+    let required_length = match message_slice[0] & 0xF0 {
+        0x80 | 0x90 | 0xA0 | 0xB0 | 0xE0 => 3,
+        0xC0 | 0xD0 => 2,
+        _ => 1,
+    };
+    if message_slice.len() < required_length {
+        return LVInteropError::TruncatedMessage(format!(
+            "status 0x{:02X} needs {} bytes, got {}", message_slice[0], required_length, message_slice.len()
+        )).code();
+    }
+    if !is_midi1_message(message_slice) {
+        unsafe {
+            *message_type = MESSAGE_TYPE_UNSUPPORTED as c_uchar;
+            *channel = 0;
+            *note_or_controller = 0;
+            *velocity_or_value = 0;
+        }
+        return 0;
+    }
+
+    // System messages (and SysEx fragments) are reported as type 255 with no data
+    let parsed = MidiMessage::parse(message_slice).ok();
+    let (data1, data2) = match &parsed {
+        // Pitch bend is reported as the 14-bit value split into low and high bytes
+        Some(MidiMessage::PitchBend { value, .. }) => ((value & 0xFF) as u8, (value >> 8) as u8),
+        Some(MidiMessage::ProgramChange { program: data1, .. } | MidiMessage::ChannelPressure { pressure: data1, .. }) => {
+            (*data1, 0)
+        }
+        Some(parsed) if parsed.channel().is_some() => (message_slice[1], message_slice[2]),
+        _ => (0, 0),
+    };
+    unsafe {
+        *message_type = parsed.as_ref().map_or(255, |parsed| message_type_code(parsed, zero_velocity_as_note_off())) as c_uchar;
+        *channel = message_slice[0] & 0x0F;
+        *note_or_controller = data1;
+        *velocity_or_value = data2;
+    }
+
+    0
+}
+
+/// Parse a MIDI message like `midi_parse_message`, but report pitch bend as a single
+/// 14-bit value (0-16383, center 8192) in `pitch_bend_value`. For pitch bend the two data
+/// bytes are returned unchanged (LSB, MSB); for other messages `pitch_bend_value` is 8192.
+#[no_mangle]
+pub extern "C" fn midi_parse_message_ex(
+    message: *const c_uchar,
+    message_length: c_int,
+    message_type: *mut c_uchar,
+    channel: *mut c_uchar,
+    note_or_controller: *mut c_uchar,
+    velocity_or_value: *mut c_uchar,
+    pitch_bend_value: *mut i32,
+) -> c_int {
+    if pitch_bend_value.is_null() {
+        return -1;
+    }
+
+    let result = midi_parse_message(
+        message,
+        message_length,
+        message_type,
+        channel,
+        note_or_controller,
+        velocity_or_value,
+    );
+    if result != 0 {
+        return result;
+    }
+
+    let message_slice = unsafe {
+        std::slice::from_raw_parts(message, message_length as usize)
+    };
+
+    unsafe {
+        *pitch_bend_value = 8192;
+        if *message_type == 4 {
+            let lsb = message_slice[1] & 0x7F;
+            let msb = message_slice[2] & 0x7F;
+            *note_or_controller = lsb;
+            *velocity_or_value = msb;
+            *pitch_bend_value = ((msb as i32) << 7) | lsb as i32;
+        }
+    }
+
+    0
+}
+
+// ========== LABVIEW USER EVENTS - CALLBACK SYSTEM ==========
+
+/// MIDI data structure for LabVIEW User Events
+///
+/// LabVIEW reads this as a cluster of five I32 in exactly this field order:
+/// offsets 0, 4, 8, 12, 16 with no padding (20 bytes total). Values are in the
+/// platform's native byte order (little-endian on every supported target), which
+/// is what LabVIEW expects for data posted from a Call Library Function Node.
+/// Changing the fields breaks every LabVIEW VI that declares the cluster.
+///
+/// `message_type` (also returned by `midi_parse_message` and named by
+/// `midi_get_message_type_name`):
+///
+/// | value | message |
+/// |-------|---------|
+/// | 0 | Note Off (and Note On with velocity 0, unless that is switched off) |
+/// | 1 | Note On |
+/// | 2 | Control Change (controllers 0-119) |
+/// | 3 | Program Change |
+/// | 4 | Pitch Bend |
+/// | 5 | Channel Aftertouch (pressure in `note_or_controller`) |
+/// | 6 | Poly Aftertouch |
+/// | 7 | Channel Mode (controllers 120-127) |
+/// | 8 | not MIDI 1.0 (`MESSAGE_TYPE_UNSUPPORTED`) |
+/// | 255 | unknown (system messages and messages that do not parse) |
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MidiEventData {
+    pub message_type: i32,
+    pub channel: i32,
+    pub note_or_controller: i32,
+    pub velocity_or_value: i32,
+    pub raw_status: i32,
+}
+
+unsafe impl LVPostable for MidiEventData {}
+
+/// `message_type` of data that is not MIDI 1.0 (e.g. MIDI 2.0 Universal MIDI Packets).
+/// Only the first byte is decoded (into `raw_status`); the bytes are passed through unparsed.
+pub const MESSAGE_TYPE_UNSUPPORTED: i32 = 8;
+
+impl MidiEventData {
+    /// Decode a non-empty raw message into the cluster posted to LabVIEW
+    pub fn from_message(message: &[u8]) -> Self {
+        let status_byte = message[0];
+        if !is_midi1_message(message) {
+            return MidiEventData {
+                message_type: MESSAGE_TYPE_UNSUPPORTED,
+                channel: 0,
+                note_or_controller: 0,
+                velocity_or_value: 0,
+                raw_status: status_byte as i32,
+            };
+        }
+
+        let data1 = if message.len() > 1 { message[1] } else { 0 };
+        let data2 = if message.len() > 2 { message[2] } else { 0 };
+
+        MidiEventData {
+            message_type: MidiMessage::parse(message).as_ref()
+                .map_or(255, |parsed| message_type_code(parsed, zero_velocity_as_note_off())),
+            channel: (status_byte & 0x0F) as i32,
+            note_or_controller: data1 as i32,
+            velocity_or_value: data2 as i32,
+            raw_status: status_byte as i32,
+        }
+    }
+}
+
+/// The `MidiEventData::message_type` of a parsed message (255 for system messages;
+/// callers use 255 for messages that do not parse either). Callers pass the
+/// `set_zero_velocity_as_note_off` setting in as `zero_velocity_note_off`.
+fn message_type_code(message: &MidiMessage, zero_velocity_note_off: bool) -> i32 {
+    match message {
+        MidiMessage::NoteOff { .. } => 0,
+        MidiMessage::NoteOn { velocity: 0, .. } if zero_velocity_note_off => 0,
+        MidiMessage::NoteOn { .. } => 1,
+        MidiMessage::ControlChange { controller, .. } => if *controller >= 120 { 7 } else { 2 }, // Channel Mode / Control Change
+        MidiMessage::ProgramChange { .. } => 3,
+        MidiMessage::PitchBend { .. } => 4,
+        MidiMessage::ChannelPressure { .. } => 5, // Pressure in note_or_controller
+        MidiMessage::PolyAftertouch { .. } => 6,
+        _ => 255,
+    }
+}
+
+/// Number of per-message-type counters kept by an `EventListener`.
+/// Indices 0-7 follow `MidiEventData::message_type` and 8 counts unknown and unsupported
+/// (non-MIDI 1.0) messages.
+pub const EVENT_COUNT_SLOTS: usize = 9;
+
+/// SysEx notification posted to LabVIEW; the bytes are read with `midi_get_last_sysex`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SysExEventData {
+    pub handle: i32,
+    pub length: i32,
+}
+
+unsafe impl LVPostable for SysExEventData {}
+
+/// Per-note expression for an MPE member channel, posted to LabVIEW User Events
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MpeNoteEvent {
+    pub channel: i32,
+    pub note: i32,
+    /// Signed pitch bend, -8192..8191 with 0 at center
+    pub per_note_bend: i32,
+    pub per_note_pressure: i32,
+}
+
+unsafe impl LVPostable for MpeNoteEvent {}
+
+/// Chord held on a channel, posted to LabVIEW User Events whenever its held notes change.
+/// `quality_code` is a `ChordQuality` (0 if the notes are not a known triad or seventh, in
+/// which case `root` is -1); the notes themselves are read with `midi_get_held_notes`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ChordEvent {
+    pub channel: i32,
+    /// Pitch class of the root, 0 = C .. 11 = B
+    pub root: i32,
+    pub quality_code: i32,
+    pub note_count: i32,
+}
+
+unsafe impl LVPostable for ChordEvent {}
+
+/// Latest channel pressure and pitch bend of a channel, posted to LabVIEW User Events when
+/// either changes (e.g. breath and bend of a wind controller as one continuous expression)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpressionEvent {
+    pub channel: i32,
+    /// Channel aftertouch, 0..127
+    pub pressure: i32,
+    /// Signed pitch bend, -8192..8191 with 0 at center
+    pub bend: i32,
+}
+
+unsafe impl LVPostable for ExpressionEvent {}
+
+/// MIDI Time Code assembled from a full sequence of eight quarter-frame messages (0xF1),
+/// posted to LabVIEW User Events. It is the time at which the sequence started; a device
+/// running in real time is two frames further on by the time the sequence completes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimecodeEvent {
+    pub hours: i32,
+    pub minutes: i32,
+    pub seconds: i32,
+    pub frames: i32,
+    /// Frames per second: 24, 25, 29 (29.97 drop-frame) or 30
+    pub rate: i32,
+}
+
+unsafe impl LVPostable for TimecodeEvent {}
+
+/// Collects the eight pieces of a quarter-frame sequence, in order from piece 0
+#[derive(Debug, Default)]
+struct MtcAssembler {
+    nibbles: [u8; 8],
+    next_piece: u8,
+}
+
+impl MtcAssembler {
+    /// Add the data byte of a quarter-frame message. Returns the timecode once piece 7
+    /// completes a sequence; a piece out of order discards the sequence so far.
+    fn push(&mut self, data: u8) -> Option<TimecodeEvent> {
+        let piece = (data >> 4) & 0x07;
+        if piece != self.next_piece {
+            self.next_piece = 0;
+            if piece != 0 {
+                return None;
+            }
+        }
+        self.nibbles[piece as usize] = data & 0x0F;
+        self.next_piece = (piece + 1) % 8;
+        if piece != 7 {
+            return None;
+        }
+
+        let value = |low: usize| (self.nibbles[low] | (self.nibbles[low + 1] << 4)) as i32;
+        Some(TimecodeEvent {
+            hours: value(6) & 0x1F,
+            minutes: value(4) & 0x3F,
+            seconds: value(2) & 0x3F,
+            frames: value(0) & 0x1F,
+            rate: [24, 25, 29, 30][(self.nibbles[7] >> 1) as usize & 0x03],
+        })
+    }
+}
+
+/// Expression state of the note sounding on one MPE member channel
+#[derive(Debug, Clone, Copy, Default)]
+struct MpeVoice {
+    note: Option<u8>,
+    bend: i32,
+    pressure: i32,
+}
+
+/// Device hot-plug notification for LabVIEW User Events (a cluster of three I32)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceChangeEvent {
+    /// 0 = input device, 1 = output device
+    pub direction: i32,
+    /// 0 = removed, 1 = added
+    pub change_type: i32,
+    /// Number of devices in that direction after the change
+    pub device_count: i32,
+}
+
+unsafe impl LVPostable for DeviceChangeEvent {}
+
+/// State shared between a listener's midir callback and the FFI functions
+pub struct EventListener {
+    filter: Arc<MessageFilter>,
+    user_event: LVUserEvent<MidiEventData>,
+    event_counts: [AtomicU32; EVENT_COUNT_SLOTS],
+    sysex_event: Mutex<Option<(i32, LVUserEvent<SysExEventData>)>>,
+    sysex_pending: Mutex<Vec<u8>>,
+    last_sysex: Mutex<Vec<u8>>,
+    last_unsupported: Mutex<Vec<u8>>,
+    coalesce_enabled: AtomicBool,
+    coalesce_window_ms: AtomicU32,
+    coalesce_slots: Mutex<[CoalesceSlot; 32]>, // Pitch bend then channel aftertouch, per channel
+    mpe_member_channels: AtomicU8, // Lower zone member channels (0 = MPE off)
+    mpe_event: Mutex<Option<LVUserEvent<MpeNoteEvent>>>,
+    mpe_voices: Mutex<[MpeVoice; 16]>,
+    chord_event: Mutex<Option<LVUserEvent<ChordEvent>>>,
+    held_notes: Mutex<[u128; 16]>, // Bit n = note n held, per channel (tracked for chord detection)
+    expression_event: Mutex<Option<LVUserEvent<ExpressionEvent>>>,
+    expression: Mutex<[(i32, i32); 16]>, // Pressure and bend, per channel
+    release_curve: Mutex<Option<[u8; 128]>>, // Note-off velocity mapping (None = identity)
+    mtc_event: Mutex<Option<LVUserEvent<TimecodeEvent>>>,
+    mtc: Mutex<MtcAssembler>,
+    event_log: Mutex<Option<EventLogWriter>>,
+    history: Mutex<(usize, VecDeque<MidiEventData>)>, // Capacity (0 = off) and recent events, oldest first
+    coalesce_thread: Mutex<Option<JoinHandle<()>>>,
+    started_at: Instant,
+    latency_enabled: AtomicBool,
+    latency: Mutex<LatencyStats>,
+    priority: AtomicI32, // Requested input thread priority (-1 = leave the OS default)
+    priority_applied: AtomicBool,
+}
+
+/// Time from entering the input callback to the User Event post returning
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatencyStats {
+    count: u64,
+    total_us: u64,
+    min_us: u64,
+    max_us: u64,
+}
+
+impl LatencyStats {
+    fn record(&mut self, elapsed: Duration) {
+        let elapsed_us = elapsed.as_micros() as u64;
+        self.min_us = if self.count == 0 { elapsed_us } else { self.min_us.min(elapsed_us) };
+        self.max_us = self.max_us.max(elapsed_us);
+        self.total_us += elapsed_us;
+        self.count += 1;
+    }
+
+    /// Minimum, average and maximum in microseconds (all zero before the first sample)
+    pub fn min_avg_max(&self) -> (f64, f64, f64) {
+        if self.count == 0 {
+            return (0.0, 0.0, 0.0);
+        }
+        (self.min_us as f64, self.total_us as f64 / self.count as f64, self.max_us as f64)
+    }
+}
+
+/// Default window within which continuous messages on one channel are coalesced
+pub const DEFAULT_COALESCE_WINDOW_MS: u32 = 5;
+
+/// Coalescing state for one continuous message type on one channel
+#[derive(Debug, Clone, Copy, Default)]
+struct CoalesceSlot {
+    last_post: Option<Instant>,
+    pending: Option<MidiEventData>,
+}
+
+impl EventListener {
+    pub fn new(user_event: LVUserEvent<MidiEventData>, filter: Arc<MessageFilter>) -> Self {
+        EventListener {
+            filter,
+            user_event,
+            event_counts: Default::default(),
+            sysex_event: Mutex::new(None),
+            sysex_pending: Mutex::new(Vec::new()),
+            last_sysex: Mutex::new(Vec::new()),
+            last_unsupported: Mutex::new(Vec::new()),
+            coalesce_enabled: AtomicBool::new(false),
+            coalesce_window_ms: AtomicU32::new(DEFAULT_COALESCE_WINDOW_MS),
+            coalesce_slots: Mutex::new([CoalesceSlot::default(); 32]),
+            mpe_member_channels: AtomicU8::new(0),
+            mpe_event: Mutex::new(None),
+            mpe_voices: Mutex::new([MpeVoice::default(); 16]),
+            chord_event: Mutex::new(None),
+            held_notes: Mutex::new([0; 16]),
+            expression_event: Mutex::new(None),
+            expression: Mutex::new([(0, 0); 16]),
+            release_curve: Mutex::new(None),
+            mtc_event: Mutex::new(None),
+            mtc: Mutex::new(MtcAssembler::default()),
+            event_log: Mutex::new(None),
+            history: Mutex::new((0, VecDeque::new())),
+            coalesce_thread: Mutex::new(None),
+            started_at: Instant::now(),
+            latency_enabled: AtomicBool::new(false),
+            latency: Mutex::new(LatencyStats::default()),
+            priority: AtomicI32::new(-1),
+            priority_applied: AtomicBool::new(true),
+        }
+    }
+
+    /// Filter, count and post a single incoming MIDI message
+    pub fn process(&self, message: &[u8]) {
+        self.process_input(message, true);
+    }
+
+    /// Like `process`, for a message replayed from a log. Replayed messages are not written
+    /// to the listener's event log, so replaying while logging does not log them twice.
+    pub fn process_replayed(&self, message: &[u8]) {
+        self.process_input(message, false);
+    }
+
+    fn process_input(&self, message: &[u8], log: bool) {
+        if message.is_empty() {
+            return;
+        }
+        let started = self.latency_enabled.load(Ordering::Relaxed).then(Instant::now);
+        self.apply_priority();
+
+        if log {
+            if let Some(event_log) = &*self.event_log.lock().unwrap() {
+                event_log.log(message);
+            }
+        }
+
+        // SysEx has its own delivery path and bypasses the status filter
+        if self.process_sysex(message) {
+            return;
+        }
+
+        // Data that is not MIDI 1.0 (e.g. MIDI 2.0 packets) is passed through as-is rather than
+        // misread as channel voice messages; the status filter, MPE and coalescing don't apply
+        if !is_midi1_message(message) {
+            *self.last_unsupported.lock().unwrap() = message.to_vec();
+            let mut event_data = MidiEventData::from_message(message);
+            self.count(event_data.message_type as u8);
+            self.remember(event_data);
+            self.post(&mut event_data);
+            return;
+        }
+
+        let remapped;
+        let message = match self.remap_release_velocity(message) {
+            Some(message) => {
+                remapped = message;
+                &remapped[..]
+            }
+            None => message,
+        };
+        let status_byte = message[0];
+
+        // MPE, chord detection and expression need to see every message, so they track
+        // notes and expression ahead of the filter
+        self.track_mpe(message);
+        self.track_chord(message);
+        self.track_expression(message);
+        self.track_mtc(message);
+
+        // Apply filter (status bytes and velocity threshold) if specified
+        if !self.filter.passes_message(message) {
+            return;
+        }
+
+        let mut event_data = MidiEventData::from_message(message);
+        self.count(event_data.message_type as u8);
+        self.remember(event_data);
+
+        if self.coalesce(status_byte, event_data) {
+            return;
+        }
+
+        // Post the event to LabVIEW directly from midir's callback
+        self.post(&mut event_data);
+
+        if let Some(started) = started {
+            self.latency.lock().unwrap().record(started.elapsed());
+        }
+    }
+
+    /// Request an OS priority for the input thread. midir owns that thread, so the priority
+    /// is applied from the callback, when the next message arrives.
+    pub fn set_priority(&self, priority: i32) {
+        self.priority.store(priority, Ordering::Relaxed);
+        self.priority_applied.store(false, Ordering::Release);
+    }
+
+    /// Apply the requested priority again, e.g. after the input connection (and with it
+    /// midir's thread) has been replaced
+    fn reapply_priority(&self) {
+        if self.priority.load(Ordering::Relaxed) >= 0 {
+            self.priority_applied.store(false, Ordering::Release);
+        }
+    }
+
+    fn apply_priority(&self) {
+        if self.priority_applied.swap(true, Ordering::Acquire) {
+            return;
+        }
+        let priority = self.priority.load(Ordering::Relaxed);
+        if let Err(e) = set_current_thread_priority(priority) {
+            log!(Warning, "Failed to set MIDI input thread priority {}: {}", priority, e);
+        }
+    }
+
+    /// Turn latency measurement on or off; either way the collected statistics are cleared
+    pub fn set_latency_stats(&self, enabled: bool) {
+        *self.latency.lock().unwrap() = LatencyStats::default();
+        self.latency_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn latency_stats(&self) -> LatencyStats {
+        *self.latency.lock().unwrap()
+    }
+
+    /// Enable MPE with a lower zone of `member_channels` channels (master channel 1,
+    /// members 2..=member_channels + 1), or disable it with 0
+    pub fn set_mpe(&self, member_channels: u8) {
+        self.mpe_member_channels.store(member_channels.min(15), Ordering::Relaxed);
+        *self.mpe_voices.lock().unwrap() = [MpeVoice::default(); 16];
+    }
+
+    /// Register the user event that receives `MpeNoteEvent`s
+    pub fn set_mpe_event(&self, mpe_event: LVUserEvent<MpeNoteEvent>) {
+        *self.mpe_event.lock().unwrap() = Some(mpe_event);
+    }
+
+    /// Enable chord detection posting to `chord_event`, or disable it with None.
+    /// Either way tracking starts over with no notes held.
+    pub fn set_chord_event(&self, chord_event: Option<LVUserEvent<ChordEvent>>) {
+        let mut current = self.chord_event.lock().unwrap();
+        *self.held_notes.lock().unwrap() = [0; 16];
+        *current = chord_event;
+    }
+
+    /// Notes currently held on a channel, in ascending order (only tracked while chord
+    /// detection is enabled)
+    pub fn held_notes(&self, channel: u8) -> Vec<u8> {
+        let held = self.held_notes.lock().unwrap()[channel as usize & 0x0F];
+        (0..128u8).filter(|note| held & (1 << note) != 0).collect()
+    }
+
+    /// Update the held notes of the message's channel and post the chord they form
+    fn track_chord(&self, message: &[u8]) {
+        let chord_event = match *self.chord_event.lock().unwrap() {
+            Some(chord_event) => chord_event,
+            None => return,
+        };
+
+        let channel = message[0] & 0x0F;
+        let data1 = message.get(1).copied().unwrap_or(0) & 0x7F;
+        let data2 = message.get(2).copied().unwrap_or(0);
+        {
+            let mut held_notes = self.held_notes.lock().unwrap();
+            let held = &mut held_notes[channel as usize];
+            match message[0] & 0xF0 {
+                0x90 if data2 > 0 => *held |= 1 << data1,
+                0x80 | 0x90 => *held &= !(1 << data1),
+                0xB0 if data1 == 120 || data1 == 123 => *held = 0, // All Sound / All Notes Off
+                _ => return,
+            }
+        }
+
+        let notes = self.held_notes(channel);
+        let (root, quality_code) = match detect_chord(&notes) {
+            Some((root, quality)) => (root as i32, quality as i32),
+            None => (-1, 0),
+        };
+        let mut chord_data = ChordEvent {
+            channel: channel as i32,
+            root,
+            quality_code,
+            note_count: notes.len() as i32,
+        };
+        if let Err(e) = chord_event.post(&mut chord_data) {
+            log!(Error, "Failed to post chord event to LabVIEW: {}", e);
+        }
+    }
+
+    /// Start writing every received message, before any filtering, to an event log at `path`
+    /// (appending if it exists). Replaces any log already being written.
+    pub fn start_logging(&self, path: &Path) -> std::io::Result<()> {
+        let writer = EventLogWriter::create(path)?;
+        let previous = self.event_log.lock().unwrap().replace(writer);
+        match previous {
+            Some(previous) => previous.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop logging, waiting for queued messages to be written. None if not logging.
+    pub fn stop_logging(&self) -> Option<std::io::Result<()>> {
+        let writer = self.event_log.lock().unwrap().take();
+        writer.map(EventLogWriter::finish)
+    }
+
+    /// Keep the last `size` events that passed the filter (0 turns the history off and clears it)
+    pub fn set_history_size(&self, size: usize) {
+        let mut history = self.history.lock().unwrap();
+        history.0 = size;
+        let excess = history.1.len().saturating_sub(size);
+        history.1.drain(..excess);
+    }
+
+    fn remember(&self, event: MidiEventData) {
+        let mut history = self.history.lock().unwrap();
+        let (size, events) = &mut *history;
+        if *size == 0 {
+            return;
+        }
+        if events.len() == *size {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// The remembered events, newest first
+    pub fn history(&self) -> Vec<MidiEventData> {
+        self.history.lock().unwrap().1.iter().rev().copied().collect()
+    }
+
+    /// Map note-off (0x80) velocities through `curve` (indexed by the received velocity), or
+    /// pass them through unchanged with None
+    pub fn set_release_curve(&self, curve: Option<[u8; 128]>) {
+        *self.release_curve.lock().unwrap() = curve;
+    }
+
+    /// The note-off with its velocity mapped through the release curve, if one is set
+    fn remap_release_velocity(&self, message: &[u8]) -> Option<[u8; 3]> {
+        match message {
+            [status, note, velocity] if status & 0xF0 == 0x80 => {
+                let curve = (*self.release_curve.lock().unwrap())?;
+                Some([*status, *note, curve[(*velocity & 0x7F) as usize]])
+            }
+            _ => None,
+        }
+    }
+
+    /// Enable combined expression events posting to `expression_event`, or disable them with
+    /// None. Either way every channel starts over at zero pressure and centered bend.
+    pub fn set_expression_event(&self, expression_event: Option<LVUserEvent<ExpressionEvent>>) {
+        let mut current = self.expression_event.lock().unwrap();
+        *self.expression.lock().unwrap() = [(0, 0); 16];
+        *current = expression_event;
+    }
+
+    /// Merge channel pressure or pitch bend into the channel's expression and post it.
+    /// Returns the posted event, or None if the message is neither or the mode is off.
+    fn track_expression(&self, message: &[u8]) -> Option<ExpressionEvent> {
+        let expression_event = (*self.expression_event.lock().unwrap())?;
+
+        let channel = message[0] & 0x0F;
+        let data1 = message.get(1).copied().unwrap_or(0) as i32;
+        let data2 = message.get(2).copied().unwrap_or(0) as i32;
+        let mut expression_data = {
+            let mut expression = self.expression.lock().unwrap();
+            let (pressure, bend) = &mut expression[channel as usize];
+            match message[0] & 0xF0 {
+                0xD0 => *pressure = data1,
+                0xE0 => *bend = ((data2 << 7) | data1) - 8192,
+                _ => return None,
+            }
+            ExpressionEvent {
+                channel: channel as i32,
+                pressure: *pressure,
+                bend: *bend,
+            }
+        };
+
+        if let Err(e) = expression_event.post(&mut expression_data) {
+            log!(Error, "Failed to post expression event to LabVIEW: {}", e);
+        }
+        Some(expression_data)
+    }
+
+    pub fn set_mtc_event(&self, mtc_event: Option<LVUserEvent<TimecodeEvent>>) {
+        let mut current = self.mtc_event.lock().unwrap();
+        *self.mtc.lock().unwrap() = MtcAssembler::default();
+        *current = mtc_event;
+    }
+
+    /// Feed an MTC quarter-frame into the assembler and post the timecode when a sequence
+    /// completes. Returns the posted event, or None if nothing was completed or the mode is off.
+    fn track_mtc(&self, message: &[u8]) -> Option<TimecodeEvent> {
+        let mtc_event = (*self.mtc_event.lock().unwrap())?;
+        let data = match message {
+            [0xF1, data] => *data,
+            _ => return None,
+        };
+
+        let mut timecode = self.mtc.lock().unwrap().push(data)?;
+        if let Err(e) = mtc_event.post(&mut timecode) {
+            log!(Error, "Failed to post timecode event to LabVIEW: {}", e);
+        }
+        Some(timecode)
+    }
+
+    /// Associate pitch bend and channel pressure on MPE member channels with the note
+    /// sounding on that channel. Returns the enriched event when expression changed.
+    fn track_mpe(&self, message: &[u8]) -> Option<MpeNoteEvent> {
+        let member_channels = self.mpe_member_channels.load(Ordering::Relaxed);
+        let channel = message[0] & 0x0F;
+        if member_channels == 0 || channel == 0 || channel > member_channels || message[0] < 0x80 {
+            return None;
+        }
+
+        let data1 = message.get(1).copied().unwrap_or(0);
+        let data2 = message.get(2).copied().unwrap_or(0);
+        let mut voices = self.mpe_voices.lock().unwrap();
+        let voice = &mut voices[channel as usize];
+        match message[0] & 0xF0 {
+            0x90 if data2 > 0 => {
+                // Bend sent before the note-on applies to it, pressure starts fresh
+                voice.note = Some(data1);
+                voice.pressure = 0;
+                return None;
+            }
+            0x80 | 0x90 => {
+                if voice.note == Some(data1) {
+                    voice.note = None;
+                }
+                return None;
+            }
+            0xE0 => voice.bend = (((data2 as i32) << 7) | data1 as i32) - 8192,
+            0xD0 => voice.pressure = data1 as i32,
+            _ => return None,
+        }
+
+        let note = voice.note?;
+        let mut mpe_data = MpeNoteEvent {
+            channel: channel as i32,
+            note: note as i32,
+            per_note_bend: voice.bend,
+            per_note_pressure: voice.pressure,
+        };
+        drop(voices);
+
+        if let Some(mpe_event) = *self.mpe_event.lock().unwrap() {
+            if let Err(e) = mpe_event.post(&mut mpe_data) {
+                log!(Error, "Failed to post MPE event to LabVIEW: {}", e);
+            }
+        }
+        Some(mpe_data)
+    }
+
+    fn post(&self, event_data: &mut MidiEventData) {
+        if let Err(e) = self.user_event.post(event_data) {
+            log!(Error, "Failed to post MIDI event to LabVIEW: {}", e);
+        }
+    }
+
+    /// Hold back pitch bend / channel aftertouch arriving within the coalesce window of the
+    /// last posted value on the same channel. Returns true if the event was held back; the
+    /// latest held value is posted by the flush thread once the window has passed.
+    fn coalesce(&self, status_byte: u8, event_data: MidiEventData) -> bool {
+        if !self.coalesce_enabled.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let slot_index = match status_byte & 0xF0 {
+            0xE0 => (status_byte & 0x0F) as usize,
+            0xD0 => 16 + (status_byte & 0x0F) as usize,
+            _ => return false, // Notes and everything else are never coalesced
+        };
+
+        let window = self.coalesce_window();
+        let mut slots = self.coalesce_slots.lock().unwrap();
+        let slot = &mut slots[slot_index];
+        match slot.last_post {
+            Some(last_post) if last_post.elapsed() < window => {
+                slot.pending = Some(event_data);
+                true
+            }
+            _ => {
+                slot.last_post = Some(Instant::now());
+                slot.pending = None;
+                false
+            }
+        }
+    }
+
+    /// Post held-back values whose window has passed (or all of them if `force`)
+    fn flush_coalesced(&self, force: bool) {
+        let window = self.coalesce_window();
+        let mut ready = Vec::new();
+        {
+            let mut slots = self.coalesce_slots.lock().unwrap();
+            for slot in slots.iter_mut() {
+                let window_passed = slot.last_post.is_none_or(|last_post| last_post.elapsed() >= window);
+                if slot.pending.is_some() && (force || window_passed) {
+                    ready.extend(slot.pending.take());
+                    slot.last_post = Some(Instant::now());
+                }
+            }
+        }
+
+        for mut event_data in ready {
+            self.post(&mut event_data);
+        }
+    }
+
+    fn coalesce_window(&self) -> Duration {
+        Duration::from_millis(self.coalesce_window_ms.load(Ordering::Relaxed) as u64)
+    }
+
+    pub fn set_coalesce_window_ms(&self, window_ms: u32) {
+        self.coalesce_window_ms.store(window_ms, Ordering::Relaxed);
+    }
+
+    /// Enable or disable coalescing. While enabled, a background thread posts held-back
+    /// values so the final value of a gesture is never lost. Disabling flushes them and waits
+    /// for the thread to exit, so toggling quickly never leaves two threads running.
+    pub fn set_coalesce(self: &Arc<Self>, enabled: bool) {
+        // Held throughout so concurrent calls cannot start a second thread
+        let mut coalesce_thread = self.coalesce_thread.lock().unwrap();
+        self.coalesce_enabled.store(enabled, Ordering::SeqCst);
+        if !enabled {
+            // Wake the thread so it flushes and exits now rather than after the window
+            if let Some(coalesce_thread) = coalesce_thread.take() {
+                coalesce_thread.thread().unpark();
+                let _ = coalesce_thread.join();
+            }
+            return;
+        }
+        if coalesce_thread.is_some() {
+            return;
+        }
+
+        let listener = Arc::downgrade(self);
+        *coalesce_thread = Some(thread::spawn(move || {
+            while let Some(listener) = listener.upgrade() {
+                if !listener.coalesce_enabled.load(Ordering::SeqCst) {
+                    listener.flush_coalesced(true);
+                    break;
+                }
+                listener.flush_coalesced(false);
+
+                // Parked rather than asleep so `stop` can wake it without waiting out the window
+                let window = listener.coalesce_window();
+                drop(listener);
+                thread::park_timeout(window.max(Duration::from_millis(1)));
+            }
+        }));
+    }
+
+    /// Stop background work (flushing any held-back values) and wait for it to finish.
+    /// The coalescing thread is woken rather than left to finish its wait, so stopping takes
+    /// at most one `post` already in flight plus the final flush, independent of the window.
+    pub fn stop(&self) {
+        self.coalesce_enabled.store(false, Ordering::SeqCst);
+        let coalesce_thread = self.coalesce_thread.lock().unwrap().take();
+        if let Some(coalesce_thread) = coalesce_thread {
+            coalesce_thread.thread().unpark();
+            let _ = coalesce_thread.join();
+        }
+    }
+
+    /// Collect SysEx data until the terminating 0xF7, which may arrive over several callbacks.
+    /// A new 0xF0 or any non-real-time status byte discards an unterminated dump.
+    /// Returns true if the message was part of a SysEx dump.
+    fn process_sysex(&self, message: &[u8]) -> bool {
+        let mut pending = self.sysex_pending.lock().unwrap();
+        if message[0] == 0xF0 {
+            pending.clear();
+            pending.extend_from_slice(message);
+        } else if !pending.is_empty() && (message[0] < 0x80 || message[0] == 0xF7) {
+            pending.extend_from_slice(message);
+        } else {
+            // Any status byte other than real-time means the dump's 0xF7 was lost; drop it
+            // rather than appending later data to it
+            if message[0] < 0xF8 {
+                pending.clear();
+            }
+            return false;
+        }
+
+        if pending.last() != Some(&0xF7) {
+            return true;
+        }
+
+        let length = pending.len() as i32;
+        *self.last_sysex.lock().unwrap() = std::mem::take(&mut *pending);
+        drop(pending);
+
+        if let Some((handle, sysex_event)) = *self.sysex_event.lock().unwrap() {
+            let mut event_data = SysExEventData { handle, length };
+            if let Err(e) = sysex_event.post(&mut event_data) {
+                log!(Error, "Failed to post SysEx event to LabVIEW: {}", e);
+            }
+        }
+        true
+    }
+
+    /// Register the user event notified when a complete SysEx dump has been received
+    pub fn set_sysex_event(&self, handle: i32, sysex_event: LVUserEvent<SysExEventData>) {
+        *self.sysex_event.lock().unwrap() = Some((handle, sysex_event));
+    }
+
+    /// The most recently completed SysEx dump, including the 0xF0/0xF7 framing bytes
+    pub fn last_sysex(&self) -> Vec<u8> {
+        self.last_sysex.lock().unwrap().clone()
+    }
+
+    /// The raw bytes of the most recent message that was not MIDI 1.0
+    pub fn last_unsupported(&self) -> Vec<u8> {
+        self.last_unsupported.lock().unwrap().clone()
+    }
+
+    fn count(&self, message_type: u8) {
+        let slot = (message_type as usize).min(EVENT_COUNT_SLOTS - 1);
+        self.event_counts[slot].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Snapshot of the per-message-type counters
+    pub fn event_counts(&self) -> [u32; EVENT_COUNT_SLOTS] {
+        std::array::from_fn(|i| self.event_counts[i].load(Ordering::Relaxed))
+    }
+
+    pub fn reset_event_counts(&self) {
+        for counter in &self.event_counts {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Time since the listener was created (when its input connection was opened)
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+}
+
+impl Drop for EventListener {
+    /// Stop the coalesce thread however the last reference goes away (a handle destroyed,
+    /// a context shut down, a map cleared), so no background thread outlives its listener
+    fn drop(&mut self) {
+        let was_enabled = self.coalesce_enabled.swap(false, Ordering::SeqCst);
+        if let Some(coalesce_thread) = self.coalesce_thread.get_mut().unwrap().take() {
+            // The coalesce thread briefly holds a reference while flushing, so the last
+            // reference can be dropped on that thread, which must not join itself
+            if coalesce_thread.thread().id() != thread::current().id() {
+                // Wake it so it notices the listener is gone instead of finishing its wait
+                coalesce_thread.thread().unpark();
+                let _ = coalesce_thread.join();
+            }
+        }
+        if was_enabled {
+            self.flush_coalesced(true);
+        }
+    }
+}
+
+/// Direct callback-based MIDI event system
+/// This connects directly to midir's callback without polling.
+/// Returns the listener handle or a negative error code (see `LVInteropError::code`);
+/// a negative device index gives -2 (`DeviceNotFound`).
+#[no_mangle]
+pub extern "C" fn midi_connect_with_user_event(
+    device_index: c_int,
+    user_event_ref: u32,
+    filter_array: *const c_uchar,
+    array_size: c_int,
+) -> c_int {
+    let filter = match filter_from_raw(filter_array, array_size) {
+        Ok(filter) => filter,
+        Err(e) => return e.code(),
+    };
+
+    default_context().connect_with_user_event(device_index, user_event_ref, filter)
+}
+
+/// `midi_connect_with_user_event` on the first input device.
+/// Returns the listener handle, -8 (`NoDevices`) if no input device is present,
+/// or another negative error code (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_open_first_with_user_event(
+    user_event_ref: u32,
+    filter_array: *const c_uchar,
+    array_size: c_int,
+) -> c_int {
+    match MidiManager::new().list_input_devices() {
+        Ok(devices) if devices.is_empty() => {
+            LVInteropError::NoDevices("no MIDI input devices present".to_string()).code()
+        }
+        Ok(_) => midi_connect_with_user_event(0, user_event_ref, filter_array, array_size),
+        Err(e) => e.code(),
+    }
+}
+
+/// Restart a listener created by `midi_connect_with_user_event`: the input connection is closed
+/// (waiting for midir's thread to finish) and the same device is opened again by name. The
+/// handle, User Event and filter settings are kept, so nothing has to be re-registered.
+/// Returns 0 on success or a negative error code (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_restart_event_listening(handle: c_int) -> c_int {
+    default_context().restart_listener(handle)
+}
+
+/// Register a User Event that is posted (with `SysExEventData`) whenever a listener
+/// receives a complete SysEx dump
+#[no_mangle]
+pub extern "C" fn midi_set_sysex_user_event(handle: c_int, user_event_ref: u32) -> c_int {
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            listener.set_sysex_event(handle, LVUserEvent::from_raw(user_event_ref));
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Copy the last complete SysEx dump received by a listener.
+/// `message_length` is set to the dump size even if the buffer is too small (returns -1).
+#[no_mangle]
+pub extern "C" fn midi_get_last_sysex(
+    handle: c_int,
+    buffer: *mut c_uchar,
+    buffer_size: c_int,
+    message_length: *mut c_int,
+) -> c_int {
+    if buffer.is_null() || message_length.is_null() || buffer_size < 0 {
+        return -1;
+    }
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            let sysex = listener.last_sysex();
+            unsafe {
+                *message_length = sysex.len() as c_int;
+            }
+            if sysex.len() > buffer_size as usize {
+                return -1;
+            }
+
+            unsafe {
+                std::ptr::copy_nonoverlapping(sysex.as_ptr(), buffer, sysex.len());
+            }
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Identify the manufacturer of a SysEx message (starting with 0xF0). `out_id` receives the ID
+/// bytes as one number (0x41 for Roland; 0x2029 for the 3-byte ID 00 20 29 of Novation) and
+/// `out_id_length` its length in the message (1 or 3); the vendor name, or "Unknown" if it is
+/// not in the built-in table, is written to `name_buffer`.
+/// Returns 0 on success, -1 if the data is not SysEx with an ID or the buffer is too small, or
+/// another negative error code (see `LVInteropError::code`).
+#[no_mangle]
+pub extern "C" fn midi_sysex_manufacturer(
+    data: *const c_uchar,
+    data_length: c_int,
+    out_id: *mut c_int,
+    out_id_length: *mut c_int,
+    name_buffer: *mut c_char,
+    name_buffer_size: c_int,
+) -> c_int {
+    if data.is_null() || data_length <= 0 || out_id.is_null() || out_id_length.is_null() {
+        return -1;
+    }
+    if let Err(e) = check_max_length(data_length as usize) {
+        return e.code();
+    }
+
+    let data_slice = unsafe { std::slice::from_raw_parts(data, data_length as usize) };
+    let manufacturer = match sysex_manufacturer(data_slice) {
+        Some(manufacturer) => manufacturer,
+        None => return -1,
+    };
+    unsafe {
+        *out_id = manufacturer.value() as c_int;
+        *out_id_length = manufacturer.byte_count() as c_int;
+    }
+    write_c_string(manufacturer.name(), name_buffer, name_buffer_size)
+}
+
+/// Copy the raw bytes of the last message a listener posted as type 8 (UMP/Unsupported).
+/// `message_length` is set to the message size even if the buffer is too small (returns -1).
+#[no_mangle]
+pub extern "C" fn midi_get_last_unsupported(
+    handle: c_int,
+    buffer: *mut c_uchar,
+    buffer_size: c_int,
+    message_length: *mut c_int,
+) -> c_int {
+    if buffer.is_null() || message_length.is_null() || buffer_size < 0 {
+        return -1;
+    }
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            let message = listener.last_unsupported();
+            unsafe {
+                *message_length = message.len() as c_int;
+            }
+            if message.len() > buffer_size as usize {
+                return -1;
+            }
+
+            unsafe {
+                std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, message.len());
+            }
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Enable or disable coalescing of pitch bend and channel aftertouch on a listener.
+/// Only the latest value per channel within the coalesce window is posted; notes are never coalesced.
+#[no_mangle]
+pub extern "C" fn midi_enable_coalesce(handle: c_int, enabled: c_uchar) -> c_int {
+    // Disabling waits for the coalescing thread, so don't hold the listeners lock meanwhile
+    let listener = get_event_listeners().lock().unwrap().get(&handle).cloned();
+    match listener {
+        Some(listener) => {
+            listener.set_coalesce(enabled != 0);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Set the OS priority of a listener's input thread, from 0 (normal scheduling) to 99 (most
+/// urgent realtime level). midir owns the thread, so the priority takes effect when the next
+/// message arrives; if the OS refuses (on Linux realtime scheduling needs `CAP_SYS_NICE` or an
+/// `rtprio` limit) a warning is logged and the thread keeps its priority.
+///
+/// Realtime priority cuts scheduling jitter but is only safe because the callback never blocks
+/// for long; with coalescing, MPE or SysEx events enabled it also takes locks shared with
+/// LabVIEW's threads, which can briefly invert priorities.
+/// Returns 0, -1 for an invalid handle or priority, or -10 (`NotSupported`) on platforms
+/// without thread priorities.
+#[no_mangle]
+pub extern "C" fn midi_set_listener_priority(handle: c_int, priority: c_int) -> c_int {
+    if !THREAD_PRIORITY_SUPPORTED {
+        return LVInteropError::NotSupported("thread priorities".to_string()).code();
+    }
+    if !(0..=MAX_THREAD_PRIORITY).contains(&priority) {
+        return -1;
+    }
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            listener.set_priority(priority);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Enable or disable measuring, per posted event, the time from midir's callback to the
+/// User Event post returning. Enabling (or disabling) clears the collected statistics.
+#[no_mangle]
+pub extern "C" fn midi_enable_latency_stats(handle: c_int, enabled: c_uchar) -> c_int {
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            listener.set_latency_stats(enabled != 0);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Get the minimum, average and maximum callback-to-post latency of a listener in
+/// microseconds (all 0 until an event has been measured)
+#[no_mangle]
+pub extern "C" fn midi_get_latency_stats(
+    handle: c_int,
+    out_min: *mut f64,
+    out_avg: *mut f64,
+    out_max: *mut f64,
+) -> c_int {
+    if out_min.is_null() || out_avg.is_null() || out_max.is_null() {
+        return -1;
+    }
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            let (min, avg, max) = listener.latency_stats().min_avg_max();
+            unsafe {
+                *out_min = min;
+                *out_avg = avg;
+                *out_max = max;
+            }
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Set the coalesce window of a listener in milliseconds (default 5)
+#[no_mangle]
+pub extern "C" fn midi_set_coalesce_window(handle: c_int, window_ms: c_int) -> c_int {
+    if window_ms < 0 {
+        return -1;
+    }
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            listener.set_coalesce_window_ms(window_ms as u32);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Enable MPE on a listener with a lower zone of `lower_zone_channels` member channels
+/// (channel 1 is the master, channels 2..=lower_zone_channels + 1 carry one note each).
+/// Pass 0 to disable. Enriched per-note events go to the event set with `midi_set_mpe_user_event`.
+#[no_mangle]
+pub extern "C" fn midi_enable_mpe(handle: c_int, lower_zone_channels: c_int) -> c_int {
+    if !(0..=15).contains(&lower_zone_channels) {
+        return -1;
+    }
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            listener.set_mpe(lower_zone_channels as u8);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Enable chord detection on a listener: held notes are tracked per channel and a `ChordEvent`
+/// is posted to `user_event_ref` on every note-on/off (and All Notes Off), naming the chord
+/// when the held notes form a triad or seventh chord. This is heuristic; LabVIEW can always
+/// name the chord itself from `midi_get_held_notes`. Pass 0 to disable.
+#[no_mangle]
+pub extern "C" fn midi_enable_chord_detect(handle: c_int, user_event_ref: u32) -> c_int {
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            let chord_event = (user_event_ref != 0).then(|| LVUserEvent::from_raw(user_event_ref));
+            listener.set_chord_event(chord_event);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Remap the release velocity of note-offs (0x80) a listener receives through a 128-entry table
+/// (`curve[received velocity]` = posted velocity, each 0-127), e.g. to give a controller that
+/// always sends the same release velocity a shaped response. Note-ons are not affected; a note-on
+/// with velocity 0 has no release velocity. Pass null to restore the default identity mapping.
+#[no_mangle]
+pub extern "C" fn midi_set_release_velocity_curve(handle: c_int, curve: *const c_uchar) -> c_int {
+    let curve = if curve.is_null() {
+        None
+    } else {
+        let mut table = [0u8; 128];
+        table.copy_from_slice(unsafe { std::slice::from_raw_parts(curve, 128) });
+        if table.iter().any(|velocity| *velocity > 0x7F) {
+            return -1;
+        }
+        Some(table)
+    };
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            listener.set_release_curve(curve);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Enable combined expression events on a listener: the latest channel pressure and pitch
+/// bend of each channel are posted together as an `ExpressionEvent` to `user_event_ref`
+/// whenever either changes. The regular MIDI events are still posted. Pass 0 to disable.
+#[no_mangle]
+pub extern "C" fn midi_enable_expression_events(handle: c_int, user_event_ref: u32) -> c_int {
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            let expression_event = (user_event_ref != 0).then(|| LVUserEvent::from_raw(user_event_ref));
+            listener.set_expression_event(expression_event);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Enable MIDI Time Code decoding on a listener: each complete sequence of eight
+/// quarter-frame messages is posted as a `TimecodeEvent` to `user_event_ref`. A sequence
+/// interrupted by a missing or out-of-order piece is discarded. Pass 0 to disable.
+#[no_mangle]
+pub extern "C" fn midi_enable_mtc(handle: c_int, user_event_ref: u32) -> c_int {
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            let mtc_event = (user_event_ref != 0).then(|| LVUserEvent::from_raw(user_event_ref));
+            listener.set_mtc_event(mtc_event);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Copy the notes held on a channel (0-15) of a chord-detecting listener into `buffer`, in
+/// ascending order. Returns the number of notes held (the buffer gets as many as fit) or -1.
+#[no_mangle]
+pub extern "C" fn midi_get_held_notes(
+    handle: c_int,
+    channel: c_uchar,
+    buffer: *mut c_uchar,
+    buffer_size: c_int,
+) -> c_int {
+    if buffer.is_null() || buffer_size < 0 || channel > 15 {
+        return -1;
+    }
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            let notes = listener.held_notes(channel);
+            let copied = notes.len().min(buffer_size as usize);
+            unsafe {
+                std::ptr::copy_nonoverlapping(notes.as_ptr(), buffer, copied);
+            }
+            notes.len() as c_int
+        }
+        None => -1,
+    }
+}
+
+/// Most events `midi_enable_history` will keep per listener
+pub const MAX_HISTORY_SIZE: c_int = 4096;
+
+/// Keep the last `size` (1 to `MAX_HISTORY_SIZE`) events a listener posts, after filtering,
+/// for `midi_get_history`. Pass 0 to turn the history off and clear it.
+#[no_mangle]
+pub extern "C" fn midi_enable_history(handle: c_int, size: c_int) -> c_int {
+    if !(0..=MAX_HISTORY_SIZE).contains(&size) {
+        return -1;
+    }
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            listener.set_history_size(size as usize);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Copy up to `max_events` of a listener's most recent events into `events`, newest first.
+/// Returns the number copied (0 if the history is off) or -1.
+#[no_mangle]
+pub extern "C" fn midi_get_history(handle: c_int, events: *mut MidiEventData, max_events: c_int) -> c_int {
+    if events.is_null() || max_events < 0 {
+        return -1;
+    }
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            let history = listener.history();
+            let copied = history.len().min(max_events as usize);
+            unsafe {
+                std::ptr::copy_nonoverlapping(history.as_ptr(), events, copied);
+            }
+            copied as c_int
+        }
+        None => -1,
+    }
+}
+
+/// Register a User Event that receives `MpeNoteEvent`s from an MPE-enabled listener
+#[no_mangle]
+pub extern "C" fn midi_set_mpe_user_event(handle: c_int, user_event_ref: u32) -> c_int {
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            listener.set_mpe_event(LVUserEvent::from_raw(user_event_ref));
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Replace the status-byte filter of a handle (polling or user event listener)
+#[no_mangle]
+pub extern "C" fn midi_set_filter(
+    handle: c_int,
+    filter_array: *const c_uchar,
+    array_size: c_int,
+) -> c_int {
+    let filter = match filter_from_raw(filter_array, array_size) {
+        Ok(filter) => filter,
+        Err(e) => return e.code(),
+    };
+
+    let managers = get_midi_managers().lock().unwrap();
+    match managers.get(&handle) {
         Some(manager) => {
-            match manager.receive_message() {
-                Some(msg) => {
-                    if msg.len() > buffer_size as usize {
-                        return -1;
-                    }
-                    
-                    unsafe {
-                        std::ptr::copy_nonoverlapping(
-                            msg.as_ptr(),
-                            buffer,
-                            msg.len(),
-                        );
-                        *message_length = msg.len() as c_int;
-                    }
-                    1
-                }
-                None => 0,
+            manager.filter().set_status_bytes(filter);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Set how the status-byte filter of a handle is applied:
+/// 0 = whitelist (only listed bytes pass, empty passes all), 1 = blacklist (listed bytes dropped)
+#[no_mangle]
+pub extern "C" fn midi_set_filter_mode(handle: c_int, mode: c_int) -> c_int {
+    let mode = match mode {
+        0 => FilterMode::Whitelist,
+        1 => FilterMode::Blacklist,
+        _ => return -1,
+    };
+
+    let managers = get_midi_managers().lock().unwrap();
+    match managers.get(&handle) {
+        Some(manager) => {
+            manager.filter().set_mode(mode);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Drop incoming Note Ons with a velocity below `min_velocity` (0-127, 0 = pass all), e.g. to
+/// ignore ghost notes on a pad. Note Offs always pass. Applies to both polled input and user events.
+#[no_mangle]
+pub extern "C" fn midi_set_velocity_threshold(handle: c_int, min_velocity: c_int) -> c_int {
+    if !(0..=127).contains(&min_velocity) {
+        return -1;
+    }
+
+    let managers = get_midi_managers().lock().unwrap();
+    match managers.get(&handle) {
+        Some(manager) => {
+            manager.filter().set_min_velocity(min_velocity as u8);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Number of counts written by `midi_get_event_counts`
+const LEGACY_EVENT_COUNT_SLOTS: c_int = 8;
+
+/// Get the per-message-type event counts of a listener.
+/// `counts` must point to an array of 8 ints (message types 0-7); the count of unknown and
+/// unsupported messages is only available from `midi_get_event_counts_ex`.
+#[no_mangle]
+pub extern "C" fn midi_get_event_counts(handle: c_int, counts: *mut c_int) -> c_int {
+    match midi_get_event_counts_ex(handle, counts, LEGACY_EVENT_COUNT_SLOTS) {
+        written if written >= 0 => 0,
+        error => error,
+    }
+}
+
+/// Get the per-message-type event counts of a listener into an array of `counts_length`
+/// ints, writing at most that many. Index `i` counts `message_type` `i` (see
+/// `MidiEventData`) and index 8 counts unknown and unsupported messages.
+/// Returns the number of counts written, or -1.
+#[no_mangle]
+pub extern "C" fn midi_get_event_counts_ex(handle: c_int, counts: *mut c_int, counts_length: c_int) -> c_int {
+    if counts.is_null() || counts_length < 0 {
+        return -1;
+    }
+
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            let snapshot = listener.event_counts();
+            let written = snapshot.len().min(counts_length as usize);
+            let counts_slice = unsafe {
+                std::slice::from_raw_parts_mut(counts, written)
+            };
+            for (out, count) in counts_slice.iter_mut().zip(snapshot) {
+                *out = count as c_int;
             }
+            written as c_int
+        }
+        None => -1,
+    }
+}
+
+/// Reset the per-message-type event counts of a listener
+#[no_mangle]
+pub extern "C" fn midi_reset_event_counts(handle: c_int) -> c_int {
+    let listeners = get_event_listeners().lock().unwrap();
+    match listeners.get(&handle) {
+        Some(listener) => {
+            listener.reset_event_counts();
+            0
         }
         None => -1,
     }
 }
 
-/// Disconnect and cleanup a MIDI connection
+/// One message of a captured MIDI session, for `midi_replay_log`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MidiLogEntry {
+    /// Delay since the previous message in microseconds
+    pub delay_us: u32,
+    pub len: i32,
+    pub bytes: *const c_uchar,
+}
+
+/// Feed recorded messages through a listener on a background thread, honoring their delays
+fn replay_log(listener: Arc<EventListener>, entries: Vec<(Duration, Vec<u8>)>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let start = Instant::now();
+        let mut due = Duration::ZERO;
+        for (delay, message) in entries {
+            due += delay;
+            if let Some(remaining) = due.checked_sub(start.elapsed()) {
+                thread::sleep(remaining);
+            }
+            listener.process_replayed(&message);
+        }
+    })
+}
+
+/// Replay a captured session through a handle's event listener as if it arrived from the device.
+/// The entries are copied before returning; the delays are honored on a background thread.
+#[no_mangle]
+pub extern "C" fn midi_replay_log(handle: c_int, entries: *const MidiLogEntry, count: c_int) -> c_int {
+    if entries.is_null() || count <= 0 {
+        return -1;
+    }
+
+    let listener = match get_event_listeners().lock().unwrap().get(&handle) {
+        Some(listener) => Arc::clone(listener),
+        None => return -1,
+    };
+
+    let entries_slice = unsafe {
+        std::slice::from_raw_parts(entries, count as usize)
+    };
+    let mut messages = Vec::with_capacity(entries_slice.len());
+    for entry in entries_slice {
+        if entry.bytes.is_null() || entry.len <= 0 {
+            return -1;
+        }
+        let bytes = unsafe {
+            std::slice::from_raw_parts(entry.bytes, entry.len as usize)
+        };
+        messages.push((Duration::from_micros(entry.delay_us as u64), bytes.to_vec()));
+    }
+
+    replay_log(listener, messages);
+    0
+}
+
+/// Get how long a handle's event listener has been running, in milliseconds.
+/// Returns 0 for a manager without a listener and -1 for an unknown handle.
 #[no_mangle]
-pub extern "C" fn midi_disconnect(handle: c_int) -> c_int {
-    let mut managers = get_midi_managers().lock().unwrap();
-    match managers.remove(&handle) {
-        Some(_) => 0,
-        None => -1,
+pub extern "C" fn midi_get_listener_uptime_ms(handle: c_int) -> i64 {
+    if let Some(listener) = get_event_listeners().lock().unwrap().get(&handle) {
+        return listener.uptime().as_millis() as i64;
+    }
+    if get_midi_managers().lock().unwrap().contains_key(&handle) {
+        0
+    } else {
+        -1
     }
 }
 
-// ========== HELPER FUNCTIONS ==========
+// ========== PLAYBACK ==========
 
-/// Create a Note On message
-#[no_mangle]
-pub extern "C" fn midi_create_note_on(
-    channel: c_uchar,
-    note: c_uchar,
-    velocity: c_uchar,
-    buffer: *mut c_uchar,
-) -> c_int {
-    if buffer.is_null() {
-        return -1;
+/// Longest single sleep in the playback loop, so a stop request is noticed promptly
+const PLAYBACK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Send the chase messages, then stream the timed events until done or stopped.
+/// Every played message is posted to `user_event` if one is given.
+fn run_playback(
+    mut manager: MidiManager,
+    chase: Vec<Vec<u8>>,
+    events: Vec<(f64, Vec<u8>)>,
+    user_event: Option<LVUserEvent<MidiEventData>>,
+    stop: Arc<AtomicBool>,
+) {
+    for message in &chase {
+        let _ = manager.send_message(message);
     }
 
-    let message = MidiManager::note_on(channel, note, velocity);
-    unsafe {
-        std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, 3);
+    let start = Instant::now();
+    let mut due_ms = 0.0;
+    'events: for (delay_ms, message) in events {
+        due_ms += delay_ms;
+        let due = Duration::from_secs_f64(due_ms / 1000.0);
+
+        // Sleep against the start time so rounding does not accumulate
+        while let Some(remaining) = due.checked_sub(start.elapsed()).filter(|remaining| !remaining.is_zero()) {
+            if stop.load(Ordering::Relaxed) {
+                break 'events;
+            }
+            thread::sleep(remaining.min(PLAYBACK_POLL_INTERVAL));
+        }
+        if stop.load(Ordering::Relaxed) || manager.send_message(&message).is_err() {
+            break;
+        }
+
+        if let Some(user_event) = user_event {
+            let mut event_data = MidiEventData::from_message(&message);
+            if let Err(e) = user_event.post(&mut event_data) {
+                log!(Error, "Failed to post playback event to LabVIEW: {}", e);
+            }
+        }
+    }
+
+    // Release anything still sounding when playback is cut short
+    if stop.load(Ordering::Relaxed) {
+        for channel in 0..16 {
+            let _ = manager.send_message(&MidiManager::control_change(channel, 123, 0));
+        }
     }
-    3
 }
 
-/// Create a Note Off message
+/// Play a loaded MIDI file to an output device starting at `start_tick`.
+/// Controller, program, pitch bend and channel pressure state in effect at `start_tick` is
+/// sent first (a "chase") so the instrument sounds right; notes already sounding are not
+/// restarted. Played messages are posted as `MidiEventData` to `user_event_ref` (0 = none).
+/// The output may already be open on another handle; playback does not claim the port.
+/// Returns a playback handle for `midi_file_stop_playback`, or -1 on error.
 #[no_mangle]
-pub extern "C" fn midi_create_note_off(
-    channel: c_uchar,
-    note: c_uchar,
-    velocity: c_uchar,
-    buffer: *mut c_uchar,
+pub extern "C" fn midi_file_play_from(
+    file_handle: c_int,
+    out_device: c_int,
+    start_tick: u32,
+    user_event_ref: u32,
 ) -> c_int {
-    if buffer.is_null() {
+    if out_device < 0 {
         return -1;
     }
 
-    let message = MidiManager::note_off(channel, note, velocity);
-    unsafe {
-        std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, 3);
-    }
-    3
-}
+    let (chase, events) = {
+        let files = match get_midi_file(file_handle) {
+            Some(guard) => guard,
+            None => return -1,
+        };
+        match files.get(&file_handle) {
+            Some(midi_file) => (
+                midi_file.chase_messages(start_tick),
+                midi_file.timed_events_from(start_tick).collect::<Vec<_>>(),
+            ),
+            None => return -1,
+        }
+    };
 
-/// Create a Control Change message
-#[no_mangle]
-pub extern "C" fn midi_create_control_change(
-    channel: c_uchar,
-    controller: c_uchar,
-    value: c_uchar,
-    buffer: *mut c_uchar,
-) -> c_int {
-    if buffer.is_null() {
+    let mut manager = MidiManager::unclaimed();
+    if manager.connect_output(out_device as usize).is_err() {
         return -1;
     }
 
-    let message = MidiManager::control_change(channel, controller, value);
-    unsafe {
-        std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, 3);
+    let user_event = match user_event_ref {
+        0 => None,
+        reference => Some(LVUserEvent::<MidiEventData>::from_raw(reference)),
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // The player is registered before the lock is released, so the thread cannot finish and
+    // look for its entry first
+    let mut players = get_players().lock().unwrap();
+    let handle = get_next_handle();
+    let thread = {
+        let stop = stop.clone();
+        thread::spawn(move || {
+            run_playback(manager, chase, events, user_event, stop);
+            reap_player(handle);
+        })
+    };
+    players.insert(handle, Player { stop, thread });
+    handle
+}
+
+/// Forget a player whose playback ended on its own and release its handle. Does nothing if
+/// `midi_file_stop_playback` or shutdown already took the player.
+fn reap_player(handle: c_int) -> bool {
+    let finished = get_players().lock().unwrap().remove(&handle);
+    if finished.is_some() {
+        release_handle(handle);
     }
-    3
+    finished.is_some()
 }
 
-// ========== MIDI MESSAGE PARSING ==========
+/// Check whether playback is still running. Returns 1 if playing, or 0 once it has finished
+/// or been stopped (a finished player releases its handle, so the handle is not kept).
+#[no_mangle]
+pub extern "C" fn midi_file_is_playing(handle: c_int) -> c_int {
+    get_players().lock().unwrap().contains_key(&handle) as c_int
+}
 
-/// Parse a MIDI message into its components
+/// Stop playback (sending All Notes Off) and release the handle. Returns 0, or -1 if the
+/// handle is not playing, including playback that already ended and released it.
 #[no_mangle]
-pub extern "C" fn midi_parse_message(
-    message: *const c_uchar,
-    message_length: c_int,
-    message_type: *mut c_uchar,
-    channel: *mut c_uchar,
-    note_or_controller: *mut c_uchar,
-    velocity_or_value: *mut c_uchar,
-) -> c_int {
-    if message.is_null() || message_type.is_null() || channel.is_null() || 
-       note_or_controller.is_null() || velocity_or_value.is_null() || message_length < 1 {
-        return -1;
+pub extern "C" fn midi_file_stop_playback(handle: c_int) -> c_int {
+    let player = get_players().lock().unwrap().remove(&handle);
+    match player {
+        Some(player) => {
+            player.stop();
+            release_handle(handle);
+            0
+        }
+        None => -1,
     }
+}
 
-    let message_slice = unsafe {
-        std::slice::from_raw_parts(message, message_length as usize)
-    };
+// ========== EVENT LOG ==========
 
-    if message_slice.is_empty() {
-        return -1;
+/// Read a C path argument, None if null or not UTF-8
+fn path_from_ptr<'a>(path: *const c_char) -> Option<&'a Path> {
+    if path.is_null() {
+        return None;
     }
+    unsafe { CStr::from_ptr(path) }.to_str().ok().map(Path::new)
+}
 
-    let status_byte = message_slice[0];
-    let midi_channel = status_byte & 0x0F;
-    let msg_type = status_byte & 0xF0;
-    
-    unsafe {
-        *channel = midi_channel;
-        
-        match msg_type {
-            0x80 => {
-                *message_type = 0;
-                if message_length >= 3 {
-                    *note_or_controller = message_slice[1];
-                    *velocity_or_value = message_slice[2];
-                } else {
-                    *note_or_controller = 0;
-                    *velocity_or_value = 0;
-                }
-            },
-            0x90 => {
-                if message_length >= 3 {
-                    *note_or_controller = message_slice[1];
-                    *velocity_or_value = message_slice[2];
-                    
-                    if message_slice[2] == 0 {
-                        *message_type = 0;
-                    } else {
-                        *message_type = 1;
-                    }
-                } else {
-                    *message_type = 1;
-                    *note_or_controller = 0;
-                    *velocity_or_value = 0;
-                }
-            },
-            0xB0 => {
-                *message_type = 2;
-                if message_length >= 3 {
-                    *note_or_controller = message_slice[1];
-                    *velocity_or_value = message_slice[2];
-                } else {
-                    *note_or_controller = 0;
-                    *velocity_or_value = 0;
-                }
-            },
-            0xC0 => {
-                *message_type = 3;
-                if message_length >= 2 {
-                    *note_or_controller = message_slice[1];
-                    *velocity_or_value = 0;
-                } else {
-                    *note_or_controller = 0;
-                    *velocity_or_value = 0;
-                }
-            },
-            0xE0 => {
-                *message_type = 4;
-                if message_length >= 3 {
-                    let lsb = message_slice[1] as u16;
-                    let msb = message_slice[2] as u16;
-                    let bend_value = (msb << 7) | lsb;
-                    
-                    *note_or_controller = (bend_value & 0xFF) as u8;
-                    *velocity_or_value = ((bend_value >> 8) & 0xFF) as u8;
-                } else {
-                    *note_or_controller = 64;
-                    *velocity_or_value = 64;
-                }
-            },
-            _ => {
-                *message_type = 255;
-                *note_or_controller = 0;
-                *velocity_or_value = 0;
-            }
+/// Log every message a listener receives (before filtering) to a binary event log at
+/// `path`, appending if the file is already a log. Writing happens on a background thread.
+/// Returns 0 on success or -1 for an unknown handle or a file that cannot be opened.
+#[no_mangle]
+pub extern "C" fn midi_start_logging(handle: c_int, path: *const c_char) -> c_int {
+    let path = match path_from_ptr(path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    // Clone the listener out so a replaced log is finished without holding the table lock
+    let listener = match get_event_listeners().lock().unwrap().get(&handle) {
+        Some(listener) => listener.clone(),
+        None => return -1,
+    };
+    match listener.start_logging(path) {
+        Ok(()) => 0,
+        Err(e) => {
+            log!(Error, "Failed to start event log {}: {}", path.display(), e);
+            -1
         }
     }
-    
-    0
 }
 
-// ========== LABVIEW USER EVENTS - CALLBACK SYSTEM ==========
+/// Stop logging on a listener once everything received so far has been written.
+/// Returns 0 on success or -1 for an unknown handle, no log running, or a write error.
+#[no_mangle]
+pub extern "C" fn midi_stop_logging(handle: c_int) -> c_int {
+    let listener = match get_event_listeners().lock().unwrap().get(&handle) {
+        Some(listener) => listener.clone(),
+        None => return -1,
+    };
+    match listener.stop_logging() {
+        Some(Ok(())) => 0,
+        Some(Err(e)) => {
+            log!(Error, "Failed to write event log: {}", e);
+            -1
+        }
+        None => -1,
+    }
+}
 
-/// MIDI data structure for LabVIEW User Events
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
-pub struct MidiEventData {
-    pub message_type: i32,
-    pub channel: i32,
-    pub note_or_controller: i32,
-    pub velocity_or_value: i32,
-    pub raw_status: i32,
+/// Replay an event log file written by `midi_start_logging` through a handle's event
+/// listener, like `midi_replay_log`. Each appended session starts again from its first
+/// message. Returns 0 once the log is read, or -1 for an unknown handle or unreadable log.
+#[no_mangle]
+pub extern "C" fn midi_replay_log_file(handle: c_int, path: *const c_char) -> c_int {
+    let path = match path_from_ptr(path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    let listener = match get_event_listeners().lock().unwrap().get(&handle) {
+        Some(listener) => Arc::clone(listener),
+        None => return -1,
+    };
+
+    let records = match open_event_log(path).and_then(|log| log.collect::<std::io::Result<Vec<_>>>()) {
+        Ok(records) => records,
+        Err(e) => {
+            log!(Error, "Failed to read event log {}: {}", path.display(), e);
+            return -1;
+        }
+    };
+    let mut previous_us = 0;
+    let messages = records
+        .into_iter()
+        .map(|(timestamp_us, message)| {
+            let delay = Duration::from_micros(timestamp_us.saturating_sub(previous_us));
+            previous_us = timestamp_us;
+            (delay, message)
+        })
+        .collect();
+
+    replay_log(listener, messages);
+    0
 }
 
-/// Direct callback-based MIDI event system
-/// This connects directly to midir's callback without polling
+// ========== RECORDING ==========
+
+/// Start recording a MIDI input device into a new MIDI file.
+/// Messages are timestamped on arrival and converted to ticks at the given resolution and
+/// tempo (microseconds per quarter note). The input may already be open on another handle;
+/// recording does not claim the port. Returns a recording handle or a negative error code
+/// (see `LVInteropError::code`).
 #[no_mangle]
-pub extern "C" fn midi_connect_with_user_event(
+pub extern "C" fn midi_start_recording(
     device_index: c_int,
-    user_event_ref: u32,
-    filter_array: *const c_uchar,
-    array_size: c_int,
+    ticks_per_quarter: c_int,
+    tempo_us_per_quarter: c_int,
 ) -> c_int {
-    use std::sync::Arc;
-    
-    // Create filter vector
-    let filter = if array_size > 0 && !filter_array.is_null() {
-        let filter_slice = unsafe {
-            std::slice::from_raw_parts(filter_array, array_size as usize)
-        };
-        Arc::new(filter_slice.to_vec())
-    } else {
-        Arc::new(Vec::new())
-    };
-    
-    // Create User Event
-    let user_event = Arc::new(LVUserEvent::<MidiEventData>::from_raw(user_event_ref));
-    
-    // Create MIDI manager
-    let mut manager = MidiManager::new();
-    
-    // Create the callback that will be called directly by midir
+    if device_index < 0 || !(1..=0x7FFF).contains(&ticks_per_quarter)
+        || !(1..=0xFF_FFFF).contains(&tempo_us_per_quarter) {
+        return -1;
+    }
+
+    let messages = Arc::new(Mutex::new(Vec::new()));
     let callback = {
-        let filter = filter.clone();
-        let user_event = user_event.clone();
-        
-        move |message: Vec<u8>| {
-            if !message.is_empty() {
-                let status_byte = message[0];
-                
-                // Apply filter if specified
-                if filter.is_empty() || filter.contains(&status_byte) {
-                    // Parse the MIDI message
-                    let channel = status_byte & 0x0F;
-                    let msg_type = status_byte & 0xF0;
-                    let data1 = if message.len() > 1 { message[1] } else { 0 };
-                    let data2 = if message.len() > 2 { message[2] } else { 0 };
-                    
-                    let message_type = match msg_type {
-                        0x80 => 0, // Note Off
-                        0x90 => if data2 == 0 { 0 } else { 1 }, // Note On
-                        0xB0 => 2, // Control Change
-                        0xC0 => 3, // Program Change
-                        0xE0 => 4, // Pitch Bend
-                        _ => 255,  // Unknown
-                    };
-                    
-                    // Create event data
-                    let mut event_data = MidiEventData {
-                        message_type: message_type as i32,
-                        channel: channel as i32,
-                        note_or_controller: data1 as i32,
-                        velocity_or_value: data2 as i32,
-                        raw_status: status_byte as i32,
-                    };
-                    
-                    // Post the event to LabVIEW directly from midir's callback
-                    if let Err(e) = user_event.post(&mut event_data) {
-                        eprintln!("Failed to post MIDI event to LabVIEW: {}", e);
-                    }
-                }
-            }
-        }
+        let messages = messages.clone();
+        move |message: &[u8], timestamp: u64| messages.lock().unwrap().push((timestamp, message.to_vec()))
     };
-    
-    // Connect with the callback
+
+    let mut manager = MidiManager::unclaimed();
     match manager.connect_input_with_callback(device_index as usize, callback) {
         Ok(_) => {
-            // Store the manager to keep the connection alive
             let handle = get_next_handle();
             let mut managers = get_midi_managers().lock().unwrap();
             managers.insert(handle, manager);
+            get_recordings().lock().unwrap().insert(handle, Recording {
+                messages,
+                ticks_per_quarter: ticks_per_quarter as u16,
+                tempo_us_per_quarter: tempo_us_per_quarter as u32,
+            });
             handle
         }
+        Err(e) => e.code(),
+    }
+}
+
+/// Stop a recording and register the result as a MIDI file.
+/// Returns the MIDI file handle or -1 on error.
+#[no_mangle]
+pub extern "C" fn midi_stop_recording(handle: c_int) -> c_int {
+    let recording = match get_recordings().lock().unwrap().remove(&handle) {
+        Some(recording) => recording,
+        None => return -1,
+    };
+
+    // Dropping the manager closes the input connection
+    get_midi_managers().lock().unwrap().remove(&handle);
+    release_handle(handle);
+
+    let messages = recording.messages.lock().unwrap();
+    match MidiFile::from_recording(&messages, recording.ticks_per_quarter, recording.tempo_us_per_quarter) {
+        Ok(midi_file) => register_midi_file(midi_file),
         Err(_) => -1,
     }
 }
 
 // ========== UTILITY FUNCTIONS ==========
 
+/// Route library diagnostics (connections, failed event posts) to a callback instead of stderr.
+/// The callback receives a level (0 = error, 1 = warning, 2 = info) and a NUL-terminated message
+/// valid only during the call, and may be invoked from MIDI input threads. Pass null to restore stderr.
+#[no_mangle]
+pub extern "C" fn midi_set_log_callback(callback: Option<LogCallback>) -> c_int {
+    set_log_callback(callback);
+    0
+}
+
 /// Convert MIDI note number to note name
 #[no_mangle]
 pub extern "C" fn midi_note_to_name(
@@ -561,7 +3768,166 @@ pub extern "C" fn midi_note_to_name(
             name_bytes.len(),
         );
     }
-    0
+    0
+}
+
+/// Convert MIDI note number to note name, spelling accidentals as flats if `use_flats` is non-zero
+#[no_mangle]
+pub extern "C" fn midi_note_to_name_ex(
+    note: c_uchar,
+    use_flats: c_uchar,
+    buffer: *mut c_char,
+    buffer_size: c_int,
+) -> c_int {
+    if buffer.is_null() || buffer_size < 4 {
+        return -1;
+    }
+
+    if note > 127 {
+        return -1;
+    }
+
+    write_c_string(&get_note_name_ex(note, use_flats != 0), buffer, buffer_size)
+}
+
+/// Convert a MIDI note number to its name like `midi_note_to_name`, writing straight into
+/// the caller's buffer without any heap allocation (for displays that update many names at
+/// a high rate). A buffer of 5 bytes fits every name.
+#[no_mangle]
+pub extern "C" fn midi_note_to_name_into(
+    note: c_uchar,
+    buffer: *mut c_char,
+    buffer_size: c_int,
+) -> c_int {
+    if buffer.is_null() || buffer_size <= 0 || note > 127 {
+        return -1;
+    }
+
+    let (name, len) = note_name_bytes(note, false);
+    if len + 1 > buffer_size as usize {
+        return -1;
+    }
+
+    unsafe {
+        std::ptr::copy_nonoverlapping(name.as_ptr() as *const c_char, buffer, len);
+        *buffer.add(len) = 0;
+    }
+    0
+}
+
+/// The reference pitch to use: `a4_hz`, or 440 Hz if it is 0 (or otherwise not a usable pitch)
+fn reference_pitch(a4_hz: f64) -> f64 {
+    if a4_hz.is_finite() && a4_hz > 0.0 { a4_hz } else { DEFAULT_A4_HZ }
+}
+
+/// Frequency of a MIDI note (0-127) in Hz, in equal temperament with A4 (note 69) tuned to
+/// `a4_hz` (pass 0 for 440 Hz). Returns -1.0 for a note above 127.
+#[no_mangle]
+pub extern "C" fn midi_note_to_frequency(note: c_uchar, a4_hz: f64) -> f64 {
+    if note > 127 {
+        return -1.0;
+    }
+    note_to_frequency(note, reference_pitch(a4_hz))
+}
+
+/// Find the MIDI note nearest to a frequency, for pitch-detection displays, and how far the
+/// frequency is from it in cents (-50 to +50 within the note range). `a4_hz` is as for
+/// `midi_note_to_frequency`. Returns 0, or -1 for a null pointer or a frequency that is not positive.
+#[no_mangle]
+pub extern "C" fn midi_frequency_to_nearest_note(
+    hz: f64,
+    a4_hz: f64,
+    note: *mut c_uchar,
+    cents: *mut f64,
+) -> c_int {
+    if note.is_null() || cents.is_null() || !(hz.is_finite() && hz > 0.0) {
+        return -1;
+    }
+
+    let (nearest, offset) = frequency_to_nearest_note(hz, reference_pitch(a4_hz));
+    unsafe {
+        *note = nearest;
+        *cents = offset;
+    }
+    0
+}
+
+/// Convert a note on the General MIDI percussion channel (channel 10) to its drum name,
+/// e.g. 36 -> "Bass Drum 1". Notes outside 35-81 give "Unknown Percussion".
+#[no_mangle]
+pub extern "C" fn midi_drum_note_to_name(
+    note: c_uchar,
+    buffer: *mut c_char,
+    buffer_size: c_int,
+) -> c_int {
+    if buffer.is_null() || buffer_size <= 0 {
+        return -1;
+    }
+
+    write_c_string(get_drum_name(note), buffer, buffer_size)
+}
+
+/// Convert a program number (0-127) to its General MIDI instrument name,
+/// e.g. 0 -> "Acoustic Grand Piano"
+#[no_mangle]
+pub extern "C" fn midi_program_to_name(
+    program: c_uchar,
+    buffer: *mut c_char,
+    buffer_size: c_int,
+) -> c_int {
+    if buffer.is_null() || buffer_size <= 0 {
+        return -1;
+    }
+
+    if program > 127 {
+        return -1;
+    }
+
+    write_c_string(get_gm_program_name(program), buffer, buffer_size)
+}
+
+/// Look up a controller number by its standard name (case-insensitive), e.g. "Volume" -> 7.
+/// Returns -1 if the name is not a defined controller.
+#[no_mangle]
+pub extern "C" fn midi_control_name_to_number(name: *const c_char, out: *mut c_uchar) -> c_int {
+    if name.is_null() || out.is_null() {
+        return -1;
+    }
+
+    let name = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(name) => name,
+        Err(_) => return -1,
+    };
+    match get_control_number(name) {
+        Some(number) => {
+            unsafe { *out = number };
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Describe a raw MIDI message as a single human-readable string,
+/// e.g. "Note On  Ch:1  C4  Vel:100"
+#[no_mangle]
+pub extern "C" fn midi_describe_message(
+    message: *const c_uchar,
+    message_length: c_int,
+    buffer: *mut c_char,
+    buffer_size: c_int,
+) -> c_int {
+    if message.is_null() || buffer.is_null() || message_length <= 0 || buffer_size <= 0 {
+        return -1;
+    }
+    if let Err(e) = check_max_length(message_length as usize) {
+        return e.code();
+    }
+
+    let message_slice = unsafe {
+        std::slice::from_raw_parts(message, message_length as usize)
+    };
+
+    write_c_string(&describe_message(message_slice), buffer, buffer_size)
 }
 
 /// Get message type name for debugging
@@ -581,6 +3947,10 @@ pub extern "C" fn midi_get_message_type_name(
         2 => "Control Change",
         3 => "Program Change", 
         4 => "Pitch Bend",
+        5 => "Channel Aftertouch",
+        6 => "Poly Aftertouch",
+        7 => "Channel Mode",
+        8 => "UMP/Unsupported",
         255 => "Unknown",
         _ => "Invalid",
     };
@@ -605,6 +3975,73 @@ pub extern "C" fn midi_get_message_type_name(
     0
 }
 
+/// Loopback self-test: sends a Note On through a virtual port and checks it is received.
+/// Returns 0 on success, -1..-6 for the failing stage (init, virtual port, connect, send,
+/// no response, wrong message) or -100 where virtual ports are not supported (Windows).
+#[no_mangle]
+pub extern "C" fn midi_self_test() -> c_int {
+    match MidiManager::self_test() {
+        Ok(_) => 0,
+        Err(stage) => stage as c_int,
+    }
+}
+
+/// Version of this library, with the commit it was built from appended (e.g. "0.1.0+3f2a9c1")
+/// when the build set `TOMMIDILLAN_BUILD_HASH`
+fn library_version() -> String {
+    match option_env!("TOMMIDILLAN_BUILD_HASH") {
+        Some(hash) if !hash.is_empty() => format!("{}+{}", env!("CARGO_PKG_VERSION"), hash),
+        _ => env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+/// Get the library version as text, e.g. "0.1.0" or "0.1.0+3f2a9c1" with the build's commit hash.
+/// Returns 0 on success or -1 if the buffer is invalid or too small.
+#[no_mangle]
+pub extern "C" fn midi_library_version(buffer: *mut c_char, buffer_size: c_int) -> c_int {
+    write_c_string(&library_version(), buffer, buffer_size)
+}
+
+/// Get the library version as numbers, for checking that a DLL is at least a given version
+#[no_mangle]
+pub extern "C" fn midi_library_version_number(
+    out_major: *mut c_int,
+    out_minor: *mut c_int,
+    out_patch: *mut c_int,
+) -> c_int {
+    if out_major.is_null() || out_minor.is_null() || out_patch.is_null() {
+        return -1;
+    }
+
+    let part = |value: &str| value.parse().unwrap_or(0);
+    unsafe {
+        *out_major = part(env!("CARGO_PKG_VERSION_MAJOR"));
+        *out_minor = part(env!("CARGO_PKG_VERSION_MINOR"));
+        *out_patch = part(env!("CARGO_PKG_VERSION_PATCH"));
+    }
+    0
+}
+
+/// Get the version of the LabVIEW runtime the library is bound to, from the runtime module's
+/// version resource on Windows (e.g. "21.0.1") or its path elsewhere (e.g. "23.0" for
+/// liblvrt.so.23.0). Writes "unknown" if no runtime is loaded or its version cannot be
+/// determined. Returns 0, or -1 if the buffer is missing or too small.
+#[no_mangle]
+pub extern "C" fn lv_runtime_version(buffer: *mut c_char, buffer_size: c_int) -> c_int {
+    let version = runtime_version().unwrap_or_else(|| "unknown".to_string());
+    write_c_string(&version, buffer, buffer_size)
+}
+
+/// Retry binding to the LabVIEW runtime, e.g. when the first use happened before LabVIEW was
+/// fully initialized and user events keep failing. Returns 0 on success or a negative error code.
+#[no_mangle]
+pub extern "C" fn lv_reload_runtime() -> c_int {
+    match reload_sync_api() {
+        Ok(_) => 0,
+        Err(e) => e.code(),
+    }
+}
+
 // ========== STATUS CODES ==========
 
 /// Return the LabVIEW status code enum values for use in LabVIEW
@@ -623,9 +4060,7 @@ pub extern "C" fn lv_status_error() -> c_int {
 /// Test function: Generate a test MIDI event
 #[no_mangle]
 pub extern "C" fn test_generate_midi_event(user_event_ref: u32) -> c_int {
-    let user_event: LVUserEvent<MidiEventData> = LVUserEvent::from_raw(user_event_ref);
-    
-    let mut test_event = MidiEventData {
+    let test_event = MidiEventData {
         message_type: 1,
         channel: 0,
         note_or_controller: 60,
@@ -633,10 +4068,41 @@ pub extern "C" fn test_generate_midi_event(user_event_ref: u32) -> c_int {
         raw_status: 0x90,
     };
     
-    match user_event.post(&mut test_event) {
-        Ok(_) => LVStatusCode::SUCCESS as c_int,
-        Err(_) => LVStatusCode::ARG_ERROR as c_int,
-    }
+    post_user_event(user_event_ref, test_event) as c_int
+}
+
+/// Test function: Post an event with exactly the given fields (no validation), for checking
+/// how each message type is displayed without hardware
+#[no_mangle]
+pub extern "C" fn test_post_custom_event(
+    user_event_ref: u32,
+    message_type: c_int,
+    channel: c_int,
+    note_or_controller: c_int,
+    velocity_or_value: c_int,
+    raw_status: c_int,
+) -> c_int {
+    let test_event = MidiEventData {
+        message_type,
+        channel,
+        note_or_controller,
+        velocity_or_value,
+        raw_status,
+    };
+
+    post_user_event(user_event_ref, test_event) as c_int
+}
+
+/// Test function: Generate a device change event reporting the current input device count
+#[no_mangle]
+pub extern "C" fn test_generate_device_change_event(user_event_ref: u32) -> c_int {
+    let test_event = DeviceChangeEvent {
+        direction: 0,
+        change_type: 1,
+        device_count: midi_get_input_device_count(),
+    };
+
+    post_user_event(user_event_ref, test_event) as c_int
 }
 
 /// Test function: Generate multiple test events
@@ -669,6 +4135,81 @@ pub extern "C" fn test_generate_chord_events(user_event_ref: u32) -> c_int {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_cc_ramp_values() {
+        assert_eq!(cc_ramp_values(0, 127, 4), vec![32, 64, 95, 127]);
+        assert_eq!(cc_ramp_values(100, 10, 3), vec![70, 40, 10]);
+        assert_eq!(cc_ramp_values(5, 90, 0), vec![90]);
+        assert_eq!(*cc_ramp_values(3, 7, 50).last().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_parse_message_ex_pitch_bend() {
+        let parse = |message: &[u8]| {
+            let (mut message_type, mut channel, mut data1, mut data2, mut bend) = (0u8, 0u8, 0u8, 0u8, 0i32);
+            let result = midi_parse_message_ex(
+                message.as_ptr(), message.len() as c_int,
+                &mut message_type, &mut channel, &mut data1, &mut data2, &mut bend,
+            );
+            assert_eq!(result, 0);
+            (message_type, channel, data1, data2, bend)
+        };
+
+        assert_eq!(parse(&[0xE3, 0x7F, 0x7F]), (4, 3, 0x7F, 0x7F, 16383));
+        assert_eq!(parse(&[0xE0, 0x00, 0x40]), (4, 0, 0x00, 0x40, 8192));
+        assert_eq!(parse(&[0x90, 60, 100]), (1, 0, 60, 100, 8192));
+        assert_eq!(parse(&[0xD2, 90]), (5, 2, 90, 0, 8192));
+        assert_eq!(parse(&[0xA2, 60, 80]), (6, 2, 60, 80, 8192));
+    }
+
+    #[test]
+    fn test_channel_mode_messages() {
+        let parse = |message: &[u8]| {
+            let (mut message_type, mut channel, mut data1, mut data2) = (0u8, 0u8, 0u8, 0u8);
+            let result = midi_parse_message(
+                message.as_ptr(), message.len() as c_int,
+                &mut message_type, &mut channel, &mut data1, &mut data2,
+            );
+            assert_eq!(result, 0);
+            (message_type, channel, data1, data2)
+        };
+
+        // CC 120-127 are channel-mode messages, identified by the controller number
+        assert_eq!(parse(&[0xB1, 120, 0]), (7, 1, 120, 0));
+        assert_eq!(parse(&[0xB1, 123, 0]), (7, 1, 123, 0));
+        assert_eq!(parse(&[0xB1, 127, 0]), (7, 1, 127, 0));
+        assert_eq!(parse(&[0xB1, 119, 0]), (2, 1, 119, 0));
+        assert_eq!(MidiEventData::from_message(&[0xB2, 122, 0]).message_type, 7);
+    }
+
+    #[test]
+    fn test_zero_velocity_note_on_type() {
+        let silent = MidiMessage::NoteOn { channel: 0, note: 60, velocity: 0 };
+        let sounding = MidiMessage::NoteOn { channel: 0, note: 60, velocity: 100 };
+        assert_eq!(message_type_code(&silent, true), 0); // Note Off by default
+        assert_eq!(message_type_code(&silent, false), 1);
+        assert_eq!(message_type_code(&sounding, true), 1);
+        assert_eq!(message_type_code(&sounding, false), 1);
+    }
+
+    #[test]
+    fn test_parse_message_rejects_truncated_messages() {
+        let parse = |message: &[u8]| {
+            let (mut message_type, mut channel, mut data1, mut data2) = (0u8, 0u8, 0u8, 0u8);
+            midi_parse_message(message.as_ptr(), message.len() as c_int, &mut message_type, &mut channel, &mut data1, &mut data2)
+        };
+
+        let truncated = LVInteropError::TruncatedMessage(String::new()).code();
+        assert_eq!(parse(&[0x90]), truncated);
+        assert_eq!(parse(&[0x90, 60]), truncated);
+        assert_eq!(parse(&[0xE0, 0]), truncated);
+        assert_eq!(parse(&[0xC0]), truncated);
+        assert_ne!(truncated, LVInteropError::DeviceNotFound(String::new()).code());
+        assert_eq!(parse(&[0xC0, 5]), 0);
+        assert_eq!(parse(&[0xD0, 90]), 0);
+        assert_eq!(parse(&[0xF8]), 0); // System real-time messages are a single byte
+    }
+
     #[test]
     fn test_device_counting() {
         let input_count = midi_get_input_device_count();
@@ -682,8 +4223,579 @@ mod tests {
     fn test_manager_lifecycle() {
         let handle = midi_create_manager();
         assert!(handle > 0);
-        
-        let result = midi_destroy_manager(handle);
+        assert_eq!(midi_destroy_manager(handle), 0);
+
+        // Exact handle values are checked on a private context, which tests running in
+        // parallel on the default context cannot disturb
+        let context = MidiContext::new();
+        let handle = context.create_manager();
+        assert_eq!(handle, 1);
+        assert_eq!(context.destroy_manager(handle), 0);
+        let reused = context.create_manager();
+        assert_ne!(reused, handle); // A reused slot comes back under a new generation
+        assert_eq!(context.destroy_manager(reused), 0);
+    }
+
+    #[test]
+    fn test_midi_event_data_layout() {
+        use std::mem::{align_of, offset_of, size_of};
+
+        // Must match the LabVIEW cluster of five I32
+        assert_eq!(offset_of!(MidiEventData, message_type), 0);
+        assert_eq!(offset_of!(MidiEventData, channel), 4);
+        assert_eq!(offset_of!(MidiEventData, note_or_controller), 8);
+        assert_eq!(offset_of!(MidiEventData, velocity_or_value), 12);
+        assert_eq!(offset_of!(MidiEventData, raw_status), 16);
+        assert_eq!(size_of::<MidiEventData>(), 20);
+        assert_eq!(align_of::<MidiEventData>(), 4);
+    }
+
+    #[test]
+    fn test_event_counts_follow_filter() {
+        // Only Note On (channel 1) passes the filter
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(vec![0x90])));
+
+        listener.process(&[0x90, 60, 100]);
+        listener.process(&[0x90, 60, 0]);
+        listener.process(&[0xB0, 7, 100]);
+        listener.process(&[]);
+
+        let counts = listener.event_counts();
+        assert_eq!(counts[0], 1); // Note On with velocity 0 counts as Note Off
+        assert_eq!(counts[1], 1);
+        assert_eq!(counts[2], 0); // Filtered out
+
+        listener.reset_event_counts();
+        assert_eq!(listener.event_counts(), [0; EVENT_COUNT_SLOTS]);
+    }
+
+    #[test]
+    fn test_listener_blacklist_filter() {
+        let filter = Arc::new(MessageFilter::new(vec![0xF8, 0xFE]));
+        filter.set_mode(FilterMode::Blacklist);
+        let listener = EventListener::new(LVUserEvent::from_raw(0), filter);
+
+        listener.process(&[0xF8]);
+        listener.process(&[0xFE]);
+        listener.process(&[0x90, 60, 100]);
+        listener.process(&[0xB0, 7, 100]);
+        listener.process(&[0xD0, 90]);
+        listener.process(&[0xA0, 60, 80]);
+
+        let counts = listener.event_counts();
+        assert_eq!(counts[1], 1);
+        assert_eq!(counts[2], 1);
+        assert_eq!(counts[5], 1); // Channel Aftertouch
+        assert_eq!(counts[6], 1); // Poly Aftertouch
+        assert_eq!(counts[EVENT_COUNT_SLOTS - 1], 0); // Clock and active sensing dropped
+    }
+
+    #[test]
+    fn test_event_counts_respect_the_buffer_length() {
+        let listener = Arc::new(EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new()))));
+        listener.process(&[0x90, 60, 100]);
+        listener.process(&[0xF8]);
+        let handle = get_next_handle();
+        get_event_listeners().lock().unwrap().insert(handle, listener);
+
+        // An 8-int buffer from the earlier layout is not overrun
+        let mut counts = [-1 as c_int; 9];
+        assert_eq!(midi_get_event_counts_ex(handle, counts.as_mut_ptr(), 8), 8);
+        assert_eq!(counts[1], 1);
+        assert_eq!(counts[8], -1);
+
+        // The fixed-size getter keeps writing 8 ints
+        counts[8] = -1;
+        assert_eq!(midi_get_event_counts(handle, counts.as_mut_ptr()), 0);
+        assert_eq!(counts[1], 1);
+        assert_eq!(counts[8], -1);
+
+        assert_eq!(midi_get_event_counts_ex(handle, counts.as_mut_ptr(), 9), 9);
+        assert_eq!(counts[8], 1); // Unknown
+        assert_eq!(midi_get_event_counts_ex(handle, counts.as_mut_ptr(), -1), -1);
+
+        get_event_listeners().lock().unwrap().remove(&handle);
+        release_handle(handle);
+    }
+
+    #[test]
+    fn test_oversized_lengths_are_rejected() {
+        let message = [0x90u8, 60, 100];
+        let (mut message_type, mut channel, mut data1, mut data2) = (0u8, 0u8, 0u8, 0u8);
+        let oversized = DEFAULT_MAX_BUFFER_LENGTH as c_int + 1;
+
+        let result = midi_parse_message(message.as_ptr(), oversized, &mut message_type, &mut channel, &mut data1, &mut data2);
+        assert_eq!(result, -7);
+        assert_eq!(midi_send_message(-1, message.as_ptr(), oversized), -7);
+        assert_eq!(midi_validate_message(message.as_ptr(), oversized), -1);
+        assert_eq!(midi_set_filter(-1, message.as_ptr(), oversized), -7);
+
+        let result = midi_parse_message(message.as_ptr(), 3, &mut message_type, &mut channel, &mut data1, &mut data2);
         assert_eq!(result, 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_strict_mode_rejects_out_of_range_values() {
+        let mut buffer = [0u8; 3];
+        assert_eq!(midi_create_note_on(0, 200, 100, buffer.as_mut_ptr()), 3);
+        assert_eq!(buffer, [0x90, 72, 100]);
+
+        midi_set_strict_mode(1);
+        assert_eq!(midi_create_note_on(0, 200, 100, buffer.as_mut_ptr()), -1);
+        assert_eq!(midi_create_note_off_zero(16, 60, buffer.as_mut_ptr()), -1);
+        assert_eq!(midi_create_control_change(0, 7, 100, buffer.as_mut_ptr()), 3);
+        midi_set_strict_mode(0);
+        assert_eq!(midi_create_note_on(0, 200, 100, buffer.as_mut_ptr()), 3);
+    }
+
+    #[test]
+    fn test_listener_priority_is_applied_from_the_callback() {
+        assert_eq!(midi_set_listener_priority(-1, 10), -1);
+        assert_eq!(midi_set_listener_priority(-1, MAX_THREAD_PRIORITY + 1), -1);
+
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new())));
+        listener.set_priority(0); // Normal scheduling, so the test thread is left as it was
+        assert!(!listener.priority_applied.load(Ordering::Relaxed));
+        listener.process(&[0x90, 60, 100]);
+        assert!(listener.priority_applied.load(Ordering::Relaxed));
+
+        listener.reapply_priority();
+        assert!(!listener.priority_applied.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_toggling_coalesce_keeps_one_thread() {
+        let listener = Arc::new(EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new()))));
+        listener.set_coalesce_window_ms(60_000);
+        for _ in 0..20 {
+            listener.set_coalesce(true);
+            let first = listener.coalesce_thread.lock().unwrap().as_ref().map(|thread| thread.thread().id());
+            listener.set_coalesce(true); // Already running
+            let second = listener.coalesce_thread.lock().unwrap().as_ref().map(|thread| thread.thread().id());
+            assert_eq!(first, second);
+
+            listener.set_coalesce(false); // Joins the thread rather than leaving it to exit
+            assert!(listener.coalesce_thread.lock().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_dropping_a_listener_stops_its_coalesce_thread() {
+        let listener = Arc::new(EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new()))));
+        listener.set_coalesce(true);
+        listener.process(&[0xE0, 0x00, 0x50]); // Held back for the coalesce thread
+
+        let weak = Arc::downgrade(&listener);
+        drop(listener); // Stops and joins the coalesce thread (without deadlocking)
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_scheduled_sends_fire_in_time_order() {
+        let later = SCHEDULED_SENDS.schedule(-1, Duration::from_secs(60), vec![0x90, 62, 100]);
+        let sooner = SCHEDULED_SENDS.schedule(-1, Duration::from_secs(30), vec![0x90, 60, 100]);
+        {
+            let queue = SCHEDULED_SENDS.queue.lock().unwrap();
+            let order: Vec<i32> = queue.keys().map(|(_, cue)| *cue).filter(|cue| [later, sooner].contains(cue)).collect();
+            assert_eq!(order, vec![sooner, later]);
+        }
+
+        assert!(SCHEDULED_SENDS.cancel(later));
+        assert!(!SCHEDULED_SENDS.cancel(later));
+        assert_eq!(midi_cancel_scheduled(sooner), 0);
+
+        // An unknown handle is rejected up front
+        let message = [0x90u8, 60, 100];
+        assert_eq!(midi_schedule_send(-1, 10, message.as_ptr(), 3), -1);
+    }
+
+    #[test]
+    fn test_destroying_a_manager_clears_its_side_tables() {
+        let handle = midi_create_manager();
+        let message = [0x90u8, 60, 100];
+        let cue = midi_schedule_send(handle, 60_000, message.as_ptr(), 3);
+        assert!(cue > 0);
+
+        assert_eq!(midi_destroy_manager(handle), 0);
+        assert!(!SCHEDULED_SENDS.queue.lock().unwrap().values().any(|(queued, _)| *queued == handle));
+
+        // The slot may be reused, but never under the old handle
+        let next = midi_create_manager();
+        assert_ne!(next, handle);
+        assert_eq!(midi_destroy_manager(handle), -1);
+        assert_eq!(midi_destroy_manager(next), 0);
+    }
+
+    #[test]
+    fn test_cc_ramps_are_capped_and_joined() {
+        let handle = midi_create_manager();
+        // Far too many steps over a minute; the ramp stops at its first failed send (no output)
+        assert_eq!(midi_send_cc_ramp(handle, 0, 7, 0, 127, 60_000, i32::MAX), 0);
+        assert_eq!(midi_send_cc_ramp(handle, 0, 7, 127, 0, 60_000, 10), 0); // Replaces the first
+
+        let start = Instant::now();
+        assert_eq!(midi_destroy_manager(handle), 0);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(!get_cc_ramps().lock().unwrap().keys().any(|(ramp_handle, _, _)| *ramp_handle == handle));
+    }
+
+    #[test]
+    fn test_finished_players_are_reaped() {
+        let handle = get_next_handle();
+        let thread = thread::spawn(|| ());
+        get_players().lock().unwrap().insert(handle, Player { stop: Arc::new(AtomicBool::new(false)), thread });
+        assert_eq!(midi_file_is_playing(handle), 1);
+
+        assert!(reap_player(handle));
+        assert_eq!(midi_file_is_playing(handle), 0);
+        assert_eq!(midi_file_stop_playback(handle), -1);
+        assert!(!reap_player(handle));
+    }
+
+    #[test]
+    fn test_active_sensing_needs_a_manager() {
+        assert_eq!(midi_enable_active_sensing(-1, 250), -1);
+        assert_eq!(midi_disable_active_sensing(-1), -1);
+    }
+
+    #[test]
+    fn test_disconnect_stops_active_sensing() {
+        let handle = midi_create_manager();
+        assert_eq!(midi_enable_active_sensing(handle, 250), 0);
+        assert!(get_active_sensing().lock().unwrap().contains_key(&handle));
+
+        assert_eq!(midi_disconnect(handle), 0);
+        assert!(!get_active_sensing().lock().unwrap().contains_key(&handle));
+    }
+
+    #[test]
+    fn test_negative_device_index_is_rejected() {
+        assert_eq!(midi_connect_input(-1, -1), -2);
+        assert_eq!(midi_connect_output(-1, i32::MIN), -2);
+        assert_eq!(midi_connect_with_user_event(-1, 0, std::ptr::null(), 0), -2);
+
+        // Other contexts go through the same checks
+        let context = MidiContext::new();
+        let handle = context.create_manager();
+        assert_eq!(context.connect_input(handle, -1), -2);
+        assert_eq!(context.connect_output(handle, i32::MIN), -2);
+        assert_eq!(context.connect_with_user_event(-1, 0, Vec::new()), -2);
+    }
+
+    #[test]
+    fn test_open_duplex_needs_a_name() {
+        assert_eq!(midi_open_duplex_by_name(std::ptr::null()), -1);
+        let name = CString::new("No Such Duplex Device").unwrap();
+        assert!(matches!(midi_open_duplex_by_name(name.as_ptr()), -2 | -5)); // -5 without a MIDI backend
+    }
+
+    #[test]
+    fn test_event_log_arguments() {
+        let path = CString::new("unused.log").unwrap();
+        assert_eq!(midi_start_logging(-1, path.as_ptr()), -1);
+        assert_eq!(midi_stop_logging(-1), -1);
+        assert_eq!(midi_replay_log_file(-1, path.as_ptr()), -1);
+    }
+
+    #[test]
+    fn test_library_version() {
+        let mut buffer = [0 as c_char; 64];
+        assert_eq!(midi_library_version(buffer.as_mut_ptr(), buffer.len() as c_int), 0);
+        let version = unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_str().unwrap();
+        assert!(version.starts_with(env!("CARGO_PKG_VERSION")));
+
+        let (mut major, mut minor, mut patch) = (-1, -1, -1);
+        assert_eq!(midi_library_version_number(&mut major, &mut minor, &mut patch), 0);
+        assert_eq!(format!("{}.{}.{}", major, minor, patch), env!("CARGO_PKG_VERSION"));
+        assert_eq!(midi_library_version_number(std::ptr::null_mut(), &mut minor, &mut patch), -1);
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new())));
+        listener.process(&[0x90, 60, 100]);
+        assert_eq!(listener.latency_stats().min_avg_max(), (0.0, 0.0, 0.0)); // Off by default
+
+        listener.set_latency_stats(true);
+        for note in 60..70 {
+            listener.process(&[0x90, note, 100]);
+        }
+        let stats = listener.latency_stats();
+        assert_eq!(stats.count, 10);
+        let (min, avg, max) = stats.min_avg_max();
+        assert!(min <= avg && avg <= max);
+
+        listener.set_latency_stats(false);
+        assert_eq!(listener.latency_stats().count, 0);
+    }
+
+    #[test]
+    fn test_dropped_input_count() {
+        let context = MidiContext::new();
+        let handle = context.create_manager();
+        assert_eq!(context.dropped_input_count(handle), 0); // No input connected
+        assert_eq!(context.dropped_input_count(handle + 1), -1);
+    }
+
+    #[test]
+    fn test_restart_needs_a_listener() {
+        let context = MidiContext::new();
+        let handle = context.create_manager();
+        assert_eq!(context.restart_listener(handle), -1); // Plain manager, no listener
+        assert_eq!(context.restart_listener(handle + 1), -1);
+    }
+
+    #[test]
+    fn test_contexts_are_independent() {
+        let first = MidiContext::new();
+        let second = MidiContext::new();
+        assert_eq!(first.create_manager(), 1);
+        assert_eq!(first.create_manager(), 2);
+        assert_eq!(second.create_manager(), 1);
+
+        assert_eq!(second.destroy_manager(1), 0);
+        assert_eq!(second.destroy_manager(1), -1);
+        assert_eq!(second.send_message(1, &[0x90, 60, 100]), -1);
+        assert_eq!(first.send_message(1, &[0x90, 60, 100]), -3); // Known handle, no output connected
+
+        first.shutdown();
+        assert_eq!(first.destroy_manager(2), -1);
+        assert_eq!(first.create_manager(), 1);
+    }
+
+    #[test]
+    fn test_stop_and_drop_do_not_wait_out_the_coalesce_window() {
+        let stop_actions: [fn(Arc<EventListener>); 2] = [|listener| listener.stop(), drop];
+        for stop in stop_actions {
+            let listener = Arc::new(EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new()))));
+            listener.set_coalesce_window_ms(60_000);
+            listener.set_coalesce(true);
+            thread::sleep(Duration::from_millis(20)); // Let the thread start waiting
+
+            let start = Instant::now();
+            stop(listener);
+            assert!(start.elapsed() < Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn test_history_keeps_recent_events_newest_first() {
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(vec![0x90])));
+        listener.process(&[0x90, 60, 100]); // History is off
+        listener.set_history_size(2);
+        for note in [61, 62, 63] {
+            listener.process(&[0x90, note, 100]);
+        }
+        listener.process(&[0xB0, 7, 100]); // Filtered out
+
+        let notes: Vec<i32> = listener.history().iter().map(|event| event.note_or_controller).collect();
+        assert_eq!(notes, [63, 62]);
+
+        listener.set_history_size(1);
+        assert_eq!(listener.history().len(), 1);
+        listener.set_history_size(0);
+        assert!(listener.history().is_empty());
+    }
+
+    #[test]
+    fn test_replay_log_honors_delays() {
+        let listener = Arc::new(EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new()))));
+        let entries = vec![
+            (Duration::ZERO, vec![0x90, 60, 100]),
+            (Duration::from_millis(20), vec![0xB0, 7, 100]),
+            (Duration::from_millis(20), vec![0x80, 60, 0]),
+        ];
+
+        let start = Instant::now();
+        replay_log(Arc::clone(&listener), entries).join().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        let counts = listener.event_counts();
+        assert_eq!(counts[0], 1); // Note Off
+        assert_eq!(counts[1], 1); // Note On
+        assert_eq!(counts[2], 1); // Control Change
+    }
+
+    #[test]
+    fn test_replayed_messages_are_not_logged() {
+        let path = std::env::temp_dir().join(format!("labview_midi_{}_replay.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = Arc::new(EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new()))));
+        listener.start_logging(&path).unwrap();
+
+        listener.process(&[0x90, 60, 100]);
+        replay_log(Arc::clone(&listener), vec![(Duration::ZERO, vec![0x80, 60, 0])]).join().unwrap();
+        listener.stop_logging().unwrap().unwrap();
+
+        let messages: Vec<Vec<u8>> = open_event_log(&path).unwrap().map(|record| record.unwrap().1).collect();
+        assert_eq!(messages, vec![vec![0x90, 60, 100]]);
+        assert_eq!(listener.event_counts()[0], 1); // Still delivered
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_coalesce_holds_back_continuous_messages_only() {
+        let listener = Arc::new(EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new()))));
+        listener.set_coalesce_window_ms(60_000);
+        let event = |raw_status: i32| MidiEventData {
+            message_type: 4,
+            channel: 0,
+            note_or_controller: 0,
+            velocity_or_value: 64,
+            raw_status,
+        };
+
+        // Disabled: nothing is held back
+        assert!(!listener.coalesce(0xE0, event(0xE0)));
+
+        listener.set_coalesce(true);
+        assert!(!listener.coalesce(0xE0, event(0xE0))); // First value goes straight out
+        assert!(listener.coalesce(0xE0, event(0xE0)));
+        assert!(!listener.coalesce(0xD0, event(0xD0))); // Separate slot
+        assert!(!listener.coalesce(0xE1, event(0xE1))); // Separate channel
+        assert!(!listener.coalesce(0x90, event(0x90))); // Notes are never coalesced
+
+        listener.set_coalesce(false);
+        assert!(!listener.coalesce(0xE0, event(0xE0)));
+    }
+
+    #[test]
+    fn test_mpe_tracks_per_note_expression() {
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new())));
+
+        // Off by default
+        listener.process(&[0x91, 60, 100]);
+        assert!(listener.track_mpe(&[0xE1, 0x00, 0x50]).is_none());
+
+        listener.set_mpe(15);
+        listener.process(&[0x91, 60, 100]);
+        let bend = listener.track_mpe(&[0xE1, 0x00, 0x50]).unwrap();
+        assert_eq!((bend.channel, bend.note, bend.per_note_bend), (1, 60, 2048));
+
+        let pressure = listener.track_mpe(&[0xD1, 90]).unwrap();
+        assert_eq!((pressure.per_note_bend, pressure.per_note_pressure), (2048, 90));
+
+        assert!(listener.track_mpe(&[0xE0, 0x00, 0x50]).is_none()); // Master channel
+        listener.process(&[0x81, 60, 0]);
+        assert!(listener.track_mpe(&[0xD1, 90]).is_none()); // No note sounding
+    }
+
+    #[test]
+    fn test_ump_is_passed_through_as_unsupported() {
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(vec![0x90])));
+        let ump_note_on = [0x20, 0x90, 0x3C, 0x64];
+
+        listener.process(&ump_note_on);
+        assert_eq!(listener.last_unsupported(), ump_note_on.to_vec());
+        assert_eq!(listener.event_counts()[EVENT_COUNT_SLOTS - 1], 1);
+        assert_eq!(listener.event_counts()[1], 0); // Not misread as a Note On
+
+        let (mut message_type, mut channel, mut data1, mut data2) = (0u8, 0u8, 0u8, 0u8);
+        let result = midi_parse_message(ump_note_on.as_ptr(), 4, &mut message_type, &mut channel, &mut data1, &mut data2);
+        assert_eq!((result, message_type), (0, MESSAGE_TYPE_UNSUPPORTED as u8));
+    }
+
+    #[test]
+    fn test_chord_detection_tracks_held_notes() {
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new())));
+        listener.process(&[0x90, 60, 100]);
+        assert!(listener.held_notes(0).is_empty()); // Not tracked until enabled
+
+        listener.set_chord_event(Some(LVUserEvent::from_raw(0)));
+        for note in [60, 64, 67] {
+            listener.process(&[0x90, note, 100]);
+        }
+        listener.process(&[0x91, 50, 100]);
+        assert_eq!(listener.held_notes(0), vec![60, 64, 67]);
+        assert_eq!(listener.held_notes(1), vec![50]);
+
+        listener.process(&[0x90, 64, 0]);
+        assert_eq!(listener.held_notes(0), vec![60, 67]);
+        listener.process(&[0xB0, 123, 0]);
+        assert!(listener.held_notes(0).is_empty());
+        assert_eq!(listener.held_notes(1), vec![50]);
+    }
+
+    #[test]
+    fn test_release_velocity_curve() {
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new())));
+        assert_eq!(listener.remap_release_velocity(&[0x80, 60, 64]), None); // Identity by default
+
+        let mut curve = [0u8; 128];
+        for (velocity, mapped) in curve.iter_mut().enumerate() {
+            *mapped = 127 - velocity as u8;
+        }
+        listener.set_release_curve(Some(curve));
+        assert_eq!(listener.remap_release_velocity(&[0x83, 60, 0]), Some([0x83, 60, 127]));
+        assert_eq!(listener.remap_release_velocity(&[0x83, 60, 100]), Some([0x83, 60, 27]));
+        assert_eq!(listener.remap_release_velocity(&[0x90, 60, 0]), None);
+        assert_eq!(listener.remap_release_velocity(&[0x90, 60, 100]), None);
+
+        listener.set_release_curve(None);
+        assert_eq!(listener.remap_release_velocity(&[0x80, 60, 64]), None);
+    }
+
+    #[test]
+    fn test_expression_merges_pressure_and_bend() {
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new())));
+        assert_eq!(listener.track_expression(&[0xD0, 64]), None); // Off until enabled
+
+        listener.set_expression_event(Some(LVUserEvent::from_raw(0)));
+        let expression = |channel, pressure, bend| Some(ExpressionEvent { channel, pressure, bend });
+        assert_eq!(listener.track_expression(&[0xD2, 64]), expression(2, 64, 0));
+        assert_eq!(listener.track_expression(&[0xE2, 0x00, 0x50]), expression(2, 64, 2048));
+        assert_eq!(listener.track_expression(&[0xD2, 10]), expression(2, 10, 2048));
+        assert_eq!(listener.track_expression(&[0xE3, 0x00, 0x00]), expression(3, 0, -8192));
+        assert_eq!(listener.track_expression(&[0x92, 60, 100]), None);
+
+        listener.set_expression_event(None);
+        assert_eq!(listener.track_expression(&[0xD2, 64]), None);
+    }
+
+    #[test]
+    fn test_mtc_quarter_frames_are_assembled() {
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new())));
+        // 01:02:03:04 at 25 fps, as pieces 0-7
+        let pieces = [0x04, 0x10, 0x23, 0x30, 0x42, 0x50, 0x61, 0x72];
+        let feed = |pieces: &[u8]| pieces.iter().map(|data| listener.track_mtc(&[0xF1, *data])).last().flatten();
+        assert_eq!(feed(&pieces), None); // Off until enabled
+
+        listener.set_mtc_event(Some(LVUserEvent::from_raw(0)));
+        let timecode = TimecodeEvent { hours: 1, minutes: 2, seconds: 3, frames: 4, rate: 25 };
+        assert_eq!(feed(&pieces), Some(timecode));
+        assert_eq!(feed(&pieces[..7]), None);
+
+        // A dropped piece discards the sequence; the next one starting at piece 0 is decoded
+        assert_eq!(feed(&[0x04, 0x10, 0x23, 0x42, 0x50, 0x61, 0x72]), None);
+        assert_eq!(feed(&pieces), Some(timecode));
+    }
+
+    #[test]
+    fn test_sysex_reassembled_across_callbacks() {
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(vec![0x90])));
+
+        listener.process(&[0xF0, 0x7E, 0x7F]);
+        listener.process(&[0xF8]); // Real-time bytes may be interleaved
+        listener.process(&[0x06, 0x01]);
+        assert!(listener.last_sysex().is_empty());
+
+        listener.process(&[0xF7]);
+        assert_eq!(listener.last_sysex(), vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+        assert_eq!(listener.event_counts(), [0; EVENT_COUNT_SLOTS]);
+    }
+
+    #[test]
+    fn test_unterminated_sysex_is_discarded() {
+        let listener = EventListener::new(LVUserEvent::from_raw(0), Arc::new(MessageFilter::new(Vec::new())));
+
+        listener.process(&[0xF0, 0x7E, 0x01]); // 0xF7 never arrives
+        listener.process(&[0x90, 60, 100]);
+        listener.process(&[0x02, 0xF7]); // Stray continuation, not a dump
+        assert!(listener.last_sysex().is_empty());
+        assert_eq!(listener.event_counts()[1], 1);
+
+        listener.process(&[0xF0, 0x7E, 0x01]);
+        listener.process(&[0xF0, 0x7E, 0x02, 0xF7]); // A new dump restarts the buffer
+        assert_eq!(listener.last_sysex(), vec![0xF0, 0x7E, 0x02, 0xF7]);
+    }
+}
@@ -2,13 +2,23 @@
 // Run with: cargo run --bin midi_file_test -- path/to/file.mid
 
 use std::env;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::sync::Mutex;
 
 // Import the Rust functions directly from our library
 use crate::{
-    midi_file::{load_midi_file, get_midi_file, close_midi_file, EventType},
+    midi_file::{
+        load_midi_file, get_midi_file, close_midi_file, register_midi_file, event_uid, decode_event_uid,
+        AbsoluteEvent, EventType, NoteEvent,
+    },
     get_note_name,
+    lv_midi::{c_string_len, write_c_string},
 };
 
+/// Description of the last failed `midi_file_open`
+static LAST_FILE_ERROR: Mutex<String> = Mutex::new(String::new());
+
 fn main() {
     println!("🎵 MIDI File Test Program 🎵");
     println!("=============================");
@@ -92,7 +102,7 @@ fn main() {
             
             for (event_idx, abs_event) in track.events.iter().take(events_to_show).enumerate() {
                 // Calculate UID as it would be generated in the C API
-                let uid = generate_event_uid(file_handle, track_idx as i32, event_idx as i32);
+                let uid = event_uid(file_handle, track_idx as i32, event_idx as i32).unwrap_or(0);
                 
                 let type_name = match abs_event.event_type {
                     EventType::NoteOff => "Note Off",
@@ -121,7 +131,7 @@ fn main() {
                     EventType::Unknown => "Unknown",
                 };
                 
-                print!("     [{}] UID:{:08X} T:{:6} {} Ch:{} ", 
+                print!("     [{}] UID:{:016X} T:{:6} {} Ch:{} ", 
                        event_idx, uid, abs_event.absolute_time, type_name, abs_event.channel + 1);
                 
                 // Show relevant data based on event type
@@ -161,13 +171,11 @@ fn main() {
     // Test UID functionality
     println!("\n🔧 Testing UID Functionality:");
     if !midi_file.tracks.is_empty() && !midi_file.tracks[0].events.is_empty() {
-        let test_uid = generate_event_uid(file_handle, 0, 0);
-        println!("   Generated UID for first event: 0x{:08X}", test_uid);
+        let test_uid = event_uid(file_handle, 0, 0).unwrap_or(0);
+        println!("   Generated UID for first event: 0x{:016X}", test_uid);
         
         // Decode the UID
-        let decoded_file = ((test_uid >> 24) & 0xFF) as i32;
-        let decoded_track = ((test_uid >> 16) & 0xFF) as i32;
-        let decoded_event = (test_uid & 0xFFFF) as i32;
+        let (decoded_file, decoded_track, decoded_event) = decode_event_uid(test_uid);
         
         println!("   Decoded UID: file={}, track={}, event={}", 
                 decoded_file, decoded_track, decoded_event);
@@ -191,15 +199,6 @@ fn main() {
     println!("\n🎼 MIDI File Analysis Complete! 🎼");
 }
 
-/// Generate UID using the same algorithm as the C API
-fn generate_event_uid(file_handle: i32, track_index: i32, event_index: i32) -> u32 {
-    let file_part = ((file_handle as u32) & 0xFF) << 24;
-    let track_part = ((track_index as u32) & 0xFF) << 16;
-    let event_part = (event_index as u32) & 0xFFFF;
-    
-    file_part | track_part | event_part
-}
-
 // Helper function to get controller names
 fn get_controller_name(controller: u8) -> &'static str {
     match controller {
@@ -214,3 +213,798 @@ fn get_controller_name(controller: u8) -> &'static str {
         _ => "Other"
     }
 }
+
+// ========== OPENING FILES ==========
+
+fn set_last_file_error(error: String) {
+    *LAST_FILE_ERROR.lock().unwrap() = error;
+}
+
+/// Load a Standard MIDI File from disk and write its handle to `file_handle`.
+/// Returns 0 on success, -1 for a null pointer, -2 if the path is not valid UTF-8, or -3 if
+/// the file could not be loaded. Why a load failed (not found, unreadable, not a valid MIDI
+/// file, or empty) is available from `midi_file_last_error`.
+#[no_mangle]
+pub extern "C" fn midi_file_open(file_path: *const c_char, file_handle: *mut c_int) -> c_int {
+    if file_path.is_null() || file_handle.is_null() {
+        return -1;
+    }
+
+    let path = match unsafe { CStr::from_ptr(file_path) }.to_str() {
+        Ok(path) => path,
+        Err(e) => {
+            set_last_file_error(format!("Path is not valid UTF-8: {}", e));
+            return -2;
+        }
+    };
+    match load_midi_file(path) {
+        Ok(handle) => {
+            unsafe { *file_handle = handle; }
+            0
+        }
+        Err(e) => {
+            set_last_file_error(e.to_string());
+            -3
+        }
+    }
+}
+
+/// Get the reason the last `midi_file_open` failed (empty if none has).
+/// Returns 0 on success or -1 if the buffer is invalid or too small.
+#[no_mangle]
+pub extern "C" fn midi_file_last_error(buffer: *mut c_char, buffer_size: c_int) -> c_int {
+    write_c_string(&LAST_FILE_ERROR.lock().unwrap(), buffer, buffer_size)
+}
+
+// ========== STRING LENGTH QUERIES ==========
+
+/// Get the buffer size (including the null terminator) needed for a track name
+#[no_mangle]
+pub extern "C" fn midi_file_get_track_name_len(
+    file_handle: c_int,
+    track_index: c_int,
+) -> c_int {
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    if track_index < 0 || track_index as usize >= midi_file.tracks.len() {
+        return -1;
+    }
+
+    c_string_len(&midi_file.tracks[track_index as usize].name)
+}
+
+/// Get the buffer size (including the null terminator) needed for a track instrument name.
+/// Returns -1 if the track has no instrument name.
+#[no_mangle]
+pub extern "C" fn midi_file_get_track_instrument_len(
+    file_handle: c_int,
+    track_index: c_int,
+) -> c_int {
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    if track_index < 0 || track_index as usize >= midi_file.tracks.len() {
+        return -1;
+    }
+
+    match midi_file.tracks[track_index as usize].instrument {
+        Some(ref instrument) => c_string_len(instrument),
+        None => -1,
+    }
+}
+
+/// Get the buffer size (including the null terminator) needed for an event's text
+#[no_mangle]
+pub extern "C" fn midi_file_get_event_text_len(
+    file_handle: c_int,
+    track_index: c_int,
+    event_index: c_int,
+) -> c_int {
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    if track_index < 0 || track_index as usize >= midi_file.tracks.len() {
+        return -1;
+    }
+
+    let track = &midi_file.tracks[track_index as usize];
+    if event_index < 0 || event_index as usize >= track.events.len() {
+        return -1;
+    }
+
+    c_string_len(&track.events[event_index as usize].text)
+}
+
+// ========== TEMPO ==========
+
+/// Get the initial tempo of a MIDI file in BPM (120 if it has no tempo event).
+/// Returns a negative value if the handle is invalid.
+#[no_mangle]
+pub extern "C" fn midi_file_get_bpm(file_handle: c_int) -> f64 {
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1.0,
+    };
+    match files.get(&file_handle) {
+        Some(midi_file) => midi_file.get_initial_bpm(),
+        None => -1.0,
+    }
+}
+
+/// Get the duration of a MIDI file in milliseconds, following its tempo changes.
+/// Returns a negative value if the handle is invalid.
+#[no_mangle]
+pub extern "C" fn midi_file_get_duration_ms(file_handle: c_int) -> f64 {
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1.0,
+    };
+    match files.get(&file_handle) {
+        Some(midi_file) => midi_file.duration_ms(),
+        None => -1.0,
+    }
+}
+
+/// Convert a time in milliseconds from the start of a MIDI file to ticks, following its
+/// tempo changes (the inverse of the millisecond times above). Returns 0 if the handle is invalid.
+#[no_mangle]
+pub extern "C" fn midi_file_ms_to_ticks(file_handle: c_int, ms: f64) -> u32 {
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return 0,
+    };
+    match files.get(&file_handle) {
+        Some(midi_file) => midi_file.ms_to_ticks(ms),
+        None => 0,
+    }
+}
+
+/// Replace a MIDI file's tempo map with a single tempo in BPM
+#[no_mangle]
+pub extern "C" fn midi_file_set_bpm(file_handle: c_int, bpm: f64) -> c_int {
+    let mut files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    match files.get_mut(&file_handle) {
+        Some(midi_file) => match midi_file.set_uniform_tempo(bpm) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        },
+        None => -1,
+    }
+}
+
+// ========== TIMING ==========
+
+/// Get a MIDI file's ticks per quarter note.
+/// Returns -1 if the handle is invalid or the file uses SMPTE timing.
+#[no_mangle]
+pub extern "C" fn midi_file_get_ppq(file_handle: c_int) -> c_int {
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    match files.get(&file_handle).map(|midi_file| midi_file.timing) {
+        Some(midly::Timing::Metrical(tpq)) => tpq.as_int() as c_int,
+        _ => -1,
+    }
+}
+
+/// Get a MIDI file's SMPTE frames per second and ticks per frame.
+/// Returns -1 if the handle is invalid or the file uses metrical (PPQ) timing.
+#[no_mangle]
+pub extern "C" fn midi_file_get_smpte(
+    file_handle: c_int,
+    out_fps: *mut c_int,
+    out_tpf: *mut c_int,
+) -> c_int {
+    if out_fps.is_null() || out_tpf.is_null() {
+        return -1;
+    }
+
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    match files.get(&file_handle).map(|midi_file| midi_file.timing) {
+        Some(midly::Timing::Timecode(fps, tpf)) => {
+            unsafe {
+                *out_fps = fps.as_int() as c_int;
+                *out_tpf = tpf as c_int;
+            }
+            0
+        }
+        _ => -1,
+    }
+}
+
+// ========== NOTE VALIDATION ==========
+
+/// Get the number of note pairing issues (stuck notes and orphaned note-offs) in a MIDI file
+#[no_mangle]
+pub extern "C" fn midi_file_get_note_issue_count(file_handle: c_int) -> c_int {
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    match files.get(&file_handle) {
+        Some(midi_file) => midi_file.validate_notes().len() as c_int,
+        None => -1,
+    }
+}
+
+/// Get a note pairing issue by index. `kind` is 0 for a stuck note (note-on never released)
+/// or 1 for a note-off without a preceding note-on.
+#[no_mangle]
+pub extern "C" fn midi_file_get_note_issue(
+    file_handle: c_int,
+    issue_index: c_int,
+    track_index: *mut c_int,
+    tick: *mut u32,
+    channel: *mut u8,
+    note: *mut u8,
+    kind: *mut c_int,
+) -> c_int {
+    if track_index.is_null() || tick.is_null() || channel.is_null() || note.is_null() || kind.is_null() {
+        return -1;
+    }
+
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+
+    let issues = midi_file.validate_notes();
+    if issue_index < 0 || issue_index as usize >= issues.len() {
+        return -1;
+    }
+
+    let issue = &issues[issue_index as usize];
+    unsafe {
+        *track_index = issue.track as c_int;
+        *tick = issue.tick;
+        *channel = issue.channel;
+        *note = issue.note;
+        *kind = issue.kind as c_int;
+    }
+    0
+}
+
+/// Insert note-offs at the end of each track for notes that are never released.
+/// Returns the number of note-offs inserted, or -1 on error.
+#[no_mangle]
+pub extern "C" fn midi_file_fix_dangling_notes(file_handle: c_int) -> c_int {
+    let mut files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    match files.get_mut(&file_handle) {
+        Some(midi_file) => match midi_file.fix_dangling_notes() {
+            Ok(inserted) => inserted as c_int,
+            Err(_) => -1,
+        },
+        None => -1,
+    }
+}
+
+// ========== EVENTS BY TYPE ==========
+
+/// MIDI file event for LabVIEW (text is read with `midi_file_get_event_text_len` and friends)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MidiFileEvent {
+    pub absolute_time: u32,
+    pub event_type: c_int,
+    pub channel: u8,
+    pub data1: u8,
+    pub data2: u8,
+    pub has_text: c_int,
+}
+
+impl From<&AbsoluteEvent> for MidiFileEvent {
+    fn from(event: &AbsoluteEvent) -> Self {
+        MidiFileEvent {
+            absolute_time: event.absolute_time,
+            event_type: event.event_type.code(),
+            channel: event.channel,
+            data1: event.data1,
+            data2: event.data2,
+            has_text: !event.text.is_empty() as c_int,
+        }
+    }
+}
+
+/// Copy up to `count` events of a track, starting at event `start`, into `out_array` in one
+/// call (so a table of thousands of events does not need one call per row).
+/// Returns the number of events written: fewer than `count` near the end of the track,
+/// 0 if `start` is past the end, or -1 for an invalid handle, track or argument.
+#[no_mangle]
+pub extern "C" fn midi_file_get_events_range(
+    file_handle: c_int,
+    track_index: c_int,
+    start: c_int,
+    count: c_int,
+    out_array: *mut MidiFileEvent,
+) -> c_int {
+    if out_array.is_null() || start < 0 || count < 0 {
+        return -1;
+    }
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    let track = match usize::try_from(track_index).ok().and_then(|index| midi_file.tracks.get(index)) {
+        Some(track) => track,
+        None => return -1,
+    };
+
+    let events = track.events.iter().skip(start as usize).take(count as usize);
+    let out_events = unsafe { std::slice::from_raw_parts_mut(out_array, count as usize) };
+    let mut written = 0;
+    for (out_event, event) in out_events.iter_mut().zip(events) {
+        *out_event = MidiFileEvent::from(event);
+        written += 1;
+    }
+    written
+}
+
+/// Get the number of events of one type (see `EventType::code`) in a track
+#[no_mangle]
+pub extern "C" fn midi_file_get_event_count_of_type(
+    file_handle: c_int,
+    track_index: c_int,
+    event_type: c_int,
+) -> c_int {
+    let event_type = match EventType::from_code(event_type) {
+        Some(event_type) => event_type,
+        None => return -1,
+    };
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    if track_index < 0 || track_index as usize >= midi_file.tracks.len() {
+        return -1;
+    }
+
+    midi_file.events_of_type(track_index as usize, event_type).len() as c_int
+}
+
+/// Get the `index`-th event of one type in a track
+#[no_mangle]
+pub extern "C" fn midi_file_get_event_of_type(
+    file_handle: c_int,
+    track_index: c_int,
+    event_type: c_int,
+    index: c_int,
+    event: *mut MidiFileEvent,
+) -> c_int {
+    if event.is_null() || index < 0 {
+        return -1;
+    }
+    let event_type = match EventType::from_code(event_type) {
+        Some(event_type) => event_type,
+        None => return -1,
+    };
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    if track_index < 0 || track_index as usize >= midi_file.tracks.len() {
+        return -1;
+    }
+
+    let events = midi_file.events_of_type(track_index as usize, event_type);
+    let found = match events.get(index as usize) {
+        Some(found) => found,
+        None => return -1,
+    };
+
+    unsafe {
+        *event = MidiFileEvent::from(*found);
+    }
+    0
+}
+
+/// Get the UID of an event (see `midi_file::event_uid` for the layout), unique across every
+/// open file. Returns 0 on success or -1 for an invalid handle, track or event.
+#[no_mangle]
+pub extern "C" fn midi_file_get_event_uid(
+    file_handle: c_int,
+    track_index: c_int,
+    event_index: c_int,
+    uid: *mut u64,
+) -> c_int {
+    if uid.is_null() {
+        return -1;
+    }
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    let track = match usize::try_from(track_index).ok().and_then(|index| midi_file.tracks.get(index)) {
+        Some(track) => track,
+        None => return -1,
+    };
+    if event_index < 0 || event_index as usize >= track.events.len() {
+        return -1;
+    }
+
+    match event_uid(file_handle, track_index, event_index) {
+        Some(event_uid) => {
+            unsafe { *uid = event_uid; }
+            0
+        }
+        None => -1,
+    }
+}
+
+// ========== TEXT SEARCH ==========
+
+/// Search a file's text events (see `MidiFile::find_text`) for a query string
+fn find_text(file_handle: c_int, query: *const c_char) -> Option<Vec<(usize, usize)>> {
+    if query.is_null() {
+        return None;
+    }
+    let query = unsafe { CStr::from_ptr(query) }.to_str().ok()?;
+    let files = get_midi_file(file_handle)?;
+    files.get(&file_handle).map(|midi_file| midi_file.find_text(query))
+}
+
+/// Get the number of text, lyric, marker, cue point, copyright and track name events
+/// containing `query` (case-insensitive)
+#[no_mangle]
+pub extern "C" fn midi_file_find_text_count(file_handle: c_int, query: *const c_char) -> c_int {
+    match find_text(file_handle, query) {
+        Some(matches) => matches.len() as c_int,
+        None => -1,
+    }
+}
+
+/// Get the `index`-th match of `midi_file_find_text_count` as a track and event index
+/// (usable with `midi_file_get_event_text_len` and friends)
+#[no_mangle]
+pub extern "C" fn midi_file_find_text_result(
+    file_handle: c_int,
+    query: *const c_char,
+    index: c_int,
+    track_index: *mut c_int,
+    event_index: *mut c_int,
+) -> c_int {
+    if track_index.is_null() || event_index.is_null() || index < 0 {
+        return -1;
+    }
+    let matches = match find_text(file_handle, query) {
+        Some(matches) => matches,
+        None => return -1,
+    };
+
+    match matches.get(index as usize) {
+        Some(&(track, event)) => {
+            unsafe {
+                *track_index = track as c_int;
+                *event_index = event as c_int;
+            }
+            0
+        }
+        None => -1,
+    }
+}
+
+// ========== EDITING ==========
+
+/// Insert a note into a track as a note-on at `tick` and a note-off `duration_ticks` later.
+/// Returns 0, or -1 on error (including a zero duration or velocity).
+#[no_mangle]
+pub extern "C" fn midi_file_insert_note(
+    file_handle: c_int,
+    track_index: c_int,
+    tick: u32,
+    channel: u8,
+    note: u8,
+    velocity: u8,
+    duration_ticks: u32,
+) -> c_int {
+    if track_index < 0 {
+        return -1;
+    }
+
+    let mut files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    match files.get_mut(&file_handle) {
+        Some(midi_file) => {
+            match midi_file.insert_note(track_index as usize, tick, channel, note, velocity, duration_ticks) {
+                Ok(_) => 0,
+                Err(_) => -1,
+            }
+        }
+        None => -1,
+    }
+}
+
+/// Remove an event from a track by index
+#[no_mangle]
+pub extern "C" fn midi_file_remove_event(
+    file_handle: c_int,
+    track_index: c_int,
+    event_index: c_int,
+) -> c_int {
+    if track_index < 0 || event_index < 0 {
+        return -1;
+    }
+
+    let mut files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    match files.get_mut(&file_handle) {
+        Some(midi_file) => match midi_file.remove_event(track_index as usize, event_index as usize) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        },
+        None => -1,
+    }
+}
+
+/// Write a MIDI file, including any edits, to `file_path`
+#[no_mangle]
+pub extern "C" fn midi_file_save(file_handle: c_int, file_path: *const c_char) -> c_int {
+    if file_path.is_null() {
+        return -1;
+    }
+
+    let path = match unsafe { CStr::from_ptr(file_path) }.to_str() {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    match files.get(&file_handle) {
+        Some(midi_file) => match midi_file.save(path) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        },
+        None => -1,
+    }
+}
+
+/// Split a track into a new file with a conductor track (meta events and tempo map) followed
+/// by one track per channel used; channels with no events get no track. The new file's handle
+/// is written to `out_handle`. Returns 0 on success or -1 on error.
+#[no_mangle]
+pub extern "C" fn midi_file_split_channels(
+    file_handle: c_int,
+    track_index: c_int,
+    out_handle: *mut c_int,
+) -> c_int {
+    if track_index < 0 || out_handle.is_null() {
+        return -1;
+    }
+
+    let split = {
+        let files = match get_midi_file(file_handle) {
+            Some(guard) => guard,
+            None => return -1,
+        };
+        match files.get(&file_handle).map(|midi_file| midi_file.split_by_channel(track_index as usize)) {
+            Some(Ok(split)) => split,
+            _ => return -1,
+        }
+    };
+
+    // Registered after the file table lock is released
+    unsafe { *out_handle = register_midi_file(split) };
+    0
+}
+
+// ========== MUTE / SOLO ==========
+
+/// Mute (non-zero) or unmute a track. Muted tracks' channel events are left out of
+/// playback and export; their tempo and other meta events are kept.
+#[no_mangle]
+pub extern "C" fn midi_file_set_track_mute(file_handle: c_int, track_index: c_int, mute: u8) -> c_int {
+    if track_index < 0 {
+        return -1;
+    }
+
+    let mut files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    match files.get_mut(&file_handle) {
+        Some(midi_file) => match midi_file.set_track_mute(track_index as usize, mute != 0) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        },
+        None => -1,
+    }
+}
+
+/// Solo (non-zero) or unsolo a track. While any track is soloed, only soloed tracks' channel
+/// events are played and exported (a muted track stays silent even when soloed).
+#[no_mangle]
+pub extern "C" fn midi_file_set_track_solo(file_handle: c_int, track_index: c_int, solo: u8) -> c_int {
+    if track_index < 0 {
+        return -1;
+    }
+
+    let mut files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    match files.get_mut(&file_handle) {
+        Some(midi_file) => match midi_file.set_track_solo(track_index as usize, solo != 0) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        },
+        None => -1,
+    }
+}
+
+// ========== NOTE SPANS ==========
+
+/// Get the number of notes (paired note-on/off) in a track.
+/// With `apply_sustain` non-zero, releases under a held sustain pedal are extended to the pedal lift.
+#[no_mangle]
+pub extern "C" fn midi_file_get_note_span_count(
+    file_handle: c_int,
+    track_index: c_int,
+    apply_sustain: u8,
+) -> c_int {
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    if track_index < 0 || track_index as usize >= midi_file.tracks.len() {
+        return -1;
+    }
+
+    midi_file.note_spans(track_index as usize, apply_sustain != 0).len() as c_int
+}
+
+/// Get a note of a track by index (ordered by start tick)
+#[no_mangle]
+pub extern "C" fn midi_file_get_note_span(
+    file_handle: c_int,
+    track_index: c_int,
+    apply_sustain: u8,
+    span_index: c_int,
+    channel: *mut u8,
+    note: *mut u8,
+    velocity: *mut u8,
+    start_tick: *mut u32,
+    end_tick: *mut u32,
+) -> c_int {
+    if channel.is_null() || note.is_null() || velocity.is_null() || start_tick.is_null() || end_tick.is_null() {
+        return -1;
+    }
+
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    if track_index < 0 || track_index as usize >= midi_file.tracks.len() {
+        return -1;
+    }
+
+    let spans = midi_file.note_spans(track_index as usize, apply_sustain != 0);
+    if span_index < 0 || span_index as usize >= spans.len() {
+        return -1;
+    }
+
+    let span = &spans[span_index as usize];
+    unsafe {
+        *channel = span.channel;
+        *note = span.note;
+        *velocity = span.velocity;
+        *start_tick = span.start_tick;
+        *end_tick = span.end_tick;
+    }
+    0
+}
+
+// ========== NOTE EVENTS ==========
+
+/// Get the number of notes in a track as returned by `midi_file_get_note_events`
+#[no_mangle]
+pub extern "C" fn midi_file_get_note_event_count(file_handle: c_int, track_index: c_int) -> c_int {
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    if track_index < 0 || track_index as usize >= midi_file.tracks.len() {
+        return -1;
+    }
+
+    midi_file.note_events(track_index as usize).len() as c_int
+}
+
+/// Copy up to `count` notes of a track, starting at note `start`, into `out_array`. Each note
+/// carries its duration instead of separate note-on/off events; a note that is never released
+/// has duration 0xFFFFFFFF. Returns the number written (0 past the end) or -1.
+#[no_mangle]
+pub extern "C" fn midi_file_get_note_events(
+    file_handle: c_int,
+    track_index: c_int,
+    start: c_int,
+    count: c_int,
+    out_array: *mut NoteEvent,
+) -> c_int {
+    if out_array.is_null() || start < 0 || count < 0 {
+        return -1;
+    }
+    let files = match get_midi_file(file_handle) {
+        Some(guard) => guard,
+        None => return -1,
+    };
+    let midi_file = match files.get(&file_handle) {
+        Some(file) => file,
+        None => return -1,
+    };
+    if track_index < 0 || track_index as usize >= midi_file.tracks.len() {
+        return -1;
+    }
+
+    let notes = midi_file.note_events(track_index as usize);
+    let notes = notes.iter().skip(start as usize).take(count as usize);
+    let out_notes = unsafe { std::slice::from_raw_parts_mut(out_array, count as usize) };
+    let mut written = 0;
+    for (out_note, note) in out_notes.iter_mut().zip(notes) {
+        *out_note = *note;
+        written += 1;
+    }
+    written
+}
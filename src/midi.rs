@@ -1,153 +1,1195 @@
-use midir::{MidiInput, MidiOutput, MidiInputConnection, MidiOutputConnection};
-use std::sync::mpsc::{self, Receiver};
+use midir::{MidiIO, MidiInput, MidiInputPort, MidiOutput, MidiOutputPort, MidiInputConnection, MidiOutputConnection};
+use midir::{ConnectError, InitError, PortInfoError, SendError};
+use crate::labview_interop::errors::{LVInteropError, Result};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+impl From<InitError> for LVInteropError {
+    fn from(e: InitError) -> Self {
+        LVInteropError::MidiBackend(e.to_string())
+    }
+}
+
+impl From<PortInfoError> for LVInteropError {
+    fn from(e: PortInfoError) -> Self {
+        LVInteropError::MidiBackend(e.to_string())
+    }
+}
+
+impl<T> From<ConnectError<T>> for LVInteropError {
+    fn from(e: ConnectError<T>) -> Self {
+        LVInteropError::MidiBackend(e.to_string())
+    }
+}
+
+impl From<SendError> for LVInteropError {
+    fn from(e: SendError) -> Self {
+        LVInteropError::MidiBackend(e.to_string())
+    }
+}
+
+fn device_index_error(device_index: usize) -> LVInteropError {
+    LVInteropError::DeviceNotFound(format!("Device index {} out of range", device_index))
+}
+
+/// Channel must be 0-15 and both data bytes 0-127
+fn check_channel_message(channel: u8, data1: u8, data2: u8) -> Result<()> {
+    if channel > 0x0F {
+        return Err(LVInteropError::InvalidMessage(format!("Channel {} out of range (0-15)", channel)));
+    }
+    if let Some(value) = [data1, data2].into_iter().find(|value| *value > 0x7F) {
+        return Err(LVInteropError::InvalidMessage(format!("Data byte {} out of range (0-127)", value)));
+    }
+    Ok(())
+}
+
+/// Ports whose names contain one of these (case-insensitive) are hidden from enumeration
+/// by default: the ALSA loopback port and the ports of this library's own clients
+pub const DEFAULT_PORT_IGNORE_LIST: [&str; 2] = ["Midi Through", "LabVIEW MIDI"];
+
+static PORT_IGNORE_LIST: OnceLock<RwLock<Vec<String>>> = OnceLock::new();
+
+fn port_ignore_list() -> &'static RwLock<Vec<String>> {
+    PORT_IGNORE_LIST.get_or_init(|| {
+        RwLock::new(DEFAULT_PORT_IGNORE_LIST.iter().map(|pattern| pattern.to_string()).collect())
+    })
+}
+
+/// Replace the name patterns hidden from device enumeration; an empty list shows every port
+pub fn set_port_ignore_list(patterns: Vec<String>) {
+    let patterns = patterns.into_iter().filter(|pattern| !pattern.is_empty()).collect();
+    *port_ignore_list().write().unwrap() = patterns;
+}
+
+/// Check whether a port name contains any of the patterns (case-insensitive)
+fn matches_ignore_list(name: &str, patterns: &[String]) -> bool {
+    let name = name.to_lowercase();
+    patterns.iter().any(|pattern| name.contains(&pattern.to_lowercase()))
+}
+
+static SORT_DEVICES: AtomicBool = AtomicBool::new(false);
+
+/// Order devices by name instead of the backend's order, which some backends do not keep
+/// stable between calls. Identically named devices are ordered by port id. Applies to
+/// listing and connecting by index alike.
+pub fn set_sort_devices(enabled: bool) {
+    SORT_DEVICES.store(enabled, Ordering::Relaxed);
+}
+
+/// The ports that enumeration and connection by index work over: every port whose name can be
+/// read and is not on the ignore list, sorted by name if `set_sort_devices` is enabled.
+/// Device indices are positions in this list.
+fn visible_ports<T: MidiIO>(midi_io: &T) -> Vec<(T::Port, String)>
+where
+    T::Port: PortId,
+{
+    let patterns = port_ignore_list().read().unwrap();
+    let mut ports: Vec<(T::Port, String)> = midi_io.ports()
+        .into_iter()
+        .filter_map(|port| midi_io.port_name(&port).ok().map(|name| (port, name)))
+        .filter(|(_, name)| !matches_ignore_list(name, &patterns))
+        .collect();
+    if SORT_DEVICES.load(Ordering::Relaxed) {
+        sort_by_name_then_id(&mut ports, |port| port.port_id());
+    }
+    ports
+}
+
+/// Backend id of an input or output port, for code generic over both
+trait PortId {
+    fn port_id(&self) -> String;
+}
+
+impl PortId for MidiInputPort {
+    fn port_id(&self) -> String {
+        self.id()
+    }
+}
+
+impl PortId for MidiOutputPort {
+    fn port_id(&self) -> String {
+        self.id()
+    }
+}
+
+/// Sort ports by name, breaking ties (identical devices) on the port id so the order
+/// does not depend on the order the backend happened to list them in
+fn sort_by_name_then_id<P>(ports: &mut [(P, String)], id: impl Fn(&P) -> String) {
+    ports.sort_by_cached_key(|(port, name)| (name.clone(), id(port)));
+}
+
+static ZERO_VELOCITY_NOTE_OFF: AtomicBool = AtomicBool::new(true);
+
+/// Report Note On with velocity 0 as a Note Off (the MIDI convention, and the default).
+/// Disabling it keeps the raw Note On message type, e.g. to debug a device.
+pub fn set_zero_velocity_as_note_off(enabled: bool) {
+    ZERO_VELOCITY_NOTE_OFF.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether a Note On with velocity 0 should be reported as a Note Off
+pub fn zero_velocity_as_note_off() -> bool {
+    ZERO_VELOCITY_NOTE_OFF.load(Ordering::Relaxed)
+}
+
+static DEDUPE_NAMES: AtomicBool = AtomicBool::new(false);
+
+/// Number repeated device names (" #1", " #2", ...) in the device lists so identical devices
+/// can be told apart. Device indices are unchanged.
+pub fn set_dedupe_names(enabled: bool) {
+    DEDUPE_NAMES.store(enabled, Ordering::Relaxed);
+}
+
+/// Append " #N" to every name that appears more than once, numbering in port order
+fn dedupe_names(names: Vec<String>) -> Vec<String> {
+    let mut totals: HashMap<String, usize> = HashMap::new();
+    for name in &names {
+        *totals.entry(name.clone()).or_default() += 1;
+    }
+
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    names.into_iter()
+        .map(|name| {
+            if totals[&name] < 2 {
+                return name;
+            }
+            let occurrence = seen.entry(name.clone()).or_default();
+            *occurrence += 1;
+            format!("{} #{}", name, occurrence)
+        })
+        .collect()
+}
+
+/// A port name made safe to hand to C: embedded NUL bytes, which some drivers leave in
+/// their names and which cannot be part of a C string, are removed
+pub fn sanitize_device_name(name: &str) -> String {
+    name.replace('\0', "")
+}
+
+/// Names of the visible ports, sanitized and numbered if `set_dedupe_names` is enabled
+fn display_names<T>(ports: &[(T, String)]) -> Vec<String> {
+    let names = ports.iter().map(|(_, name)| sanitize_device_name(name)).collect();
+    if DEDUPE_NAMES.load(Ordering::Relaxed) {
+        dedupe_names(names)
+    } else {
+        names
+    }
+}
+
+static OPEN_PORTS: OnceLock<Mutex<HashSet<(PortDirection, String)>>> = OnceLock::new();
+
+fn open_ports() -> &'static Mutex<HashSet<(PortDirection, String)>> {
+    OPEN_PORTS.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Index of the first of the listed devices, or `NoDevices` if none is present
+fn first_device_index(devices: &[String], direction: PortDirection) -> Result<usize> {
+    if devices.is_empty() {
+        let kind = match direction {
+            PortDirection::Input => "input",
+            PortDirection::Output => "output",
+        };
+        return Err(LVInteropError::NoDevices(format!("no MIDI {} devices present", kind)));
+    }
+    Ok(0)
+}
+
+/// Key identifying a port for claims: the backend's port id, or the name when the backend
+/// reports none. Identically named devices have different ids, so each can be opened.
+fn port_key(id: String, port_name: &str) -> String {
+    if id.is_empty() { port_name.to_string() } else { id }
+}
+
+/// A port opened by a manager in this process. Opening the same port again on another
+/// handle fails with `PortInUse` instead of an opaque backend error from an exclusive
+/// driver. The port is released when the claim is dropped (with its manager).
+#[derive(Debug)]
+struct PortClaim {
+    direction: PortDirection,
+    key: String,
+}
+
+impl PortClaim {
+    fn acquire(direction: PortDirection, key: &str, port_name: &str) -> Result<Self> {
+        if !open_ports().lock().unwrap().insert((direction, key.to_string())) {
+            return Err(LVInteropError::PortInUse(port_name.to_string()));
+        }
+        Ok(PortClaim { direction, key: key.to_string() })
+    }
+
+    /// Claim a port unless `held` (the manager's current claim) already covers it.
+    /// Returns None when the existing claim should be kept.
+    fn acquire_unless_held(held: &Option<PortClaim>, direction: PortDirection, key: &str, port_name: &str) -> Result<Option<Self>> {
+        match held {
+            Some(claim) if claim.key == key => Ok(None),
+            _ => Self::acquire(direction, key, port_name).map(Some),
+        }
+    }
+}
+
+impl Drop for PortClaim {
+    fn drop(&mut self) {
+        open_ports().lock().unwrap().remove(&(self.direction, std::mem::take(&mut self.key)));
+    }
+}
+
+static CONNECT_RETRIES: AtomicU32 = AtomicU32::new(0);
+static CONNECT_RETRY_DELAY_MS: AtomicU32 = AtomicU32::new(100);
+
+/// Retry failed `connect_input`/`connect_output` calls up to `retries` more times, waiting
+/// `delay_ms` before the first retry and doubling the wait after each one. Only backend
+/// errors (busy or not yet ready devices) are retried; a bad device index fails at once.
+pub fn set_connect_retries(retries: u32, delay_ms: u32) {
+    CONNECT_RETRIES.store(retries, Ordering::Relaxed);
+    CONNECT_RETRY_DELAY_MS.store(delay_ms, Ordering::Relaxed);
+}
+
+/// Run a connection attempt, retrying transient failures as configured by `set_connect_retries`.
+/// Callers holding a lock should take it inside `connect`, so it is released while waiting.
+pub(crate) fn with_connect_retries<T>(connect: impl FnMut() -> Result<T>) -> Result<T> {
+    retry_connect(
+        CONNECT_RETRIES.load(Ordering::Relaxed),
+        Duration::from_millis(CONNECT_RETRY_DELAY_MS.load(Ordering::Relaxed) as u64),
+        connect,
+    )
+}
+
+/// Retry backend errors from `connect` up to `retries` times, starting at `delay` and doubling
+fn retry_connect<T>(retries: u32, mut delay: Duration, mut connect: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        match connect() {
+            Err(LVInteropError::MidiBackend(e)) if attempt < retries => {
+                attempt += 1;
+                log!(Warning, "MIDI connect failed ({}), retry {}/{} in {} ms", e, attempt, retries, delay.as_millis());
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Run a device enumeration on a worker thread, giving up after `timeout`. A driver that
+/// hangs leaves the worker blocked in the background, but the caller gets control back.
+fn enumerate_with_timeout<F>(timeout: Duration, enumerate: F) -> Result<Vec<String>>
+where
+    F: FnOnce() -> Result<Vec<String>> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(enumerate());
+    });
+    receiver.recv_timeout(timeout).map_err(|_| {
+        LVInteropError::Timeout(format!("Device enumeration took longer than {} ms", timeout.as_millis()))
+    })?
+}
+
+/// Whether a received message can be MIDI 1.0 at all. Devices that deliver MIDI 2.0 Universal
+/// MIDI Packets (32-bit words whose first byte is a message-type/group nibble pair) produce
+/// data that would otherwise be misparsed as channel voice messages: it starts with a data
+/// byte, is longer than its apparent status allows, or has status bytes in data positions.
+/// SysEx continuation chunks (which start with data bytes) must be handled before this check.
+pub fn is_midi1_message(message: &[u8]) -> bool {
+    let status = match message.first() {
+        Some(&status) if status >= 0x80 => status,
+        _ => return false,
+    };
+    let max_length = match status {
+        0xC0..=0xDF => 2,
+        0x80..=0xEF => 3,
+        0xF0 | 0xF7 => return true, // SysEx framing, reassembled by the listener
+        _ => 3,
+    };
+    message.len() <= max_length && message[1..].iter().all(|byte| *byte < 0x80)
+}
+
+/// Check that a message is well-formed MIDI before it is handed to the backend.
+/// Returns a description of the first problem found.
+pub fn validate_message(message: &[u8]) -> std::result::Result<(), String> {
+    match find_invalid_byte(message) {
+        Some((_, reason)) => Err(reason),
+        None => Ok(()),
+    }
+}
+
+/// Find the first invalid byte of a message. A missing byte is reported at the index it
+/// should have occupied, a surplus byte at its own index.
+pub(crate) fn find_invalid_byte(message: &[u8]) -> Option<(usize, String)> {
+    let status = match message.first() {
+        Some(&status) => status,
+        None => return Some((0, "Empty message".to_string())),
+    };
+
+    // Running status: the status byte is implied, so only one or two data bytes follow
+    if status < 0x80 {
+        if let Some(index) = message.iter().position(|&byte| byte >= 0x80) {
+            return Some((index, format!("Status byte 0x{:02X} inside running-status data", message[index])));
+        }
+        if message.len() > 2 {
+            return Some((2, "Running-status message has more than 2 data bytes".to_string()));
+        }
+        return None;
+    }
+
+    // SysEx: any number of data bytes, terminated by 0xF7
+    if status == 0xF0 {
+        let end = message.len().max(2) - 1;
+        if let Some(index) = message[1..end].iter().position(|&byte| byte >= 0x80) {
+            return Some((index + 1, format!("Status byte 0x{:02X} inside SysEx data", message[index + 1])));
+        }
+        if message.len() < 2 || message[end] != 0xF7 {
+            return Some((message.len(), "SysEx message is not terminated by 0xF7".to_string()));
+        }
+        return None;
+    }
+
+    let expected_len = match status {
+        0x80..=0xBF | 0xE0..=0xEF => 3,
+        0xC0..=0xDF => 2,
+        0xF1 | 0xF3 => 2,
+        0xF2 => 3,
+        0xF6 | 0xF8 | 0xFA | 0xFB | 0xFC | 0xFE | 0xFF => 1,
+        _ => return Some((0, format!("Undefined or unexpected status byte 0x{:02X}", status))),
+    };
+
+    if let Some(index) = message[1..].iter().take(expected_len - 1).position(|&byte| byte >= 0x80) {
+        return Some((index + 1, format!("Data byte {} is 0x{:02X} (must be 0-127)", index + 1, message[index + 1])));
+    }
+    if message.len() != expected_len {
+        let index = message.len().min(expected_len);
+        return Some((index, format!(
+            "Message with status 0x{:02X} must be {} bytes, got {}", status, expected_len, message.len()
+        )));
+    }
+    None
+}
+
+/// Stage at which the loopback self-test failed; the value is the FFI return code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestError {
+    Init = -1,
+    VirtualPort = -2,
+    Connect = -3,
+    Send = -4,
+    NoResponse = -5,
+    WrongMessage = -6,
+    NotSupported = -100,
+}
+
+/// Direction of a MIDI port, as seen from this library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PortDirection {
+    Input = 0,
+    Output = 1,
+}
+
+/// What can be learned about a MIDI port without connecting to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub name: String,
+    /// Software/virtual port (e.g. a software synth or loopback) rather than hardware
+    pub is_virtual: bool,
+    /// A port with the same name is available for input
+    pub has_input: bool,
+    /// A port with the same name is available for output
+    pub has_output: bool,
+}
+
+/// Expected output latency of a port, for warning about slow software synths
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyClass {
+    /// The backend says nothing either way
+    Unknown = 0,
+    /// A hardware port (interface or USB device): low latency
+    Hardware = 1,
+    /// A software port (synth, loopback): latency depends on the receiving application
+    Virtual = 2,
+}
+
+/// ALSA ids are "client:port"; other backends' ids don't have this form
+fn alsa_client(port_id: &str) -> Option<u32> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    port_id.split(':').next().and_then(|client| client.parse::<u32>().ok())
+}
+
+/// Best-effort guess whether a port is a software port, from its backend id and name
+fn is_virtual_port(port_id: &str, port_name: &str) -> bool {
+    // User-space ALSA clients are numbered from 128 and client 14 is the kernel's
+    // "Midi Through" loopback
+    if let Some(client) = alsa_client(port_id) {
+        return client >= 128 || client == 14;
+    }
+
+    // Other backends don't expose this, so fall back to well-known software ports
+    const SOFTWARE_PORTS: [&str; 5] = ["Microsoft GS Wavetable", "Midi Through", "IAC Driver", "loopMIDI", "Virtual"];
+    SOFTWARE_PORTS.iter().any(|software_port| port_name.contains(software_port))
+}
+
+/// Latency class of a port. Only ALSA tells hardware apart from software; elsewhere a port
+/// that is not a well-known software port is `Unknown` rather than assumed to be hardware.
+fn latency_class(port_id: &str, port_name: &str) -> LatencyClass {
+    if is_virtual_port(port_id, port_name) {
+        LatencyClass::Virtual
+    } else if alsa_client(port_id).is_some() {
+        LatencyClass::Hardware
+    } else {
+        LatencyClass::Unknown
+    }
+}
+
+/// How a `MessageFilter`'s status bytes are applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    /// Only listed status bytes pass (an empty list passes everything)
+    Whitelist = 0,
+    /// Listed status bytes are dropped
+    Blacklist = 1,
+}
+
+/// Status-byte filter shared between a manager's input callback and the FFI layer
+pub struct MessageFilter {
+    status_bytes: Mutex<Vec<u8>>,
+    blacklist: AtomicBool,
+    min_velocity: AtomicU8,
+}
+
+impl MessageFilter {
+    pub fn new(status_bytes: Vec<u8>) -> Self {
+        MessageFilter {
+            status_bytes: Mutex::new(status_bytes),
+            blacklist: AtomicBool::new(false),
+            min_velocity: AtomicU8::new(0),
+        }
+    }
+
+    /// Drop Note Ons softer than `min_velocity` (0 = pass all). Note Offs always pass.
+    pub fn set_min_velocity(&self, min_velocity: u8) {
+        self.min_velocity.store(min_velocity, Ordering::Relaxed);
+    }
+
+    pub fn set_status_bytes(&self, status_bytes: Vec<u8>) {
+        *self.status_bytes.lock().unwrap() = status_bytes;
+    }
+
+    pub fn set_mode(&self, mode: FilterMode) {
+        self.blacklist.store(mode == FilterMode::Blacklist, Ordering::Relaxed);
+    }
+
+    /// Whether a message with this status byte should be delivered
+    pub fn passes(&self, status_byte: u8) -> bool {
+        let status_bytes = self.status_bytes.lock().unwrap();
+        if self.blacklist.load(Ordering::Relaxed) {
+            !status_bytes.contains(&status_byte)
+        } else {
+            status_bytes.is_empty() || status_bytes.contains(&status_byte)
+        }
+    }
+
+    /// Whether a (non-empty) message should be delivered: the status filter, then the
+    /// Note On velocity threshold. Velocity 0 is a Note Off and is never dropped.
+    pub fn passes_message(&self, message: &[u8]) -> bool {
+        if !self.passes(message[0]) {
+            return false;
+        }
+        match (message[0] & 0xF0, message.get(2)) {
+            (0x90, Some(&velocity)) if velocity > 0 => velocity >= self.min_velocity.load(Ordering::Relaxed),
+            _ => true,
+        }
+    }
+}
+
+/// Paces outgoing messages to a maximum rate. Each sender reserves the next free slot and
+/// is told how long to wait for it, so the caller can sleep without holding any lock.
+pub struct OutputRateLimiter {
+    interval_us: AtomicU64,
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl OutputRateLimiter {
+    pub fn new() -> Self {
+        OutputRateLimiter {
+            interval_us: AtomicU64::new(0),
+            next_slot: Mutex::new(None),
+        }
+    }
+
+    /// Set the cap in messages per second (0 = unlimited)
+    pub fn set_rate(&self, messages_per_sec: u32) {
+        let interval_us = if messages_per_sec == 0 { 0 } else { 1_000_000 / messages_per_sec as u64 };
+        self.interval_us.store(interval_us, Ordering::Relaxed);
+        *self.next_slot.lock().unwrap() = None;
+    }
+
+    /// Reserve a send slot and return how long to wait before using it
+    pub fn reserve(&self) -> Duration {
+        let interval_us = self.interval_us.load(Ordering::Relaxed);
+        if interval_us == 0 {
+            return Duration::ZERO;
+        }
+
+        let now = Instant::now();
+        let mut next_slot = self.next_slot.lock().unwrap();
+        let slot = next_slot.map_or(now, |slot| slot.max(now));
+        *next_slot = Some(slot + Duration::from_micros(interval_us));
+        slot - now
+    }
+}
+
+impl Default for OutputRateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Default capacity in bytes of the receive ring used by `connect_input`
+pub const RECEIVE_RING_CAPACITY: usize = 64 * 1024;
+
+/// Fixed-capacity queue of incoming messages, stored length-prefixed in one preallocated
+/// buffer so the input callback never allocates. Messages that do not fit are dropped and
+/// counted (see `dropped`).
+pub struct MessageRing {
+    bytes: Mutex<VecDeque<u8>>,
+    capacity: usize,
+    dropped: AtomicU64,
+}
+
+impl MessageRing {
+    pub fn new(capacity: usize) -> Self {
+        MessageRing {
+            bytes: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Queue a message; returns false if it was dropped because the ring is full
+    pub fn push(&self, message: &[u8]) -> bool {
+        let mut bytes = self.bytes.lock().unwrap();
+        if message.len() > u16::MAX as usize || bytes.len() + 2 + message.len() > self.capacity {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        bytes.extend((message.len() as u16).to_le_bytes());
+        bytes.extend(message.iter().copied());
+        true
+    }
+
+    /// Number of messages dropped by `push` because the ring was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Pop the oldest message into `buffer` and return its length. A message longer than
+    /// `buffer` is discarded and its full length returned, so callers must check it.
+    pub fn pop_into(&self, buffer: &mut [u8]) -> Option<usize> {
+        let mut bytes = self.bytes.lock().unwrap();
+        let length = u16::from_le_bytes([bytes.pop_front()?, bytes.pop_front()?]) as usize;
+        let message = bytes.drain(..length);
+        if length <= buffer.len() {
+            for (slot, byte) in buffer.iter_mut().zip(message) {
+                *slot = byte;
+            }
+        }
+        Some(length)
+    }
+
+    /// Move as many queued messages as fit into `buffer`, each as a 2-byte little-endian
+    /// length followed by the message bytes (the ring's own framing). Messages that don't fit
+    /// stay queued for the next call, except one too long for the whole buffer, which is
+    /// discarded. Returns the bytes written and the number of messages.
+    pub fn drain_into(&self, buffer: &mut [u8]) -> (usize, usize) {
+        let mut bytes = self.bytes.lock().unwrap();
+        let (mut written, mut count) = (0, 0);
+        while bytes.len() >= 2 {
+            let frame_length = 2 + u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+            if frame_length > buffer.len() {
+                bytes.drain(..frame_length);
+                continue;
+            }
+            if written + frame_length > buffer.len() {
+                break;
+            }
+            for (slot, byte) in buffer[written..written + frame_length].iter_mut().zip(bytes.drain(..frame_length)) {
+                *slot = byte;
+            }
+            written += frame_length;
+            count += 1;
+        }
+        (written, count)
+    }
+}
+
+/// How long an input may stay silent before `input_is_alive` checks the port list
+pub const INPUT_SILENCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Time of the last message seen on an input connection, updated from midir's callback
+pub struct InputActivity {
+    connected_at: Instant,
+    last_message_us: AtomicU64, // Since `connected_at`
+}
+
+impl InputActivity {
+    pub fn new() -> Self {
+        InputActivity {
+            connected_at: Instant::now(),
+            last_message_us: AtomicU64::new(0),
+        }
+    }
+
+    /// Record that a message just arrived
+    pub fn touch(&self) {
+        let elapsed_us = self.connected_at.elapsed().as_micros() as u64;
+        self.last_message_us.store(elapsed_us, Ordering::Relaxed);
+    }
+
+    /// Time since the last message (or since connecting, if none has arrived)
+    pub fn silent_for(&self) -> Duration {
+        let last_message = Duration::from_micros(self.last_message_us.load(Ordering::Relaxed));
+        self.connected_at.elapsed().saturating_sub(last_message)
+    }
+}
+
+impl Default for InputActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub struct MidiManager {
     input_connection: Option<MidiInputConnection<()>>,
+    input_port_name: Option<String>,
     output_connection: Option<MidiOutputConnection>,
-    message_receiver: Option<Receiver<Vec<u8>>>,
+    output_port_name: Option<String>,
+    output_latency: LatencyClass,
+    input_claim: Option<PortClaim>,
+    output_claim: Option<PortClaim>,
+    input_ring: Option<Arc<MessageRing>>,
+    input_activity: Arc<InputActivity>,
+    filter: Arc<MessageFilter>,
+    rate_limiter: Arc<OutputRateLimiter>,
+    claims_ports: bool,
 }
 
 impl MidiManager {
     pub fn new() -> Self {
         MidiManager {
             input_connection: None,
+            input_port_name: None,
             output_connection: None,
-            message_receiver: None,
+            output_port_name: None,
+            output_latency: LatencyClass::Unknown,
+            input_claim: None,
+            output_claim: None,
+            input_ring: None,
+            input_activity: Arc::new(InputActivity::new()),
+            filter: Arc::new(MessageFilter::new(Vec::new())),
+            rate_limiter: Arc::new(OutputRateLimiter::new()),
+            claims_ports: true,
+        }
+    }
+
+    /// A manager that opens ports without claiming them, for the library's own playback and
+    /// recording managers. These run alongside a handle the caller already has open on the
+    /// same port, so they are exempt from `PortInUse`; on an exclusive driver the second open
+    /// still fails with the backend's error.
+    pub(crate) fn unclaimed() -> Self {
+        MidiManager { claims_ports: false, ..Self::new() }
+    }
+
+    fn claim(&self, held: &Option<PortClaim>, direction: PortDirection, key: String, port_name: &str) -> Result<Option<PortClaim>> {
+        if !self.claims_ports {
+            return Ok(None);
         }
+        PortClaim::acquire_unless_held(held, direction, &key, port_name)
     }
 
     // List all available MIDI input devices
-    pub fn list_input_devices(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    pub fn list_input_devices(&self) -> Result<Vec<String>> {
         let midi_in = MidiInput::new("LabVIEW MIDI Input")?;
-        let ports = midi_in.ports();
-        let mut device_names = Vec::new();
-        
-        for port in &ports {
-            if let Ok(name) = midi_in.port_name(port) {
-                device_names.push(name);
-            }
-        }
-        
-        Ok(device_names)
+        Ok(display_names(&visible_ports(&midi_in)))
+    }
+
+    /// List MIDI input devices, failing with `Timeout` if the backend blocks for longer than `timeout`
+    pub fn list_input_devices_timeout(&self, timeout: Duration) -> Result<Vec<String>> {
+        enumerate_with_timeout(timeout, || MidiManager::new().list_input_devices())
+    }
+
+    /// List MIDI output devices, failing with `Timeout` if the backend blocks for longer than `timeout`
+    pub fn list_output_devices_timeout(&self, timeout: Duration) -> Result<Vec<String>> {
+        enumerate_with_timeout(timeout, || MidiManager::new().list_output_devices())
     }
 
     // List all available MIDI output devices
-    pub fn list_output_devices(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    pub fn list_output_devices(&self) -> Result<Vec<String>> {
         let midi_out = MidiOutput::new("LabVIEW MIDI Output")?;
-        let ports = midi_out.ports();
-        let mut device_names = Vec::new();
-        
-        for port in &ports {
-            if let Ok(name) = midi_out.port_name(port) {
-                device_names.push(name);
+        Ok(display_names(&visible_ports(&midi_out)))
+    }
+
+    /// Describe a MIDI device by direction and index
+    pub fn describe_device(
+        &self,
+        direction: PortDirection,
+        device_index: usize,
+    ) -> Result<DeviceInfo> {
+        let (name, port_id, display_name) = match direction {
+            PortDirection::Input => {
+                let midi_in = MidiInput::new("LabVIEW MIDI Input")?;
+                let ports = visible_ports(&midi_in);
+                let display_name = display_names(&ports).into_iter().nth(device_index)
+                    .ok_or_else(|| device_index_error(device_index))?;
+                let (port, name) = &ports[device_index];
+                (name.clone(), port.id(), display_name)
             }
-        }
-        
-        Ok(device_names)
+            PortDirection::Output => {
+                let midi_out = MidiOutput::new("LabVIEW MIDI Output")?;
+                let ports = visible_ports(&midi_out);
+                let display_name = display_names(&ports).into_iter().nth(device_index)
+                    .ok_or_else(|| device_index_error(device_index))?;
+                let (port, name) = &ports[device_index];
+                (name.clone(), port.id(), display_name)
+            }
+        };
+
+        // Compare display names, so a numbered duplicate matches its own other half
+        let has_input = self.list_input_devices()?.contains(&display_name);
+        let has_output = self.list_output_devices()?.contains(&display_name);
+        Ok(DeviceInfo {
+            is_virtual: is_virtual_port(&port_id, &name),
+            name: display_name,
+            has_input,
+            has_output,
+        })
     }
 
-    // Connect to a MIDI input device by index
-    pub fn connect_input(&mut self, device_index: usize) -> Result<(), Box<dyn std::error::Error>> {
+    /// Backend identifier of an input port (e.g. the ALSA "client:port" address or the
+    /// CoreMIDI unique ID), falling back to the port name when the backend reports none
+    pub fn input_port_id(&self, device_index: usize) -> Result<String> {
         let midi_in = MidiInput::new("LabVIEW MIDI Input")?;
-        let ports = midi_in.ports();
-        
-        if device_index >= ports.len() {
-            return Err("Device index out of range".into());
-        }
+        let (port, name) = visible_ports(&midi_in).into_iter().nth(device_index)
+            .ok_or_else(|| device_index_error(device_index))?;
+        Ok(port_key(port.id(), &name))
+    }
 
-        let port = &ports[device_index];
-        let port_name = midi_in.port_name(port)?;
+    /// Index of the input device listed as `name` by `list_input_devices`, if there is one
+    pub fn find_input_device(&self, name: &str) -> Result<Option<usize>> {
+        Ok(self.list_input_devices()?.iter().position(|listed| listed == name))
+    }
+
+    /// Index of the output device listed as `name` by `list_output_devices`, if there is one
+    pub fn find_output_device(&self, name: &str) -> Result<Option<usize>> {
+        Ok(self.list_output_devices()?.iter().position(|listed| listed == name))
+    }
+
+    /// Connect to the first input device, for setups with a single device.
+    /// Fails with `NoDevices` (rather than `DeviceNotFound`) when no input is present.
+    pub fn connect_first_input(&mut self) -> Result<()> {
+        with_connect_retries(|| self.try_connect_first_input())
+    }
+
+    /// Connect to the first output device; see `connect_first_input`
+    pub fn connect_first_output(&mut self) -> Result<()> {
+        with_connect_retries(|| self.try_connect_first_output())
+    }
+
+    /// One attempt of `connect_first_input`, without retries
+    pub(crate) fn try_connect_first_input(&mut self) -> Result<()> {
+        let index = first_device_index(&self.list_input_devices()?, PortDirection::Input)?;
+        self.try_connect_input(index)
+    }
+
+    /// One attempt of `connect_first_output`, without retries
+    pub(crate) fn try_connect_first_output(&mut self) -> Result<()> {
+        let index = first_device_index(&self.list_output_devices()?, PortDirection::Output)?;
+        self.try_connect_output(index)
+    }
+
+    // Connect to a MIDI input device by index
+    pub fn connect_input(&mut self, device_index: usize) -> Result<()> {
+        with_connect_retries(|| self.try_connect_input(device_index))
+    }
+
+    /// One attempt of `connect_input`, without retries
+    pub(crate) fn try_connect_input(&mut self, device_index: usize) -> Result<()> {
+        let midi_in = MidiInput::new("LabVIEW MIDI Input")?;
+        let (port, port_name) = visible_ports(&midi_in).into_iter().nth(device_index)
+            .ok_or_else(|| device_index_error(device_index))?;
+        let claim = self.claim(&self.input_claim, PortDirection::Input, port_key(port.id(), &port_name), &port_name)?;
         
-        // Create a channel to receive MIDI messages
-        let (sender, receiver) = mpsc::channel();
+        // Queue incoming messages in a preallocated ring
+        let ring = Arc::new(MessageRing::new(RECEIVE_RING_CAPACITY));
+        let callback_ring = ring.clone();
+        let filter = self.filter.clone();
+        let activity = Arc::new(InputActivity::new());
+        let callback_activity = activity.clone();
         
         // Connect to the input port with a callback
-        let connection = midi_in.connect(port, &port_name, 
+        let connection = midi_in.connect(&port, &port_name, 
             move |_timestamp, message, _| {
-                // Send the MIDI message through the channel
-                let _ = sender.send(message.to_vec());
+                callback_activity.touch();
+                // A full ring drops the message; the drop is counted for `midi_get_dropped_input_count`
+                if !message.is_empty() && filter.passes_message(message) {
+                    callback_ring.push(message);
+                }
             }, 
             ()
         )?;
 
         self.input_connection = Some(connection);
-        self.message_receiver = Some(receiver);
+        self.input_ring = Some(ring);
+        self.input_activity = activity;
+        if claim.is_some() {
+            self.input_claim = claim;
+        }
         
-        println!("Connected to MIDI input: {}", port_name);
+        log!(Info, "Connected to MIDI input: {}", port_name);
+        self.input_port_name = Some(port_name);
         Ok(())
     }
 
-    /// Connect to a MIDI input device with a direct callback
-    /// This bypasses the channel system and calls the callback directly
+    /// Connect to a MIDI input device with a direct callback, for push-style delivery
+    /// instead of polling `receive_message`.
+    ///
+    /// The callback runs on midir's input thread with each message and midir's timestamp
+    /// in microseconds. The message is borrowed from midir's buffer; copy it if it must
+    /// outlive the call. The status filter is not applied on this path.
     pub fn connect_input_with_callback<F>(
         &mut self, 
         device_index: usize,
         callback: F
-    ) -> Result<(), Box<dyn std::error::Error>>
+    ) -> Result<()>
     where
-        F: Fn(Vec<u8>) + Send + 'static,
+        F: FnMut(&[u8], u64) + Send + 'static,
     {
         let midi_in = MidiInput::new("LabVIEW MIDI Input")?;
-        let ports = midi_in.ports();
+        let (port, port_name) = visible_ports(&midi_in).into_iter().nth(device_index)
+            .ok_or_else(|| device_index_error(device_index))?;
+        let claim = self.claim(&self.input_claim, PortDirection::Input, port_key(port.id(), &port_name), &port_name)?;
+
+        self.open_input_with_callback(midi_in, &port, &port_name, callback)?;
+        if claim.is_some() {
+            self.input_claim = claim;
+        }
         
-        if device_index >= ports.len() {
-            return Err("Device index out of range".into());
+        log!(Info, "Connected to MIDI input with callback: {}", port_name);
+        self.input_port_name = Some(port_name);
+        Ok(())
+    }
+
+    /// Close the input connection and open the same port again (matched by name) with a new
+    /// callback. Closing joins midir's input thread, so the old callback has finished running
+    /// by the time the new one is installed.
+    pub fn reconnect_input_with_callback<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut(&[u8], u64) + Send + 'static,
+    {
+        let port_name = match self.input_port_name {
+            Some(ref name) => name.clone(),
+            None => return Err(LVInteropError::NotConnected("No input device was previously connected".to_string())),
+        };
+
+        if let Some(connection) = self.input_connection.take() {
+            connection.close();
         }
 
-        let port = &ports[device_index];
-        let port_name = midi_in.port_name(port)?;
-        
+        let midi_in = MidiInput::new("LabVIEW MIDI Input")?;
+        let port = midi_in.ports()
+            .into_iter()
+            .find(|port| midi_in.port_name(port).is_ok_and(|name| name == port_name))
+            .ok_or_else(|| LVInteropError::DeviceNotFound(port_name.clone()))?;
+
+        self.open_input_with_callback(midi_in, &port, &port_name, callback)?;
+
+        log!(Info, "Reconnected to MIDI input with callback: {}", port_name);
+        Ok(())
+    }
+
+    fn open_input_with_callback<F>(
+        &mut self,
+        midi_in: MidiInput,
+        port: &MidiInputPort,
+        port_name: &str,
+        mut callback: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&[u8], u64) + Send + 'static,
+    {
+        let activity = Arc::new(InputActivity::new());
+        let callback_activity = activity.clone();
+
         // Connect with direct callback - no channel needed
-        let connection = midi_in.connect(port, &port_name, 
-            move |_timestamp, message, _| {
+        let connection = midi_in.connect(port, port_name, 
+            move |timestamp, message, _| {
+                callback_activity.touch();
                 // Call the callback directly with the MIDI message
-                callback(message.to_vec());
+                callback(message, timestamp);
             }, 
             ()
         )?;
 
         self.input_connection = Some(connection);
-        
-        println!("Connected to MIDI input with callback: {}", port_name);
+        self.input_activity = activity;
+        Ok(())
+    }
+
+    /// Close the input connection (joining midir's input thread) and discard anything still
+    /// queued, keeping the manager for a later connect. The port is released to other handles.
+    /// Fails with `NotConnected` if no input is open.
+    pub fn close_input(&mut self) -> Result<()> {
+        let connection = self.input_connection.take()
+            .ok_or_else(|| LVInteropError::NotConnected("No input device connected".to_string()))?;
+        connection.close();
+        self.input_ring = None;
+        self.input_claim = None;
+        log!(Info, "Closed MIDI input: {}", self.input_port_name.as_deref().unwrap_or_default());
+        Ok(())
+    }
+
+    /// Close the output connection, keeping the manager for a later connect.
+    /// Fails with `NotConnected` if no output is open.
+    pub fn close_output(&mut self) -> Result<()> {
+        let connection = self.output_connection.take()
+            .ok_or_else(|| LVInteropError::NotConnected("No output device connected".to_string()))?;
+        connection.close();
+        self.output_claim = None;
+        self.output_latency = LatencyClass::Unknown;
+        log!(Info, "Closed MIDI output: {}", self.output_port_name.as_deref().unwrap_or_default());
         Ok(())
     }
 
     // Connect to a MIDI output device by index
-    pub fn connect_output(&mut self, device_index: usize) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn connect_output(&mut self, device_index: usize) -> Result<()> {
+        with_connect_retries(|| self.try_connect_output(device_index))
+    }
+
+    /// One attempt of `connect_output`, without retries
+    pub(crate) fn try_connect_output(&mut self, device_index: usize) -> Result<()> {
         let midi_out = MidiOutput::new("LabVIEW MIDI Output")?;
-        let ports = midi_out.ports();
+        let (port, port_name) = visible_ports(&midi_out).into_iter().nth(device_index)
+            .ok_or_else(|| device_index_error(device_index))?;
+        let claim = self.claim(&self.output_claim, PortDirection::Output, port_key(port.id(), &port_name), &port_name)?;
         
-        if device_index >= ports.len() {
-            return Err("Device index out of range".into());
+        let latency = latency_class(&port.id(), &port_name);
+        let connection = midi_out.connect(&port, &port_name)?;
+        self.output_connection = Some(connection);
+        self.output_latency = latency;
+        if claim.is_some() {
+            self.output_claim = claim;
         }
-
-        let port = &ports[device_index];
-        let port_name = midi_out.port_name(port)?;
         
-        let connection = midi_out.connect(port, &port_name)?;
+        log!(Info, "Connected to MIDI output: {}", port_name);
+        self.output_port_name = Some(port_name);
+        Ok(())
+    }
+
+    /// Re-open the last connected MIDI output device.
+    /// The port is matched by name because device indices shift when devices come and go.
+    pub fn reconnect_output(&mut self) -> Result<()> {
+        let port_name = match self.output_port_name {
+            Some(ref name) => name.clone(),
+            None => return Err(LVInteropError::NotConnected("No output device was previously connected".to_string())),
+        };
+
+        // Release the stale connection before re-opening the port
+        if let Some(connection) = self.output_connection.take() {
+            connection.close();
+        }
+
+        let midi_out = MidiOutput::new("LabVIEW MIDI Output")?;
+        let port = midi_out.ports()
+            .into_iter()
+            .find(|port| midi_out.port_name(port).is_ok_and(|name| name == port_name))
+            .ok_or_else(|| LVInteropError::DeviceNotFound(port_name.clone()))?;
+
+        let connection = midi_out.connect(&port, &port_name)?;
         self.output_connection = Some(connection);
-        
-        println!("Connected to MIDI output: {}", port_name);
+
+        log!(Info, "Reconnected to MIDI output: {}", port_name);
         Ok(())
     }
 
-    // Send a MIDI message
-    pub fn send_message(&mut self, message: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(ref mut conn) = self.output_connection {
-            conn.send(message)?;
-            Ok(())
-        } else {
-            Err("No output device connected".into())
+    // Send a MIDI message, reconnecting once if the output device has dropped out
+    pub fn send_message(&mut self, message: &[u8]) -> Result<()> {
+        self.send_message_timed(message).map(|_| ())
+    }
+
+    /// Send a MIDI message and return how long the backend took to accept it.
+    /// Only the `send` call itself is timed; reconnection and any locking by the caller are excluded.
+    pub fn send_message_timed(&mut self, message: &[u8]) -> Result<Duration> {
+        validate_message(message).map_err(LVInteropError::InvalidMessage)?;
+
+        match self.output_connection {
+            Some(ref mut conn) => {
+                let start = Instant::now();
+                if conn.send(message).is_ok() {
+                    return Ok(start.elapsed());
+                }
+            }
+            None => return Err(LVInteropError::NotConnected("No output device connected".to_string())),
+        }
+
+        log!(Warning, "MIDI output send failed, reconnecting");
+        self.reconnect_output()?;
+        match self.output_connection {
+            Some(ref mut conn) => {
+                let start = Instant::now();
+                conn.send(message)?;
+                Ok(start.elapsed())
+            }
+            None => Err(LVInteropError::NotConnected("No output device connected".to_string())),
         }
     }
 
-    // Check for incoming MIDI messages (non-blocking)
-    pub fn receive_message(&self) -> Option<Vec<u8>> {
-        if let Some(ref receiver) = self.message_receiver {
-            receiver.try_recv().ok()
-        } else {
-            None
+    /// Status-byte filter applied to incoming messages (shared with any event listener)
+    pub fn filter(&self) -> &Arc<MessageFilter> {
+        &self.filter
+    }
+
+    /// Output pacing shared with the FFI layer, which waits on it outside the manager lock
+    pub fn rate_limiter(&self) -> &Arc<OutputRateLimiter> {
+        &self.rate_limiter
+    }
+
+    /// Queue of the connected input (if any), shared with the FFI layer so it can be read
+    /// outside the manager lock
+    pub fn input_ring(&self) -> Option<&Arc<MessageRing>> {
+        self.input_ring.as_ref()
+    }
+
+    /// Whether the input device still appears to be connected. midir does not report
+    /// unplugging, so an input counts as lost only once it has been silent for
+    /// `INPUT_SILENCE_TIMEOUT` and its port is no longer listed.
+    pub fn input_is_alive(&self) -> bool {
+        let port_name = match self.connected_input_name() {
+            Some(name) => name,
+            None => return false,
+        };
+        if self.input_activity.silent_for() < INPUT_SILENCE_TIMEOUT {
+            return true;
+        }
+
+        // Only enumerate ports once the input has gone quiet (by raw name, ignoring numbering)
+        match MidiInput::new("LabVIEW MIDI Input") {
+            Ok(midi_in) => visible_ports(&midi_in).iter().any(|(_, name)| name == port_name),
+            Err(_) => false,
+        }
+    }
+
+    /// Name of the connected MIDI input port, if any
+    pub fn connected_input_name(&self) -> Option<&str> {
+        match self.input_connection {
+            Some(_) => self.input_port_name.as_deref(),
+            None => None,
+        }
+    }
+
+    /// Best-effort latency class of the connected output, from its port metadata.
+    /// `Unknown` when nothing is connected or the backend gives no hint.
+    pub fn output_latency_hint(&self) -> LatencyClass {
+        match self.output_connection {
+            Some(_) => self.output_latency,
+            None => LatencyClass::Unknown,
+        }
+    }
+
+    /// Name of the connected MIDI output port, if any
+    pub fn connected_output_name(&self) -> Option<&str> {
+        match self.output_connection {
+            Some(_) => self.output_port_name.as_deref(),
+            None => None,
+        }
+    }
+
+    /// Check for an incoming MIDI message (non-blocking), copying it into a caller-owned
+    /// (reusable) buffer without allocating.
+    /// Returns the message length; if it exceeds `buffer.len()` the message was discarded.
+    pub fn receive_message(&self, buffer: &mut [u8]) -> Option<usize> {
+        self.input_ring.as_ref()?.pop_into(buffer)
+    }
+
+    /// Send a typed message
+    pub fn send(&mut self, message: &MidiMessage) -> Result<()> {
+        self.send_message(&message.to_bytes())
+    }
+
+    /// Take the next incoming message (non-blocking), decoded. None if nothing is queued;
+    /// a queued message that is not valid MIDI 1.0 (e.g. a SysEx chunk) is an error.
+    pub fn receive(&self) -> Option<Result<MidiMessage>> {
+        let mut buffer = [0u8; u16::MAX as usize];
+        let length = self.receive_message(&mut buffer)?;
+        Some(MidiMessage::parse(&buffer[..length]))
+    }
+
+    /// Send a message and wait for it to arrive back on the connected input, returning what
+    /// was received. Needs the input and output connected to a loopback pair (e.g. ALSA's
+    /// "Midi Through" port, or a loopMIDI/IAC bus); the echo is expected to match byte for byte.
+    /// Anything already queued on the input is discarded before sending.
+    #[cfg(test)]
+    pub fn send_and_echo(&mut self, message: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        if self.input_ring.is_none() {
+            return Err(LVInteropError::NotConnected("No input device connected".to_string()));
         }
+
+        let mut buffer = [0u8; u16::MAX as usize];
+        while self.receive_message(&mut buffer).is_some() {}
+
+        self.send_message(message)?;
+
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            match self.receive_message(&mut buffer) {
+                Some(length) if length <= buffer.len() => return Ok(buffer[..length].to_vec()),
+                _ => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        Err(LVInteropError::Timeout(format!("No echo within {} ms", timeout.as_millis())))
+    }
+
+    /// Send a Note On through a virtual input port and check that it comes back intact.
+    /// Virtual ports are available on every platform but Windows.
+    #[cfg(unix)]
+    pub fn self_test() -> std::result::Result<(), SelfTestError> {
+        use midir::os::unix::VirtualInput;
+
+        let port_name = format!("LabVIEW MIDI Self Test {}", std::process::id());
+        let test_message = Self::note_on(0, 60, 100);
+
+        let midi_in = MidiInput::new("LabVIEW MIDI Self Test").map_err(|_| SelfTestError::Init)?;
+        let midi_out = MidiOutput::new("LabVIEW MIDI Self Test").map_err(|_| SelfTestError::Init)?;
+
+        let (sender, receiver) = mpsc::channel();
+        let _virtual_input = midi_in
+            .create_virtual(&port_name, move |_timestamp, message, _| {
+                let _ = sender.send(message.to_vec());
+            }, ())
+            .map_err(|_| SelfTestError::VirtualPort)?;
+
+        // Backends decorate port names (e.g. with client/port numbers), so match by substring
+        let port = midi_out.ports()
+            .into_iter()
+            .find(|port| midi_out.port_name(port).is_ok_and(|name| name.contains(&port_name)))
+            .ok_or(SelfTestError::Connect)?;
+        let mut connection = midi_out.connect(&port, &port_name).map_err(|_| SelfTestError::Connect)?;
+
+        connection.send(&test_message).map_err(|_| SelfTestError::Send)?;
+
+        let received = receiver
+            .recv_timeout(Duration::from_secs(1))
+            .map_err(|_| SelfTestError::NoResponse)?;
+        if received != test_message || validate_message(&received).is_err() {
+            return Err(SelfTestError::WrongMessage);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn self_test() -> std::result::Result<(), SelfTestError> {
+        Err(SelfTestError::NotSupported)
     }
 
     // Helper function to create common MIDI messages
@@ -159,7 +1201,551 @@ impl MidiManager {
         vec![0x80 | (channel & 0x0F), note & 0x7F, velocity & 0x7F]
     }
 
+    /// A true Note Off (`0x8n`) with release velocity 0. This is not the same as
+    /// `note_on(channel, note, 0)`: the spec treats both as "note off", but some synths only
+    /// apply release-velocity envelopes to `0x8n`, so pick whichever the receiver expects.
+    pub fn note_off_zero(channel: u8, note: u8) -> Vec<u8> {
+        Self::note_off(channel, note, 0)
+    }
+
     pub fn control_change(channel: u8, controller: u8, value: u8) -> Vec<u8> {
         vec![0xB0 | (channel & 0x0F), controller & 0x7F, value & 0x7F]
     }
-}
\ No newline at end of file
+
+    /// Like `note_on`, but an out-of-range channel, note or velocity is an error instead of
+    /// being masked into range (note 200 would otherwise silently become note 72)
+    pub fn try_note_on(channel: u8, note: u8, velocity: u8) -> Result<Vec<u8>> {
+        check_channel_message(channel, note, velocity)?;
+        Ok(Self::note_on(channel, note, velocity))
+    }
+
+    pub fn try_note_off(channel: u8, note: u8, velocity: u8) -> Result<Vec<u8>> {
+        check_channel_message(channel, note, velocity)?;
+        Ok(Self::note_off(channel, note, velocity))
+    }
+
+    pub fn try_control_change(channel: u8, controller: u8, value: u8) -> Result<Vec<u8>> {
+        check_channel_message(channel, controller, value)?;
+        Ok(Self::control_change(channel, controller, value))
+    }
+
+    /// Song Position Pointer: `F2 lsb msb`, position in MIDI beats (sixteenth notes), 0-16383
+    pub fn song_position_pointer(position: u16) -> Vec<u8> {
+        let position = position & 0x3FFF;
+        vec![0xF2, (position & 0x7F) as u8, (position >> 7) as u8]
+    }
+
+    /// Song Select: `F3 song`
+    pub fn song_select(song: u8) -> Vec<u8> {
+        vec![0xF3, song & 0x7F]
+    }
+
+    /// MIDI Machine Control command as universal real-time SysEx:
+    /// `F0 7F <device_id> 06 <command> F7`, where device ID 7F addresses all devices
+    pub fn mmc(device_id: u8, command: MmcCommand) -> Vec<u8> {
+        vec![0xF0, 0x7F, device_id & 0x7F, 0x06, command as u8, 0xF7]
+    }
+}
+
+impl Default for MidiManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// MIDI Machine Control transport commands (the value is the MMC command byte)
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmcCommand {
+    Stop = 0x01,
+    Play = 0x02,
+    DeferredPlay = 0x03,
+    FastForward = 0x04,
+    Rewind = 0x05,
+    RecordStrobe = 0x06,
+    RecordExit = 0x07,
+    Pause = 0x09,
+}
+
+impl MmcCommand {
+    pub fn from_byte(command: u8) -> Option<Self> {
+        match command {
+            0x01 => Some(MmcCommand::Stop),
+            0x02 => Some(MmcCommand::Play),
+            0x03 => Some(MmcCommand::DeferredPlay),
+            0x04 => Some(MmcCommand::FastForward),
+            0x05 => Some(MmcCommand::Rewind),
+            0x06 => Some(MmcCommand::RecordStrobe),
+            0x07 => Some(MmcCommand::RecordExit),
+            0x09 => Some(MmcCommand::Pause),
+            _ => None,
+        }
+    }
+}
+
+/// A complete MIDI 1.0 message. Channels are 0-15 and data values 0-127.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    /// Velocity 0 means Note Off by convention; see `is_note_off`
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    PolyAftertouch { channel: u8, note: u8, pressure: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelPressure { channel: u8, pressure: u8 },
+    /// 14-bit value, 8192 = centre
+    PitchBend { channel: u8, value: u16 },
+    /// The whole message, from 0xF0 to 0xF7
+    SysEx(Vec<u8>),
+    MtcQuarterFrame(u8),
+    /// Position in MIDI beats (sixteenth notes), 0-16383
+    SongPosition(u16),
+    SongSelect(u8),
+    TuneRequest,
+    TimingClock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    SystemReset,
+}
+
+impl MidiMessage {
+    /// Decode a complete message. Running status (no status byte), truncated or otherwise
+    /// malformed messages are an `InvalidMessage` error.
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        validate_message(bytes).map_err(LVInteropError::InvalidMessage)?;
+        let status = bytes[0];
+        if status < 0x80 {
+            return Err(LVInteropError::InvalidMessage("Running-status message has no status byte".to_string()));
+        }
+
+        let channel = status & 0x0F;
+        let data = |index: usize| bytes[index];
+        let message = match status {
+            0x80..=0x8F => MidiMessage::NoteOff { channel, note: data(1), velocity: data(2) },
+            0x90..=0x9F => MidiMessage::NoteOn { channel, note: data(1), velocity: data(2) },
+            0xA0..=0xAF => MidiMessage::PolyAftertouch { channel, note: data(1), pressure: data(2) },
+            0xB0..=0xBF => MidiMessage::ControlChange { channel, controller: data(1), value: data(2) },
+            0xC0..=0xCF => MidiMessage::ProgramChange { channel, program: data(1) },
+            0xD0..=0xDF => MidiMessage::ChannelPressure { channel, pressure: data(1) },
+            0xE0..=0xEF => MidiMessage::PitchBend { channel, value: (data(2) as u16) << 7 | data(1) as u16 },
+            0xF0 => MidiMessage::SysEx(bytes.to_vec()),
+            0xF1 => MidiMessage::MtcQuarterFrame(data(1)),
+            0xF2 => MidiMessage::SongPosition((data(2) as u16) << 7 | data(1) as u16),
+            0xF3 => MidiMessage::SongSelect(data(1)),
+            0xF6 => MidiMessage::TuneRequest,
+            0xF8 => MidiMessage::TimingClock,
+            0xFA => MidiMessage::Start,
+            0xFB => MidiMessage::Continue,
+            0xFC => MidiMessage::Stop,
+            0xFE => MidiMessage::ActiveSensing,
+            _ => MidiMessage::SystemReset, // validate_message allows no other status
+        };
+        Ok(message)
+    }
+
+    /// The raw bytes of the message. Out-of-range values are masked into range.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let status = |kind: u8, channel: u8| kind | (channel & 0x0F);
+        match self {
+            MidiMessage::NoteOff { channel, note, velocity } => MidiManager::note_off(*channel, *note, *velocity),
+            MidiMessage::NoteOn { channel, note, velocity } => MidiManager::note_on(*channel, *note, *velocity),
+            MidiMessage::PolyAftertouch { channel, note, pressure } => {
+                vec![status(0xA0, *channel), note & 0x7F, pressure & 0x7F]
+            }
+            MidiMessage::ControlChange { channel, controller, value } => {
+                MidiManager::control_change(*channel, *controller, *value)
+            }
+            MidiMessage::ProgramChange { channel, program } => vec![status(0xC0, *channel), program & 0x7F],
+            MidiMessage::ChannelPressure { channel, pressure } => vec![status(0xD0, *channel), pressure & 0x7F],
+            MidiMessage::PitchBend { channel, value } => {
+                vec![status(0xE0, *channel), (value & 0x7F) as u8, ((value >> 7) & 0x7F) as u8]
+            }
+            MidiMessage::SysEx(bytes) => bytes.clone(),
+            MidiMessage::MtcQuarterFrame(value) => vec![0xF1, value & 0x7F],
+            MidiMessage::SongPosition(position) => MidiManager::song_position_pointer(*position),
+            MidiMessage::SongSelect(song) => MidiManager::song_select(*song),
+            MidiMessage::TuneRequest => vec![0xF6],
+            MidiMessage::TimingClock => vec![0xF8],
+            MidiMessage::Start => vec![0xFA],
+            MidiMessage::Continue => vec![0xFB],
+            MidiMessage::Stop => vec![0xFC],
+            MidiMessage::ActiveSensing => vec![0xFE],
+            MidiMessage::SystemReset => vec![0xFF],
+        }
+    }
+
+    /// The channel (0-15) of a channel message, None for system messages
+    pub fn channel(&self) -> Option<u8> {
+        match self {
+            MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::PolyAftertouch { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelPressure { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(*channel),
+            _ => None,
+        }
+    }
+
+    /// Note Off, or Note On with velocity 0
+    pub fn is_note_off(&self) -> bool {
+        matches!(self, MidiMessage::NoteOff { .. } | MidiMessage::NoteOn { velocity: 0, .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_midi_message_round_trip() {
+        let messages: [&[u8]; 6] = [
+            &[0x91, 60, 0],
+            &[0xE3, 0x00, 0x40],
+            &[0xC9, 5],
+            &[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7],
+            &[0xF2, 0x10, 0x01],
+            &[0xF8],
+        ];
+        for bytes in messages {
+            assert_eq!(MidiMessage::parse(bytes).unwrap().to_bytes(), bytes);
+        }
+
+        let bend = MidiMessage::parse(&[0xE3, 0x00, 0x40]).unwrap();
+        assert_eq!(bend, MidiMessage::PitchBend { channel: 3, value: 8192 });
+        assert_eq!(bend.channel(), Some(3));
+        assert!(MidiMessage::parse(&[0x91, 60, 0]).unwrap().is_note_off());
+        assert_eq!(MidiMessage::SongPosition(0x90).channel(), None);
+
+        assert!(MidiMessage::parse(&[60, 100]).is_err()); // Running status
+        assert!(MidiMessage::parse(&[0x90, 60]).is_err());
+        assert!(MidiMessage::parse(&[0xF0, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_validate_message() {
+        assert!(validate_message(&[0x90, 60, 100]).is_ok());
+        assert!(validate_message(&[0xC0, 5]).is_ok());
+        assert!(validate_message(&[0xF8]).is_ok());
+        assert!(validate_message(&[0xF0, 0x7E, 0x01, 0xF7]).is_ok());
+        assert!(validate_message(&[60, 100]).is_ok()); // Running status
+
+        assert_eq!(find_invalid_byte(&[]).unwrap().0, 0);
+        assert_eq!(find_invalid_byte(&[0x90, 60, 200]).unwrap().0, 2);
+        assert_eq!(find_invalid_byte(&[0x90, 60]).unwrap().0, 2);
+        assert_eq!(find_invalid_byte(&[0xC0, 5, 6]).unwrap().0, 2);
+        assert_eq!(find_invalid_byte(&[0xF0, 0x7E, 0x90, 0xF7]).unwrap().0, 2);
+        assert_eq!(find_invalid_byte(&[0xF0, 0x7E]).unwrap().0, 2);
+        assert_eq!(find_invalid_byte(&[0xF0]).unwrap().0, 1);
+        assert_eq!(find_invalid_byte(&[0xF4]).unwrap().0, 0);
+    }
+
+    #[test]
+    fn test_rate_limiter_spaces_slots() {
+        let limiter = OutputRateLimiter::new();
+        assert_eq!(limiter.reserve(), Duration::ZERO);
+
+        limiter.set_rate(100);
+        assert_eq!(limiter.reserve(), Duration::ZERO);
+        let second = limiter.reserve();
+        let third = limiter.reserve();
+        assert!(second > Duration::from_millis(9) && second <= Duration::from_millis(10));
+        assert!(third > Duration::from_millis(19) && third <= Duration::from_millis(20));
+
+        limiter.set_rate(0);
+        assert_eq!(limiter.reserve(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_message_ring() {
+        let ring = MessageRing::new(10);
+        let mut buffer = [0u8; 3];
+        assert_eq!(ring.pop_into(&mut buffer), None);
+
+        assert!(ring.push(&[0x90, 60, 100]));
+        assert!(ring.push(&[0xF8]));
+        assert!(!ring.push(&[0x80, 60, 0])); // 5 + 3 + 5 bytes would exceed the capacity
+        assert_eq!(ring.dropped(), 1);
+
+        assert_eq!(ring.pop_into(&mut buffer), Some(3));
+        assert_eq!(buffer, [0x90, 60, 100]);
+        assert_eq!(ring.pop_into(&mut buffer), Some(1));
+        assert_eq!(buffer[0], 0xF8);
+
+        // Too long for the buffer: discarded, full length reported
+        assert!(ring.push(&[0xF0, 1, 2, 0xF7]));
+        assert_eq!(ring.pop_into(&mut buffer), Some(4));
+        assert_eq!(ring.pop_into(&mut buffer), None);
+    }
+
+    #[test]
+    fn test_message_ring_drain() {
+        let ring = MessageRing::new(64);
+        let mut buffer = [0u8; 8];
+        assert_eq!(ring.drain_into(&mut buffer), (0, 0));
+
+        ring.push(&[0x90, 60, 100]);
+        ring.push(&[0xF8]);
+        ring.push(&[0x80, 60, 0]);
+        assert_eq!(ring.drain_into(&mut buffer), (8, 2)); // The note-off waits for the next call
+        assert_eq!(buffer, [3, 0, 0x90, 60, 100, 1, 0, 0xF8]);
+        assert_eq!(ring.drain_into(&mut buffer), (5, 1));
+        assert_eq!(buffer[..5], [3, 0, 0x80, 60, 0]);
+
+        ring.push(&[0xF0, 1, 2, 3, 4, 5, 0xF7]); // Can never fit, so it is dropped
+        ring.push(&[0xFE]);
+        assert_eq!(ring.drain_into(&mut buffer), (3, 1));
+        assert_eq!(buffer[..3], [1, 0, 0xFE]);
+    }
+
+    #[test]
+    fn test_input_activity() {
+        let activity = InputActivity::new();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(activity.silent_for() >= Duration::from_millis(20));
+
+        activity.touch();
+        assert!(activity.silent_for() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_port_ignore_list_matching() {
+        let defaults: Vec<String> = DEFAULT_PORT_IGNORE_LIST.iter().map(|pattern| pattern.to_string()).collect();
+        assert!(matches_ignore_list("Midi Through:Midi Through Port-0 14:0", &defaults));
+        assert!(matches_ignore_list("labview midi input:in 128:0", &defaults));
+        assert!(!matches_ignore_list("USB MIDI Keyboard", &defaults));
+        assert!(!matches_ignore_list("Midi Through", &[]));
+    }
+
+    #[test]
+    fn test_dedupe_names() {
+        let names = ["USB MIDI", "Keyboard", "USB MIDI"].map(String::from).to_vec();
+        assert_eq!(dedupe_names(names), ["USB MIDI #1", "Keyboard", "USB MIDI #2"]);
+        assert_eq!(dedupe_names(vec!["Keyboard".to_string()]), ["Keyboard"]);
+    }
+
+    #[test]
+    fn test_device_names_with_nul_are_sanitized() {
+        let ports = [((), "USB\0 MIDI".to_string()), ((), "Keyboard".to_string())];
+        assert_eq!(display_names(&ports), ["USB MIDI", "Keyboard"]);
+        assert!(std::ffi::CString::new(sanitize_device_name("\0Synth\0")).is_ok());
+    }
+
+    #[test]
+    fn test_identical_names_sort_by_port_id() {
+        let mut ports = vec![
+            ("24:0", "Synth".to_string()),
+            ("20:0", "Keyboard".to_string()),
+            ("16:0", "Synth".to_string()),
+        ];
+        sort_by_name_then_id(&mut ports, |id| id.to_string());
+        assert_eq!(ports, [
+            ("20:0", "Keyboard".to_string()),
+            ("16:0", "Synth".to_string()),
+            ("24:0", "Synth".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_first_device_needs_a_device() {
+        let error = first_device_index(&[], PortDirection::Input).unwrap_err();
+        assert!(matches!(error, LVInteropError::NoDevices(_)));
+        assert_eq!(error.code(), -8);
+        assert!(matches!(first_device_index(&[], PortDirection::Output), Err(LVInteropError::NoDevices(_))));
+        assert_eq!(first_device_index(&["Keyboard".to_string(), "Synth".to_string()], PortDirection::Output).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_close_without_connection() {
+        let mut manager = MidiManager::new();
+        assert!(matches!(manager.close_input(), Err(LVInteropError::NotConnected(_))));
+        assert!(matches!(manager.close_output(), Err(LVInteropError::NotConnected(_))));
+    }
+
+    #[test]
+    fn test_duplicate_port_claims_are_rejected() {
+        let claim = PortClaim::acquire(PortDirection::Input, "128:0", "Claim Test Port").unwrap();
+        assert!(matches!(
+            PortClaim::acquire(PortDirection::Input, "128:0", "Claim Test Port"),
+            Err(LVInteropError::PortInUse(_))
+        ));
+        // Input and output of the same device are separate ports
+        let output_claim = PortClaim::acquire(PortDirection::Output, "128:0", "Claim Test Port").unwrap();
+        // A second device with the same name has its own id
+        let twin_claim = PortClaim::acquire(PortDirection::Input, "129:0", "Claim Test Port").unwrap();
+
+        let held = Some(claim);
+        assert!(PortClaim::acquire_unless_held(&held, PortDirection::Input, "128:0", "Claim Test Port").unwrap().is_none());
+
+        drop(held);
+        drop(output_claim);
+        drop(twin_claim);
+        assert!(PortClaim::acquire(PortDirection::Input, "128:0", "Claim Test Port").is_ok());
+        assert_eq!(port_key(String::new(), "Claim Test Port"), "Claim Test Port");
+    }
+
+    #[test]
+    fn test_unclaimed_manager_skips_claims() {
+        let _claim = PortClaim::acquire(PortDirection::Output, "130:0", "Unclaimed Test Port").unwrap();
+        let manager = MidiManager::unclaimed();
+        assert!(manager.claim(&None, PortDirection::Output, "130:0".to_string(), "Unclaimed Test Port").unwrap().is_none());
+        assert!(MidiManager::new().claim(&None, PortDirection::Output, "130:0".to_string(), "Unclaimed Test Port").is_err());
+    }
+
+    #[test]
+    fn test_ump_is_not_midi1() {
+        assert!(is_midi1_message(&[0x90, 60, 100]));
+        assert!(is_midi1_message(&[0xC0, 5]));
+        assert!(is_midi1_message(&[0xF8]));
+        assert!(is_midi1_message(&[0xF0, 0x7E, 0x7F]));
+
+        assert!(!is_midi1_message(&[0x20, 0x90, 0x3C, 0x64])); // UMP MIDI 1.0 channel voice, group 0
+        assert!(!is_midi1_message(&[0x40, 0x90, 0x3C, 0x00, 0xFF, 0xFF, 0x00, 0x00])); // UMP MIDI 2.0 note on
+        assert!(!is_midi1_message(&[0xD0, 0x10, 0x01, 0x00])); // UMP flex data
+        assert!(!is_midi1_message(&[0x90, 0x90, 100]));
+        assert!(!is_midi1_message(&[]));
+    }
+
+    #[test]
+    fn test_connect_retries_only_backend_errors() {
+        let delay = Duration::from_millis(1);
+
+        let mut attempts = 0;
+        let result: Result<()> = retry_connect(2, delay, || {
+            attempts += 1;
+            Err(LVInteropError::MidiBackend("device busy".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let result = retry_connect(2, delay, || {
+            attempts += 1;
+            if attempts < 2 { Err(LVInteropError::MidiBackend("device busy".to_string())) } else { Ok(attempts) }
+        });
+        assert_eq!(result.unwrap(), 2);
+
+        let mut attempts = 0;
+        let result: Result<()> = retry_connect(2, delay, || {
+            attempts += 1;
+            Err(device_index_error(9))
+        });
+        assert!(matches!(result, Err(LVInteropError::DeviceNotFound(_))));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_validating_builders() {
+        assert_eq!(MidiManager::try_note_on(0, 60, 100).unwrap(), MidiManager::note_on(0, 60, 100));
+        assert_eq!(MidiManager::note_on(0, 200, 100), vec![0x90, 72, 100]);
+        assert!(matches!(MidiManager::try_note_on(0, 200, 100), Err(LVInteropError::InvalidMessage(_))));
+        assert!(MidiManager::try_note_off(16, 60, 0).is_err());
+        assert!(MidiManager::try_control_change(0, 7, 128).is_err());
+        assert!(MidiManager::try_control_change(15, 127, 127).is_ok());
+    }
+
+    #[test]
+    fn test_note_off_zero() {
+        assert_eq!(MidiManager::note_off_zero(3, 60), vec![0x83, 60, 0]);
+        assert_ne!(MidiManager::note_off_zero(3, 60), MidiManager::note_on(3, 60, 0));
+    }
+
+    #[test]
+    fn test_system_common_and_mmc_builders() {
+        assert_eq!(MidiManager::song_position_pointer(0), vec![0xF2, 0x00, 0x00]);
+        assert_eq!(MidiManager::song_position_pointer(16383), vec![0xF2, 0x7F, 0x7F]);
+        assert_eq!(MidiManager::song_position_pointer(200), vec![0xF2, 0x48, 0x01]);
+        assert_eq!(MidiManager::song_select(5), vec![0xF3, 5]);
+        assert_eq!(MidiManager::mmc(0x7F, MmcCommand::Play), vec![0xF0, 0x7F, 0x7F, 0x06, 0x02, 0xF7]);
+        assert_eq!(MmcCommand::from_byte(0x06), Some(MmcCommand::RecordStrobe));
+        assert_eq!(MmcCommand::from_byte(0x08), None);
+
+        for message in [MidiManager::song_position_pointer(1234), MidiManager::song_select(9),
+                        MidiManager::mmc(0x7F, MmcCommand::Stop)] {
+            assert!(validate_message(&message).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_enumeration_timeout() {
+        let names = enumerate_with_timeout(Duration::from_secs(1), || Ok(vec!["Port".to_string()]));
+        assert_eq!(names.unwrap(), vec!["Port".to_string()]);
+
+        let hung = enumerate_with_timeout(Duration::from_millis(10), || {
+            std::thread::sleep(Duration::from_millis(200));
+            Ok(Vec::new())
+        });
+        assert_eq!(hung.unwrap_err().code(), -6);
+    }
+
+    #[test]
+    fn test_filter_modes() {
+        let filter = MessageFilter::new(Vec::new());
+        assert!(filter.passes(0xF8)); // Empty whitelist passes everything
+
+        filter.set_status_bytes(vec![0x90, 0x80]);
+        assert!(filter.passes(0x90));
+        assert!(!filter.passes(0xF8));
+
+        filter.set_mode(FilterMode::Blacklist);
+        filter.set_status_bytes(vec![0xF8, 0xFE]);
+        assert!(filter.passes(0x90));
+        assert!(!filter.passes(0xF8));
+        assert!(!filter.passes(0xFE));
+
+        filter.set_status_bytes(Vec::new());
+        assert!(filter.passes(0xFE)); // Empty blacklist also passes everything
+    }
+
+    #[test]
+    fn test_velocity_threshold() {
+        let filter = MessageFilter::new(Vec::new());
+        assert!(filter.passes_message(&[0x90, 60, 1]));
+
+        filter.set_min_velocity(40);
+        assert!(!filter.passes_message(&[0x90, 60, 39]));
+        assert!(filter.passes_message(&[0x90, 60, 40]));
+        assert!(filter.passes_message(&[0x90, 60, 0])); // Note Off by velocity 0
+        assert!(filter.passes_message(&[0x80, 60, 10]));
+        assert!(filter.passes_message(&[0xB0, 7, 10]));
+
+        filter.set_status_bytes(vec![0x80]);
+        assert!(!filter.passes_message(&[0x90, 60, 100])); // Status filter still applies
+    }
+
+    #[test]
+    #[ignore] // Needs a loopback port; run with: cargo test test_send_and_echo -- --ignored
+    fn test_send_and_echo() {
+        set_port_ignore_list(Vec::new()); // Midi Through is hidden by default
+        let mut manager = MidiManager::new();
+        let loopback = |devices: Vec<String>| devices.iter().position(|name| name.contains("Midi Through"));
+        let input = loopback(manager.list_input_devices().unwrap()).expect("no loopback input");
+        let output = loopback(manager.list_output_devices().unwrap()).expect("no loopback output");
+        manager.connect_input(input).unwrap();
+        manager.connect_output(output).unwrap();
+
+        let message = MidiManager::note_on(0, 60, 100);
+        assert_eq!(manager.send_and_echo(&message, Duration::from_secs(1)).unwrap(), message);
+    }
+
+    #[test]
+    fn test_is_virtual_port() {
+        assert!(is_virtual_port("", "Microsoft GS Wavetable Synth"));
+        assert!(!is_virtual_port("", "USB MIDI Interface"));
+
+        #[cfg(target_os = "linux")]
+        {
+            assert!(is_virtual_port("128:0", "VMPK Output"));
+            assert!(is_virtual_port("14:0", "Midi Through Port-0"));
+            assert!(!is_virtual_port("20:0", "USB MIDI Interface"));
+            assert_eq!(latency_class("20:0", "USB MIDI Interface"), LatencyClass::Hardware);
+            assert_eq!(latency_class("128:0", "FLUID Synth"), LatencyClass::Virtual);
+        }
+        #[cfg(not(target_os = "linux"))]
+        assert_eq!(latency_class("", "USB MIDI Interface"), LatencyClass::Unknown);
+        assert_eq!(latency_class("", "loopMIDI Port"), LatencyClass::Virtual);
+    }
+}
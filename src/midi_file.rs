@@ -1,34 +1,40 @@
+use crate::handles::HandleAllocator;
+use crate::midi::zero_velocity_as_note_off;
 use midly::{Smf, Timing, TrackEventKind, MidiMessage, MetaMessage};
+use midly::live::LiveEvent;
+use midly::num::{u15, u24, u28};
+use midly::{Format, Header, TrackEvent};
 use std::collections::HashMap;
-use std::sync::{Mutex, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::path::Path;
-use std::fs;
+use std::{fmt, fs, io};
 
 // Global storage for MIDI files (thread-safe)
 static MIDI_FILES: OnceLock<Mutex<HashMap<i32, MidiFile>>> = OnceLock::new();
-static NEXT_FILE_HANDLE: OnceLock<Mutex<i32>> = OnceLock::new();
+static FILE_HANDLES: OnceLock<Mutex<HandleAllocator>> = OnceLock::new();
 
 fn get_midi_files() -> &'static Mutex<HashMap<i32, MidiFile>> {
     MIDI_FILES.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
-fn get_next_file_handle_mutex() -> &'static Mutex<i32> {
-    NEXT_FILE_HANDLE.get_or_init(|| Mutex::new(1))
+fn get_file_handles() -> &'static Mutex<HandleAllocator> {
+    FILE_HANDLES.get_or_init(|| Mutex::new(HandleAllocator::new()))
 }
 
 fn get_next_file_handle() -> i32 {
-    let mut handle = get_next_file_handle_mutex().lock().unwrap();
-    let current = *handle;
-    *handle += 1;
-    current
+    get_file_handles().lock().unwrap().allocate()
 }
 
 /// Represents a loaded MIDI file with processed track data
 pub struct MidiFile {
-    pub smf: Smf<'static>,
+    // Declared before `data` so it is dropped first: a parsed file's meta text and SysEx
+    // payloads borrow from `data`, so the 'static lifetime is never handed out (see `smf()`)
+    smf: Smf<'static>,
     pub tracks: Vec<TrackData>,
     pub timing: Timing,
     pub format: u16,
+    /// The file bytes `smf` borrows from, if it was parsed from bytes
+    data: Option<Arc<[u8]>>,
 }
 
 /// Processed track data with absolute timing
@@ -38,6 +44,10 @@ pub struct TrackData {
     pub name: String,
     pub instrument: Option<String>,
     pub channel_mask: u16, // Bitmask of channels used in this track
+    /// Left out of playback and export (see `MidiFile::is_track_audible`)
+    pub mute: bool,
+    /// When any track is soloed, only soloed tracks are played and exported
+    pub solo: bool,
 }
 
 /// MIDI event with absolute timing
@@ -80,15 +90,94 @@ pub enum EventType {
     Unknown,
 }
 
+impl EventType {
+    /// Numeric code used across the FFI (the declaration order above)
+    pub fn code(&self) -> i32 {
+        self.clone() as i32
+    }
+
+    pub fn from_code(code: i32) -> Option<Self> {
+        const ALL: [EventType; 24] = [
+            EventType::NoteOff, EventType::NoteOn, EventType::PolyphonicAftertouch,
+            EventType::ControlChange, EventType::ProgramChange, EventType::ChannelAftertouch,
+            EventType::PitchBend, EventType::SystemExclusive, EventType::MetaSequenceNumber,
+            EventType::MetaText, EventType::MetaCopyright, EventType::MetaTrackName,
+            EventType::MetaInstrumentName, EventType::MetaLyric, EventType::MetaMarker,
+            EventType::MetaCuePoint, EventType::MetaChannelPrefix, EventType::MetaEndOfTrack,
+            EventType::MetaSetTempo, EventType::MetaSmpteOffset, EventType::MetaTimeSignature,
+            EventType::MetaKeySignature, EventType::MetaSequencerSpecific, EventType::Unknown,
+        ];
+        usize::try_from(code).ok().and_then(|index| ALL.get(index).cloned())
+    }
+}
+
+/// A sounding note with its start and end ticks
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteSpan {
+    pub channel: u8,
+    pub note: u8,
+    pub velocity: u8,
+    pub start_tick: u32,
+    pub end_tick: u32,
+}
+
+/// `NoteEvent::duration_ticks` of a note-on that is never followed by a matching note-off
+pub const NOTE_UNMATCHED_DURATION: u32 = u32::MAX;
+
+/// A note as a single event with a duration, for score-style display
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteEvent {
+    pub start_tick: u32,
+    /// `NOTE_UNMATCHED_DURATION` if the note is never released
+    pub duration_ticks: u32,
+    pub note: u8,
+    pub velocity: u8,
+    pub channel: u8,
+}
+
+/// Kind of note pairing problem found by `MidiFile::validate_notes`
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteIssueKind {
+    /// Note-on that is never followed by a matching note-off
+    StuckNote = 0,
+    /// Note-off with no preceding note-on for the same channel and key
+    OrphanNoteOff = 1,
+}
+
+/// A note pairing problem at a specific track and tick
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteIssue {
+    pub track: usize,
+    pub tick: u32,
+    pub channel: u8,
+    pub note: u8,
+    pub kind: NoteIssueKind,
+}
+
 impl MidiFile {
     /// Create a new MidiFile from raw MIDI data
     pub fn from_bytes(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
-        // Parse the MIDI file
-        let smf = Smf::parse(data)?;
-        
-        // Convert to owned data
-        let owned_smf = smf.make_static();
-        
+        // The parsed events borrow their meta text and SysEx payloads from the file bytes
+        // (`Smf::make_static` would blank them), so the file keeps its own copy of the bytes
+        let data: Arc<[u8]> = Arc::from(data);
+        // SAFETY: the bytes are moved into the returned file with the `Smf` borrowing them.
+        // An `Arc` allocation never moves, the file drops `smf` before `data`, and `smf` is
+        // private and only lent out with a lifetime bound to the file.
+        let bytes: &'static [u8] = unsafe { &*(data.as_ref() as *const [u8]) };
+        let mut midi_file = Self::from_smf(Smf::parse(bytes)?)?;
+        midi_file.data = Some(data);
+        Ok(midi_file)
+    }
+
+    /// The parsed Standard MIDI File, including any edits
+    pub fn smf(&self) -> &Smf<'_> {
+        &self.smf
+    }
+
+    /// Create a new MidiFile from an owned SMF structure
+    pub fn from_smf(owned_smf: Smf<'static>) -> Result<Self, Box<dyn std::error::Error>> {
         let timing = owned_smf.header.timing;
         let format = match owned_smf.header.format {
             midly::Format::SingleTrack => 0,
@@ -99,7 +188,7 @@ impl MidiFile {
         // Process tracks
         let mut tracks = Vec::new();
         for (track_idx, track) in owned_smf.tracks.iter().enumerate() {
-            let track_data = Self::process_track(track, track_idx, timing)?;
+            let track_data = Self::process_track(track, track_idx, timing, zero_velocity_as_note_off())?;
             tracks.push(track_data);
         }
         
@@ -108,14 +197,72 @@ impl MidiFile {
             tracks,
             timing,
             format,
+            data: None,
+        })
+    }
+    
+    /// Build a single-track file from live messages timestamped in microseconds.
+    /// Only channel messages are recorded, and the first of them is placed at tick zero.
+    pub fn from_recording(
+        messages: &[(u64, Vec<u8>)],
+        ticks_per_quarter: u16,
+        tempo_us_per_quarter: u32,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let ticks_per_quarter = u15::try_from(ticks_per_quarter)
+            .filter(|tpq| tpq.as_int() > 0)
+            .ok_or("Ticks per quarter must be between 1 and 32767")?;
+        let tempo = u24::try_from(tempo_us_per_quarter)
+            .filter(|tempo| tempo.as_int() > 0)
+            .ok_or("Tempo must be between 1 and 16777215 us per quarter")?;
+
+        // Only channel messages are kept, and the first of them lands on tick 0 even when
+        // clock or active sensing bytes arrived before it
+        let channel_messages: Vec<_> = messages.iter()
+            .filter_map(|(timestamp, bytes)| match LiveEvent::parse(bytes) {
+                Ok(LiveEvent::Midi { channel, message }) => Some((*timestamp, channel, message)),
+                _ => None,
+            })
+            .collect();
+        let start_time = channel_messages.first().map_or(0, |(timestamp, ..)| *timestamp);
+        let mut track = vec![TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::Tempo(tempo)),
+        }];
+        let mut last_tick = 0u64;
+
+        for (timestamp, channel, message) in channel_messages {
+            // Round the elapsed wall-clock time to the nearest tick at the recording tempo
+            let elapsed_us = timestamp.saturating_sub(start_time);
+            let tick = (elapsed_us * ticks_per_quarter.as_int() as u64
+                + tempo_us_per_quarter as u64 / 2)
+                / tempo_us_per_quarter as u64;
+            let delta = (tick - last_tick).min(u28::max_value().as_int() as u64);
+            last_tick = tick;
+
+            track.push(TrackEvent {
+                delta: u28::new(delta as u32),
+                kind: TrackEventKind::Midi { channel, message },
+            });
+        }
+
+        track.push(TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+
+        Self::from_smf(Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(ticks_per_quarter)),
+            tracks: vec![track],
         })
     }
     
-    /// Process a single track to extract events with absolute timing
+    /// Process a single track to extract events with absolute timing. Note On with velocity 0
+    /// becomes a Note Off event when `zero_velocity_note_off` is set.
     fn process_track(
         track: &midly::Track<'_>,
         track_idx: usize,
         timing: Timing,
+        zero_velocity_note_off: bool,
     ) -> Result<TrackData, Box<dyn std::error::Error>> {
         let mut events = Vec::new();
         let mut absolute_time = 0u32;
@@ -137,7 +284,7 @@ impl MidiFile {
                             (EventType::NoteOff, key.as_int(), vel.as_int())
                         }
                         MidiMessage::NoteOn { key, vel } => {
-                            if vel.as_int() == 0 {
+                            if vel.as_int() == 0 && zero_velocity_note_off {
                                 (EventType::NoteOff, key.as_int(), vel.as_int())
                             } else {
                                 (EventType::NoteOn, key.as_int(), vel.as_int())
@@ -251,6 +398,8 @@ impl MidiFile {
             name: track_name,
             instrument: instrument_name,
             channel_mask,
+            mute: false,
+            solo: false,
         })
     }
     
@@ -263,6 +412,624 @@ impl MidiFile {
             .unwrap_or(0)
     }
     
+    /// Duration of the file in milliseconds, following every tempo change up to the last event
+    pub fn duration_ms(&self) -> f64 {
+        self.tick_to_ms(self.get_duration_ticks())
+    }
+
+    /// Time in milliseconds from the start of the file to `tick`, following the tempo map
+    pub fn tick_to_ms(&self, tick: u32) -> f64 {
+        let mut tempo_us_per_quarter = 500_000; // 120 BPM until the first tempo event
+        let mut last_time = 0u32;
+        let mut elapsed_ms = 0.0;
+
+        for (absolute_time, kind) in self.merged_events() {
+            if absolute_time >= tick {
+                break;
+            }
+            if let TrackEventKind::Meta(MetaMessage::Tempo(tempo)) = kind {
+                elapsed_ms += self.ticks_to_ms(absolute_time - last_time, tempo_us_per_quarter);
+                last_time = absolute_time;
+                tempo_us_per_quarter = tempo.as_int();
+            }
+        }
+
+        elapsed_ms + self.ticks_to_ms(tick - last_time, tempo_us_per_quarter)
+    }
+
+    /// Tick reached `ms` milliseconds into the file, following the tempo map; the inverse of
+    /// `tick_to_ms` up to rounding to the nearest tick. Negative times map to tick 0.
+    pub fn ms_to_ticks(&self, ms: f64) -> u32 {
+        let mut tempo_us_per_quarter = 500_000; // 120 BPM until the first tempo event
+        let mut last_time = 0u32;
+        let mut elapsed_ms = 0.0;
+
+        for (absolute_time, kind) in self.merged_events() {
+            if let TrackEventKind::Meta(MetaMessage::Tempo(tempo)) = kind {
+                let segment_ms = self.ticks_to_ms(absolute_time - last_time, tempo_us_per_quarter);
+                if elapsed_ms + segment_ms >= ms {
+                    break;
+                }
+                elapsed_ms += segment_ms;
+                last_time = absolute_time;
+                tempo_us_per_quarter = tempo.as_int();
+            }
+        }
+
+        let ms_per_tick = self.ticks_to_ms(1, tempo_us_per_quarter);
+        let ticks = last_time as f64 + (ms - elapsed_ms) / ms_per_tick;
+        ticks.round() as u32 // Saturates: negative (or NaN) becomes 0
+    }
+
+    /// Events of one type from a track, in order. Empty if the track does not exist.
+    pub fn events_of_type(&self, track: usize, event_type: EventType) -> Vec<&AbsoluteEvent> {
+        match self.tracks.get(track) {
+            Some(track) => track.events.iter()
+                .filter(|event| event.event_type == event_type)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+    
+    /// Find the text, lyric, marker, cue point, copyright and track name events whose text
+    /// contains `query` (case-insensitive). Returns (track, event index) pairs in file order.
+    pub fn find_text(&self, query: &str) -> Vec<(usize, usize)> {
+        const SEARCHED: [EventType; 6] = [
+            EventType::MetaText, EventType::MetaLyric, EventType::MetaMarker,
+            EventType::MetaCuePoint, EventType::MetaCopyright, EventType::MetaTrackName,
+        ];
+        let query = query.to_lowercase();
+
+        self.tracks.iter().enumerate()
+            .flat_map(|(track_index, track)| {
+                track.events.iter().enumerate().map(move |(event_index, event)| (track_index, event_index, event))
+            })
+            .filter(|(_, _, event)| SEARCHED.contains(&event.event_type))
+            .filter(|(_, _, event)| event.text.to_lowercase().contains(&query))
+            .map(|(track_index, event_index, _)| (track_index, event_index))
+            .collect()
+    }
+
+    /// Pair note-ons with note-offs in a track (first-in, first-out per channel and key).
+    /// Notes never released end at the track's last tick. With `apply_sustain`, a note released
+    /// while its channel's sustain pedal (CC64 >= 64) is down ends when the pedal lifts instead.
+    /// Spans are ordered by start tick.
+    pub fn note_spans(&self, track: usize, apply_sustain: bool) -> Vec<NoteSpan> {
+        let (mut spans, unmatched) = self.pair_notes(track, apply_sustain);
+        spans.extend(unmatched);
+        spans.sort_by_key(|span| (span.start_tick, span.channel, span.note));
+        spans
+    }
+
+    /// The notes of a track as single events with durations, paired like `note_spans`
+    /// (without sustain), ordered by start tick. A note that is never released is still
+    /// listed, with duration `NOTE_UNMATCHED_DURATION`.
+    pub fn note_events(&self, track: usize) -> Vec<NoteEvent> {
+        let (spans, unmatched) = self.pair_notes(track, false);
+        let note_event = |span: NoteSpan, duration_ticks| NoteEvent {
+            start_tick: span.start_tick,
+            duration_ticks,
+            note: span.note,
+            velocity: span.velocity,
+            channel: span.channel,
+        };
+
+        let mut events: Vec<NoteEvent> = spans.into_iter()
+            .map(|span| {
+                let duration_ticks = span.end_tick - span.start_tick;
+                note_event(span, duration_ticks)
+            })
+            .chain(unmatched.into_iter().map(|span| note_event(span, NOTE_UNMATCHED_DURATION)))
+            .collect();
+        events.sort_by_key(|event| (event.start_tick, event.channel, event.note));
+        events
+    }
+
+    /// Pair note-ons with note-offs as described for `note_spans`. Returns the released notes
+    /// and, separately, the notes never released (ending at the track's last tick), unsorted.
+    fn pair_notes(&self, track: usize, apply_sustain: bool) -> (Vec<NoteSpan>, Vec<NoteSpan>) {
+        let track = match self.tracks.get(track) {
+            Some(track) => track,
+            None => return (Vec::new(), Vec::new()),
+        };
+        let track_end = track.events.last().map_or(0, |event| event.absolute_time);
+
+        let mut spans = Vec::new();
+        let mut sounding: HashMap<(u8, u8), Vec<(u32, u8)>> = HashMap::new();
+        let mut pedal_down = [false; 16];
+        let mut sustained: [Vec<NoteSpan>; 16] = Default::default();
+
+        for event in &track.events {
+            let channel = event.channel as usize & 0x0F;
+            match event.event_type {
+                _ if event.ends_note() => {
+                    let starts = sounding.entry((event.channel, event.data1)).or_default();
+                    if starts.is_empty() {
+                        continue;
+                    }
+                    let (start_tick, velocity) = starts.remove(0);
+                    let span = NoteSpan {
+                        channel: event.channel,
+                        note: event.data1,
+                        velocity,
+                        start_tick,
+                        end_tick: event.absolute_time,
+                    };
+                    if apply_sustain && pedal_down[channel] {
+                        sustained[channel].push(span);
+                    } else {
+                        spans.push(span);
+                    }
+                }
+                EventType::NoteOn => {
+                    sounding.entry((event.channel, event.data1)).or_default().push((event.absolute_time, event.data2));
+                }
+                EventType::ControlChange if apply_sustain && event.data1 == 64 => {
+                    pedal_down[channel] = event.data2 >= 64;
+                    if !pedal_down[channel] {
+                        for mut span in sustained[channel].drain(..) {
+                            span.end_tick = event.absolute_time;
+                            spans.push(span);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Pedal still down or notes never released: they ring to the end of the track
+        for mut span in sustained.into_iter().flatten() {
+            span.end_tick = track_end;
+            spans.push(span);
+        }
+        let unmatched = sounding.into_iter()
+            .flat_map(|((channel, note), starts)| {
+                starts.into_iter().map(move |(start_tick, velocity)| NoteSpan {
+                    channel,
+                    note,
+                    velocity,
+                    start_tick,
+                    end_tick: track_end,
+                })
+            })
+            .collect();
+
+        (spans, unmatched)
+    }
+
+    /// Merge all tracks into wire-format channel messages, each paired with the delay in
+    /// milliseconds since the previous message. Tempo changes in any track are honored.
+    pub fn timed_events(&self) -> impl Iterator<Item = (f64, Vec<u8>)> {
+        self.timed_events_from(0)
+    }
+
+    /// Like `timed_events`, but starting at `start_tick`: earlier messages are skipped and the
+    /// first delay is measured from `start_tick`. Earlier tempo changes still apply.
+    pub fn timed_events_from(&self, start_tick: u32) -> impl Iterator<Item = (f64, Vec<u8>)> {
+        let mut tempo_us_per_quarter = 500_000; // 120 BPM until the first tempo event
+        let mut last_time = 0u32;
+        let mut elapsed_ms = 0.0;
+        let mut last_message_ms = None;
+        let mut timed_events = Vec::new();
+
+        for (absolute_time, kind) in self.merged_events() {
+            // Playback starts part-way through this gap
+            if last_message_ms.is_none() && absolute_time >= start_tick {
+                last_message_ms = Some(elapsed_ms + self.ticks_to_ms(start_tick - last_time, tempo_us_per_quarter));
+            }
+            elapsed_ms += self.ticks_to_ms(absolute_time - last_time, tempo_us_per_quarter);
+            last_time = absolute_time;
+
+            match kind {
+                TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                    tempo_us_per_quarter = tempo.as_int();
+                }
+                TrackEventKind::Midi { channel, message } => {
+                    let last_ms = match last_message_ms {
+                        Some(ref mut last_ms) => last_ms,
+                        None => continue,
+                    };
+                    let live_event = LiveEvent::Midi { channel, message };
+                    let mut bytes = Vec::with_capacity(3);
+                    if live_event.write_std(&mut bytes).is_ok() {
+                        timed_events.push((elapsed_ms - *last_ms, bytes));
+                        *last_ms = elapsed_ms;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        timed_events.into_iter()
+    }
+
+    /// Controller state in effect at `start_tick`, as messages to send before playing from there
+    /// (a "chase"). Per channel this is the last value of each controller, program, pitch bend
+    /// and channel pressure before `start_tick`, in the order they occurred so bank selects stay
+    /// ahead of their program change. Notes and channel-mode messages are never chased.
+    pub fn chase_messages(&self, start_tick: u32) -> Vec<Vec<u8>> {
+        // Key: (channel, kind) where kind is the controller number, or 128+ for other messages
+        let mut state: HashMap<(u8, u16), (usize, Vec<u8>)> = HashMap::new();
+
+        for (index, (absolute_time, kind)) in self.merged_events().into_iter().enumerate() {
+            if absolute_time >= start_tick {
+                break;
+            }
+            let (channel, message) = match kind {
+                TrackEventKind::Midi { channel, message } => (channel, message),
+                _ => continue,
+            };
+            let key = match message {
+                MidiMessage::Controller { controller, .. } if controller.as_int() < 120 => controller.as_int() as u16,
+                MidiMessage::ProgramChange { .. } => 128,
+                MidiMessage::PitchBend { .. } => 129,
+                MidiMessage::ChannelAftertouch { .. } => 130,
+                _ => continue,
+            };
+
+            let live_event = LiveEvent::Midi { channel, message };
+            let mut bytes = Vec::with_capacity(3);
+            if live_event.write_std(&mut bytes).is_ok() {
+                state.insert((channel.as_int(), key), (index, bytes));
+            }
+        }
+
+        let mut chased: Vec<(usize, Vec<u8>)> = state.into_values().collect();
+        chased.sort_by_key(|(index, _)| *index);
+        chased.into_iter().map(|(_, bytes)| bytes).collect()
+    }
+
+    /// Events from all tracks ordered by absolute tick; the stable sort keeps track order
+    /// for simultaneous events. Channel events of muted (or not soloed) tracks are left out,
+    /// their meta events kept so the tempo map is unchanged.
+    fn merged_events(&self) -> Vec<(u32, TrackEventKind<'static>)> {
+        let mut merged = Vec::new();
+        for (track_idx, track) in self.smf.tracks.iter().enumerate() {
+            let audible = self.is_track_audible(track_idx);
+            let mut absolute_time = 0u32;
+            for event in track.iter() {
+                absolute_time = absolute_time.saturating_add(event.delta.as_int());
+                if audible || !matches!(event.kind, TrackEventKind::Midi { .. }) {
+                    merged.push((absolute_time, event.kind));
+                }
+            }
+        }
+        merged.sort_by_key(|(absolute_time, _)| *absolute_time);
+        merged
+    }
+    
+    /// Tempo of the first tempo event in beats per minute (120 if the file has none)
+    pub fn get_initial_bpm(&self) -> f64 {
+        let first_tempo = self.smf.tracks.iter()
+            .filter_map(|track| {
+                let mut absolute_time = 0u32;
+                track.iter().find_map(|event| {
+                    absolute_time = absolute_time.saturating_add(event.delta.as_int());
+                    match event.kind {
+                        TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => Some((absolute_time, tempo.as_int())),
+                        _ => None,
+                    }
+                })
+            })
+            .min_by_key(|(absolute_time, _)| *absolute_time);
+
+        match first_tempo {
+            Some((_, tempo_us_per_quarter)) => 60_000_000.0 / tempo_us_per_quarter as f64,
+            None => 120.0,
+        }
+    }
+
+    /// Replace the tempo map with a single tempo at tick 0 (in the first track)
+    pub fn set_uniform_tempo(&mut self, bpm: f64) -> Result<(), Box<dyn std::error::Error>> {
+        if !bpm.is_finite() || bpm <= 0.0 {
+            return Err("BPM must be a positive number".into());
+        }
+        let tempo = u24::try_from((60_000_000.0 / bpm).round() as u32)
+            .filter(|tempo| tempo.as_int() > 0)
+            .ok_or("BPM out of range for a MIDI tempo")?;
+        if self.smf.tracks.is_empty() {
+            return Err("File has no tracks".into());
+        }
+
+        // Drop existing tempo events, carrying their delta over so other events keep their time
+        for track in self.smf.tracks.iter_mut() {
+            let mut carried_delta = 0u32;
+            track.retain_mut(|event| {
+                if let TrackEventKind::Meta(MetaMessage::Tempo(_)) = event.kind {
+                    carried_delta += event.delta.as_int();
+                    false
+                } else {
+                    event.delta = u28::new(event.delta.as_int() + carried_delta);
+                    carried_delta = 0;
+                    true
+                }
+            });
+        }
+
+        self.smf.tracks[0].insert(0, TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::Tempo(tempo)),
+        });
+
+        self.rebuild_tracks()
+    }
+
+    /// Find stuck notes and orphaned note-offs in each track. Repeated note-ons on the same
+    /// key are paired with note-offs first-in, first-out. Issues are ordered by track, then tick.
+    pub fn validate_notes(&self) -> Vec<NoteIssue> {
+        let mut issues = Vec::new();
+
+        for (track_idx, track) in self.tracks.iter().enumerate() {
+            let mut track_issues = Vec::new();
+            let mut sounding: HashMap<(u8, u8), Vec<u32>> = HashMap::new();
+
+            for event in &track.events {
+                let key = (event.channel, event.data1);
+                match event.event_type {
+                    _ if event.ends_note() => {
+                        let starts = sounding.entry(key).or_default();
+                        if starts.is_empty() {
+                            track_issues.push(NoteIssue {
+                                track: track_idx,
+                                tick: event.absolute_time,
+                                channel: event.channel,
+                                note: event.data1,
+                                kind: NoteIssueKind::OrphanNoteOff,
+                            });
+                        } else {
+                            starts.remove(0);
+                        }
+                    }
+                    EventType::NoteOn => sounding.entry(key).or_default().push(event.absolute_time),
+                    _ => {}
+                }
+            }
+
+            for ((channel, note), starts) in sounding {
+                track_issues.extend(starts.into_iter().map(|tick| NoteIssue {
+                    track: track_idx,
+                    tick,
+                    channel,
+                    note,
+                    kind: NoteIssueKind::StuckNote,
+                }));
+            }
+
+            track_issues.sort_by_key(|issue| (issue.tick, issue.channel, issue.note));
+            issues.extend(track_issues);
+        }
+
+        issues
+    }
+
+    /// Close every stuck note with a note-off at the end of its track (before End of Track).
+    /// Returns the number of note-offs inserted.
+    pub fn fix_dangling_notes(&mut self) -> Result<usize, Box<dyn std::error::Error>> {
+        let stuck_notes: Vec<NoteIssue> = self.validate_notes()
+            .into_iter()
+            .filter(|issue| issue.kind == NoteIssueKind::StuckNote)
+            .collect();
+        if stuck_notes.is_empty() {
+            return Ok(0);
+        }
+
+        for (track_idx, track) in self.smf.tracks.iter_mut().enumerate() {
+            let mut note_offs: Vec<TrackEvent<'static>> = stuck_notes.iter()
+                .filter(|issue| issue.track == track_idx)
+                .map(|issue| TrackEvent {
+                    delta: u28::new(0),
+                    kind: TrackEventKind::Midi {
+                        channel: issue.channel.into(),
+                        message: MidiMessage::NoteOff { key: issue.note.into(), vel: 0.into() },
+                    },
+                })
+                .collect();
+            if note_offs.is_empty() {
+                continue;
+            }
+
+            // The first note-off takes over the End of Track delta so it lands at the track's end
+            let insert_at = track.iter()
+                .position(|event| matches!(event.kind, TrackEventKind::Meta(MetaMessage::EndOfTrack)))
+                .unwrap_or(track.len());
+            if let Some(end_of_track) = track.get_mut(insert_at) {
+                note_offs[0].delta = end_of_track.delta;
+                end_of_track.delta = u28::new(0);
+            }
+            track.splice(insert_at..insert_at, note_offs);
+        }
+
+        self.rebuild_tracks()?;
+        Ok(stuck_notes.len())
+    }
+
+    /// Insert a channel event into a track, keeping the track sorted by absolute time.
+    /// Note-offs go before other events at the same tick (so a repeated note is not cut short),
+    /// everything else after them. End of Track stays last and moves later if needed.
+    pub fn insert_event(&mut self, track: usize, event: AbsoluteEvent) -> Result<(), Box<dyn std::error::Error>> {
+        let message = event.to_midi_message().ok_or("Only channel events can be inserted")?;
+        let kind = TrackEventKind::Midi { channel: (event.channel & 0x0F).into(), message };
+        let tick = event.absolute_time;
+
+        let smf_track = self.smf.tracks.get_mut(track).ok_or("Track index out of range")?;
+        let mut events = absolute_track(smf_track);
+        let is_end_of_track = |kind: &TrackEventKind| matches!(kind, TrackEventKind::Meta(MetaMessage::EndOfTrack));
+        let position = events.iter()
+            .position(|(time, kind)| {
+                is_end_of_track(kind) || *time > tick || (*time == tick && event.ends_note())
+            })
+            .unwrap_or(events.len());
+        events.insert(position, (tick, kind));
+        for (time, kind) in events.iter_mut().skip(position + 1) {
+            if is_end_of_track(kind) {
+                *time = (*time).max(tick);
+            }
+        }
+
+        *smf_track = delta_track(events)?;
+        self.rebuild_tracks()
+    }
+
+    /// Remove an event from a track by index; the following event keeps its absolute time
+    pub fn remove_event(&mut self, track: usize, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let smf_track = self.smf.tracks.get_mut(track).ok_or("Track index out of range")?;
+        if index >= smf_track.len() {
+            return Err("Event index out of range".into());
+        }
+
+        let removed = smf_track.remove(index);
+        if let Some(next) = smf_track.get_mut(index) {
+            let delta = next.delta.as_int().saturating_add(removed.delta.as_int());
+            next.delta = u28::try_from(delta).ok_or("Delta time too large")?;
+        }
+        self.rebuild_tracks()
+    }
+
+    /// Insert a note-on at `tick` and its note-off `duration_ticks` later.
+    /// The duration must be at least one tick: a note-off at the same tick would be placed
+    /// before its note-on and leave the note stuck.
+    pub fn insert_note(
+        &mut self,
+        track: usize,
+        tick: u32,
+        channel: u8,
+        note: u8,
+        velocity: u8,
+        duration_ticks: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if velocity == 0 {
+            return Err("Note velocity must be at least 1".into());
+        }
+        if duration_ticks == 0 {
+            return Err("Note duration must be at least 1 tick".into());
+        }
+        let end_tick = tick.checked_add(duration_ticks).ok_or("Note ends past the last tick")?;
+
+        let event = |absolute_time, event_type, data2| AbsoluteEvent {
+            absolute_time,
+            event_type,
+            channel,
+            data1: note,
+            data2,
+            text: String::new(),
+        };
+        self.insert_event(track, event(tick, EventType::NoteOn, velocity))?;
+        self.insert_event(track, event(end_tick, EventType::NoteOff, 0))
+    }
+
+    /// Split a track into a format 1 file with one track per channel used, in channel order.
+    /// The first track is a conductor track holding the source track's meta and SysEx events
+    /// plus the tempo, time and key signatures of the other tracks, so timing is unchanged.
+    pub fn split_by_channel(&self, track: usize) -> Result<MidiFile, Box<dyn std::error::Error>> {
+        let source = absolute_track(self.smf.tracks.get(track).ok_or("Track index out of range")?);
+        let is_end_of_track = |kind: &TrackEventKind| matches!(kind, TrackEventKind::Meta(MetaMessage::EndOfTrack));
+        let end_tick = source.last().map_or(0, |(time, _)| *time);
+
+        let mut conductor: Vec<(u32, TrackEventKind<'static>)> = source.iter()
+            .filter(|(_, kind)| !matches!(kind, TrackEventKind::Midi { .. }) && !is_end_of_track(kind))
+            .copied()
+            .collect();
+        for (_, other) in self.smf.tracks.iter().enumerate().filter(|(index, _)| *index != track) {
+            conductor.extend(absolute_track(other).into_iter().filter(|(time, kind)| {
+                *time <= end_tick && matches!(kind, TrackEventKind::Meta(
+                    MetaMessage::Tempo(_) | MetaMessage::TimeSignature(..) | MetaMessage::KeySignature(..)
+                ))
+            }));
+        }
+        conductor.sort_by_key(|(time, _)| *time);
+
+        let mut tracks = vec![conductor];
+        for channel in 0..16u8 {
+            let events: Vec<(u32, TrackEventKind<'static>)> = source.iter()
+                .filter(|(_, kind)| matches!(kind, TrackEventKind::Midi { channel: event_channel, .. } if event_channel.as_int() == channel))
+                .copied()
+                .collect();
+            if !events.is_empty() {
+                tracks.push(events);
+            }
+        }
+
+        let tracks = tracks.into_iter()
+            .map(|mut events| {
+                events.push((end_tick, TrackEventKind::Meta(MetaMessage::EndOfTrack)));
+                delta_track(events)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The events still borrow this file's bytes, so the split file gets its own copy
+        let mut data = Vec::new();
+        Smf { header: Header::new(Format::Parallel, self.timing), tracks }.write_std(&mut data)?;
+        Self::from_bytes(&data)
+    }
+
+    /// Mute or unmute a track for playback and export
+    pub fn set_track_mute(&mut self, track: usize, mute: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.tracks.get_mut(track).ok_or("Track index out of range")?.mute = mute;
+        Ok(())
+    }
+
+    /// Solo or unsolo a track for playback and export
+    pub fn set_track_solo(&mut self, track: usize, solo: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.tracks.get_mut(track).ok_or("Track index out of range")?.solo = solo;
+        Ok(())
+    }
+
+    /// Whether a track's channel events are played and exported: it is not muted and, if any
+    /// track is soloed, it is soloed too. Mute wins over solo.
+    pub fn is_track_audible(&self, track: usize) -> bool {
+        let any_solo = self.tracks.iter().any(|track| track.solo);
+        self.tracks.get(track).is_some_and(|track| !track.mute && (track.solo || !any_solo))
+    }
+
+    /// Serialize the file (including any edits) as Standard MIDI File bytes. Muted (or not
+    /// soloed) tracks are written without their channel events, keeping the track layout
+    /// and tempo map.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut smf = self.smf.clone();
+        for (track_idx, track) in smf.tracks.iter_mut().enumerate() {
+            if self.is_track_audible(track_idx) {
+                continue;
+            }
+            // Removed events carry their delta over so the remaining events keep their time
+            let mut carried_delta = 0u32;
+            track.retain_mut(|event| {
+                if let TrackEventKind::Midi { .. } = event.kind {
+                    carried_delta += event.delta.as_int();
+                    false
+                } else {
+                    event.delta = u28::new(event.delta.as_int() + carried_delta);
+                    carried_delta = 0;
+                    true
+                }
+            });
+        }
+
+        let mut data = Vec::new();
+        smf.write_std(&mut data)?;
+        Ok(data)
+    }
+
+    /// Write the file (including any edits) to disk
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Rebuild the processed track view after editing `smf`, keeping mute and solo
+    fn rebuild_tracks(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tracks = Vec::new();
+        for (track_idx, track) in self.smf.tracks.iter().enumerate() {
+            let mut track_data = Self::process_track(track, track_idx, self.timing, zero_velocity_as_note_off())?;
+            if let Some(previous) = self.tracks.get(track_idx) {
+                track_data.mute = previous.mute;
+                track_data.solo = previous.solo;
+            }
+            tracks.push(track_data);
+        }
+        self.tracks = tracks;
+        Ok(())
+    }
+    
     /// Convert ticks to milliseconds (approximate)
     pub fn ticks_to_ms(&self, ticks: u32, tempo_us_per_quarter: u32) -> f64 {
         match self.timing {
@@ -280,16 +1047,110 @@ impl MidiFile {
     }
 }
 
+impl AbsoluteEvent {
+    /// Note Off, or Note On with velocity 0 kept as such by `set_zero_velocity_as_note_off`
+    fn ends_note(&self) -> bool {
+        self.event_type == EventType::NoteOff || (self.event_type == EventType::NoteOn && self.data2 == 0)
+    }
+
+    /// The channel message this event describes, or None for meta and SysEx events
+    fn to_midi_message(&self) -> Option<MidiMessage> {
+        let data1 = (self.data1 & 0x7F).into();
+        let data2 = (self.data2 & 0x7F).into();
+        let message = match self.event_type {
+            EventType::NoteOff => MidiMessage::NoteOff { key: data1, vel: data2 },
+            EventType::NoteOn => MidiMessage::NoteOn { key: data1, vel: data2 },
+            EventType::PolyphonicAftertouch => MidiMessage::Aftertouch { key: data1, vel: data2 },
+            EventType::ControlChange => MidiMessage::Controller { controller: data1, value: data2 },
+            EventType::ProgramChange => MidiMessage::ProgramChange { program: data1 },
+            EventType::ChannelAftertouch => MidiMessage::ChannelAftertouch { vel: data1 },
+            EventType::PitchBend => {
+                let bend = ((self.data2 as u16 & 0x7F) << 7) | (self.data1 as u16 & 0x7F);
+                MidiMessage::PitchBend { bend: midly::PitchBend(bend.into()) }
+            }
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+/// A track's events with absolute tick times
+fn absolute_track(track: &[TrackEvent<'static>]) -> Vec<(u32, TrackEventKind<'static>)> {
+    let mut absolute_time = 0u32;
+    track.iter()
+        .map(|event| {
+            absolute_time = absolute_time.saturating_add(event.delta.as_int());
+            (absolute_time, event.kind)
+        })
+        .collect()
+}
+
+/// Turn events with (sorted) absolute tick times back into delta-timed track events
+fn delta_track(events: Vec<(u32, TrackEventKind<'static>)>) -> Result<Vec<TrackEvent<'static>>, Box<dyn std::error::Error>> {
+    let mut last_time = 0u32;
+    events.into_iter()
+        .map(|(time, kind)| {
+            let delta = u28::try_from(time - last_time).ok_or("Delta time too large")?;
+            last_time = time;
+            Ok(TrackEvent { delta, kind })
+        })
+        .collect()
+}
+
+/// Why a MIDI file could not be loaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum MidiFileError {
+    FileNotFound(String),
+    /// The file exists but could not be read
+    IoError(String),
+    /// The data is not a valid Standard MIDI File
+    ParseError(String),
+    /// The file has no data or no tracks
+    Empty,
+}
+
+impl fmt::Display for MidiFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiFileError::FileNotFound(s) => write!(f, "File not found: {}", s),
+            MidiFileError::IoError(s) => write!(f, "Could not read file: {}", s),
+            MidiFileError::ParseError(s) => write!(f, "Invalid MIDI file: {}", s),
+            MidiFileError::Empty => write!(f, "MIDI file is empty"),
+        }
+    }
+}
+
+impl std::error::Error for MidiFileError {}
+
+/// Read and parse a MIDI file from disk without registering it
+fn read_midi_file(path: &Path) -> Result<MidiFile, MidiFileError> {
+    let data = fs::read(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => MidiFileError::FileNotFound(path.display().to_string()),
+        _ => MidiFileError::IoError(e.to_string()),
+    })?;
+    if data.is_empty() {
+        return Err(MidiFileError::Empty);
+    }
+
+    let midi_file = MidiFile::from_bytes(&data).map_err(|e| MidiFileError::ParseError(e.to_string()))?;
+    if midi_file.tracks.is_empty() {
+        return Err(MidiFileError::Empty);
+    }
+    Ok(midi_file)
+}
+
 /// Load a MIDI file from disk
-pub fn load_midi_file<P: AsRef<Path>>(path: P) -> Result<i32, Box<dyn std::error::Error>> {
-    let data = fs::read(path)?;
-    let midi_file = MidiFile::from_bytes(&data)?;
-    
+pub fn load_midi_file<P: AsRef<Path>>(path: P) -> Result<i32, MidiFileError> {
+    let midi_file = read_midi_file(path.as_ref())?;
+    Ok(register_midi_file(midi_file))
+}
+
+/// Register an in-memory MIDI file (e.g. a recording) and return its handle
+pub fn register_midi_file(midi_file: MidiFile) -> i32 {
     let handle = get_next_file_handle();
     let mut files = get_midi_files().lock().unwrap();
     files.insert(handle, midi_file);
-    
-    Ok(handle)
+    handle
 }
 
 /// Get a reference to a loaded MIDI file
@@ -300,18 +1161,593 @@ pub fn get_midi_file(_handle: i32) -> Option<std::sync::MutexGuard<'static, Hash
 /// Close a MIDI file and free its resources
 pub fn close_midi_file(handle: i32) -> bool {
     let mut files = get_midi_files().lock().unwrap();
-    files.remove(&handle).is_some()
+    let closed = files.remove(&handle).is_some();
+    if closed {
+        get_file_handles().lock().unwrap().release(handle);
+    }
+    closed
+}
+
+/// Close every loaded MIDI file and restart handle numbering
+pub fn close_all_midi_files() {
+    get_midi_files().lock().unwrap().clear();
+    get_file_handles().lock().unwrap().reset();
+}
+
+/// Bits of an event UID holding the event index and the track index; the file handle fills
+/// the 31 bits above them
+const UID_EVENT_BITS: u32 = 21;
+const UID_TRACK_BITS: u32 = 12;
+
+/// Pack an event's position into a UID that is unique across every open file:
+///
+/// | bits  | field        | range              |
+/// |-------|--------------|--------------------|
+/// | 33-63 | file handle  | any (positive i32) |
+/// | 21-32 | track index  | 0 - 4,095          |
+/// | 0-20  | event index  | 0 - 2,097,151      |
+///
+/// The whole handle is kept, including the generation a reused handle carries, so a UID
+/// never collides with one from an earlier file. Returns `None` if an index is out of range.
+pub fn event_uid(file_handle: i32, track_index: i32, event_index: i32) -> Option<u64> {
+    if file_handle <= 0
+        || !(0..1 << UID_TRACK_BITS).contains(&track_index)
+        || !(0..1 << UID_EVENT_BITS).contains(&event_index)
+    {
+        return None;
+    }
+    Some(
+        (file_handle as u64) << (UID_TRACK_BITS + UID_EVENT_BITS)
+            | (track_index as u64) << UID_EVENT_BITS
+            | event_index as u64,
+    )
+}
+
+/// Split a UID from `event_uid` back into (file handle, track index, event index)
+pub fn decode_event_uid(uid: u64) -> (i32, i32, i32) {
+    (
+        (uid >> (UID_TRACK_BITS + UID_EVENT_BITS)) as i32,
+        ((uid >> UID_EVENT_BITS) & ((1 << UID_TRACK_BITS) - 1)) as i32,
+        (uid & ((1 << UID_EVENT_BITS) - 1)) as i32,
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+
+    /// Format 0 file at 96 ticks per quarter: track name, 120 BPM tempo, notes on channels 2
+    /// and 10 released a quarter later (the drum note by running status with velocity 0),
+    /// and the end of track five quarters after that
+    const SAMPLE_FILE: [u8; 58] = [
+        b'M', b'T', b'h', b'd', 0x00, 0x00, 0x00, 0x06,
+        0x00, 0x00, // Format 0
+        0x00, 0x01, // One track
+        0x00, 0x60, // 96 ticks per quarter
+        b'M', b'T', b'r', b'k', 0x00, 0x00, 0x00, 0x24,
+        0x00, 0xFF, 0x03, 0x05, b'P', b'i', b'a', b'n', b'o',
+        0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20,
+        0x00, 0x91, 0x3C, 0x64,
+        0x00, 0x99, 0x24, 0x64,
+        0x60, 0x24, 0x00,
+        0x00, 0x81, 0x3C, 0x40,
+        0x83, 0x60, 0xFF, 0x2F, 0x00,
+    ];
+
+    #[test]
+    fn test_parse_sample_file() {
+        let midi_file = MidiFile::from_bytes(&SAMPLE_FILE).unwrap();
+        assert_eq!(midi_file.format, 0);
+        assert!(matches!(midi_file.timing, Timing::Metrical(tpq) if tpq.as_int() == 96));
+        assert_eq!(midi_file.tracks.len(), 1);
+
+        let track = &midi_file.tracks[0];
+        assert_eq!(track.name, "Piano");
+        assert_eq!(track.instrument, None);
+        assert_eq!(track.channel_mask, (1 << 1) | (1 << 9));
+
+        let events: Vec<(u32, EventType, u8, u8, u8)> = track.events.iter()
+            .map(|event| (event.absolute_time, event.event_type.clone(), event.channel, event.data1, event.data2))
+            .collect();
+        assert_eq!(events, vec![
+            (0, EventType::MetaTrackName, 0, 0, 0),
+            (0, EventType::MetaSetTempo, 0, 0, 0),
+            (0, EventType::NoteOn, 1, 60, 100),
+            (0, EventType::NoteOn, 9, 36, 100),
+            (96, EventType::NoteOff, 9, 36, 0),
+            (96, EventType::NoteOff, 1, 60, 64),
+            (576, EventType::MetaEndOfTrack, 0, 0, 0),
+        ]);
+        assert_eq!(track.events[1].text, "Tempo: 500000 μs/quarter");
+
+        assert_eq!(midi_file.get_duration_ticks(), 576);
+        assert_eq!(midi_file.duration_ms(), 3000.0);
+        assert_eq!(midi_file.get_initial_bpm(), 120.0);
+        assert_eq!(midi_file.tick_to_ms(96), 500.0);
+        assert!(midi_file.validate_notes().is_empty());
+
+        let timed: Vec<(f64, Vec<u8>)> = midi_file.timed_events().collect();
+        assert_eq!(timed, vec![
+            (0.0, vec![0x91, 60, 100]),
+            (0.0, vec![0x99, 36, 100]),
+            (500.0, vec![0x99, 36, 0]),
+            (0.0, vec![0x81, 60, 64]),
+        ]);
+    }
+
+    #[test]
+    fn test_sample_file_round_trip() {
+        let midi_file = MidiFile::from_bytes(&SAMPLE_FILE).unwrap();
+        let reparsed = MidiFile::from_bytes(&midi_file.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(reparsed.tracks.len(), 1);
+        assert_eq!(reparsed.tracks[0].name, "Piano");
+        assert_eq!(reparsed.tracks[0].channel_mask, midi_file.tracks[0].channel_mask);
+        assert_eq!(reparsed.tracks[0].events.len(), midi_file.tracks[0].events.len());
+        assert_eq!(reparsed.get_duration_ticks(), 576);
+    }
+
+    #[test]
+    fn test_truncated_file_is_rejected() {
+        assert!(MidiFile::from_bytes(&SAMPLE_FILE[..10]).is_err()); // Inside the header
+        assert!(MidiFile::from_bytes(&SAMPLE_FILE[8..]).is_err()); // No "MThd"
+    }
+
+    #[test]
+    fn test_split_by_channel() {
+        let midi_file = MidiFile::from_bytes(&SAMPLE_FILE).unwrap();
+        let split = midi_file.split_by_channel(0).unwrap();
+
+        assert_eq!(split.format, 1);
+        assert_eq!(split.tracks.len(), 3); // Conductor, channel 2, channel 10
+        assert_eq!(split.tracks[0].name, "Piano");
+        assert_eq!(split.tracks[0].channel_mask, 0);
+        assert_eq!(split.tracks[1].channel_mask, 1 << 1);
+        assert_eq!(split.tracks[2].channel_mask, 1 << 9);
+        assert_eq!(split.get_duration_ticks(), 576);
+        assert_eq!(split.duration_ms(), midi_file.duration_ms());
+
+        let types = |track: &TrackData| track.events.iter().map(|event| event.event_type.clone()).collect::<Vec<_>>();
+        assert_eq!(types(&split.tracks[0]), [EventType::MetaTrackName, EventType::MetaSetTempo, EventType::MetaEndOfTrack]);
+        assert_eq!(types(&split.tracks[2]), [EventType::NoteOn, EventType::NoteOff, EventType::MetaEndOfTrack]);
+        assert_eq!(split.tracks[2].events[1].absolute_time, 96);
+
+        assert!(midi_file.split_by_channel(1).is_err());
+
+        // The split file owns its bytes and outlives the source
+        drop(midi_file);
+        let reparsed = MidiFile::from_bytes(&split.to_bytes().unwrap()).unwrap();
+        assert_eq!(reparsed.tracks[0].name, "Piano");
+    }
+
+    #[test]
+    fn test_read_errors_are_typed() {
+        let dir = std::env::temp_dir();
+        let write = |name: &str, data: &[u8]| {
+            let path = dir.join(format!("labview_midi_{}_{}.mid", std::process::id(), name));
+            fs::write(&path, data).unwrap();
+            path
+        };
+
+        let missing = dir.join("labview_midi_does_not_exist.mid");
+        assert!(matches!(read_midi_file(&missing), Err(MidiFileError::FileNotFound(_))));
+
+        let empty = write("empty", &[]);
+        let garbage = write("garbage", b"not a MIDI file");
+        let sample = write("sample", &SAMPLE_FILE);
+        assert_eq!(read_midi_file(&empty).err(), Some(MidiFileError::Empty));
+        let parse_error = read_midi_file(&garbage).err().unwrap();
+        assert!(matches!(parse_error, MidiFileError::ParseError(_)));
+        assert_eq!(read_midi_file(&sample).unwrap().tracks[0].name, "Piano");
+
+        for path in [empty, garbage, sample] {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn test_mute_and_solo() {
+        let mut midi_file = MidiFile::from_bytes(&SAMPLE_FILE).unwrap().split_by_channel(0).unwrap();
+        let played_statuses = |midi_file: &MidiFile| {
+            midi_file.timed_events().map(|(_, bytes)| bytes[0]).collect::<Vec<u8>>()
+        };
+        assert_eq!(played_statuses(&midi_file), [0x91, 0x99, 0x81, 0x99]);
+
+        midi_file.set_track_mute(2, true).unwrap();
+        assert_eq!(played_statuses(&midi_file), [0x91, 0x81]);
+        assert_eq!(midi_file.duration_ms(), 3000.0);
+
+        // Flags survive edits
+        midi_file.insert_note(1, 0, 1, 64, 90, 96).unwrap();
+        assert!(midi_file.tracks[2].mute);
+
+        let exported = MidiFile::from_bytes(&midi_file.to_bytes().unwrap()).unwrap();
+        assert_eq!(exported.tracks.len(), 3);
+        assert_eq!(exported.tracks[2].channel_mask, 0);
+        assert_eq!(exported.get_duration_ticks(), 576);
+
+        midi_file.set_track_mute(2, false).unwrap();
+        midi_file.set_track_solo(2, true).unwrap();
+        assert_eq!(played_statuses(&midi_file), [0x99, 0x99]);
+        assert!(!midi_file.is_track_audible(1));
+        assert!(midi_file.set_track_solo(3, true).is_err());
+    }
+
+    #[test]
+    fn test_event_uids_keep_the_whole_handle() {
+        let reused = (2047 << 20) | 256; // Slot 256 in its last generation
+        let uid = event_uid(reused, 4095, 2_097_151).unwrap();
+        assert_eq!(decode_event_uid(uid), (reused, 4095, 2_097_151));
+        assert_eq!(decode_event_uid(event_uid(i32::MAX, 0, 0).unwrap()), (i32::MAX, 0, 0));
+
+        // Generations of one slot no longer share UIDs
+        assert_ne!(event_uid(256, 0, 0), event_uid((16 << 20) | 256, 0, 0));
+        assert_ne!(event_uid(1, 0, 0), event_uid(257, 0, 0));
+
+        assert_eq!(event_uid(1, 4096, 0), None);
+        assert_eq!(event_uid(1, 0, 2_097_152), None);
+        assert_eq!(event_uid(0, 0, 0), None);
+    }
+
     #[test]
     fn test_midi_file_lifecycle() {
-        // This test would need a sample MIDI file
-        // For now, just test the basic structure
-        assert_eq!(get_next_file_handle(), 1);
-        assert_eq!(get_next_file_handle(), 2);
+        // Numbering is checked on a private allocator: the global file table is shared with
+        // tests running in parallel
+        let mut handles = HandleAllocator::new();
+        assert_eq!(handles.allocate(), 1);
+        assert_eq!(handles.allocate(), 2);
+
+        let handle = register_midi_file(MidiFile::from_bytes(&SAMPLE_FILE).unwrap());
+        assert!(handle > 0);
+        assert!(get_midi_file(handle).unwrap().contains_key(&handle));
+        assert!(close_midi_file(handle));
+        assert!(!close_midi_file(handle));
+    }
+
+    #[test]
+    fn test_timed_events_follow_tempo_changes() {
+        use midly::num::{u4, u7};
+
+        let event = |delta: u32, kind| TrackEvent { delta: u28::new(delta), kind };
+        let note = |delta: u32, key: u8, vel: u8| event(delta, TrackEventKind::Midi {
+            channel: u4::new(0),
+            message: MidiMessage::NoteOn { key: u7::new(key), vel: u7::new(vel) },
+        });
+
+        // 120 BPM for the first quarter note, then 240 BPM
+        let tempo_track = vec![
+            event(0, TrackEventKind::Meta(MetaMessage::Tempo(u24::new(500_000)))),
+            event(480, TrackEventKind::Meta(MetaMessage::Tempo(u24::new(250_000)))),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ];
+        let note_track = vec![
+            note(0, 60, 100),
+            note(480, 64, 100),
+            note(480, 60, 0),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ];
+        let smf = Smf {
+            header: Header::new(Format::Parallel, Timing::Metrical(u15::new(480))),
+            tracks: vec![tempo_track, note_track],
+        };
+        let mut data = Vec::new();
+        smf.write_std(&mut data).unwrap();
+
+        let midi_file = MidiFile::from_bytes(&data).unwrap();
+        let events: Vec<(f64, Vec<u8>)> = midi_file.timed_events().collect();
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0], (0.0, vec![0x90, 60, 100]));
+        assert_eq!(events[1], (500.0, vec![0x90, 64, 100]));
+        assert_eq!(events[2], (250.0, vec![0x90, 60, 0]));
+    }
+
+    #[test]
+    fn test_duration_follows_tempo_map() {
+        use midly::num::{u4, u7};
+
+        let event = |delta: u32, kind| TrackEvent { delta: u28::new(delta), kind };
+        // One quarter at 120 BPM (500 ms), then two quarters at 60 BPM (2000 ms)
+        let track = vec![
+            event(0, TrackEventKind::Meta(MetaMessage::Tempo(u24::new(500_000)))),
+            event(480, TrackEventKind::Meta(MetaMessage::Tempo(u24::new(1_000_000)))),
+            event(960, TrackEventKind::Midi {
+                channel: u4::new(0),
+                message: MidiMessage::NoteOff { key: u7::new(60), vel: u7::new(0) },
+            }),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ];
+        let midi_file = MidiFile::from_smf(Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480))),
+            tracks: vec![track],
+        }).unwrap();
+
+        assert_eq!(midi_file.duration_ms(), 2500.0);
+        assert_eq!(midi_file.tick_to_ms(480), 500.0);
+        assert_eq!(midi_file.tick_to_ms(720), 1000.0);
+
+        assert_eq!(midi_file.ms_to_ticks(500.0), 480);
+        assert_eq!(midi_file.ms_to_ticks(1000.0), 720);
+        assert_eq!(midi_file.ms_to_ticks(midi_file.duration_ms()), 1440);
+        assert_eq!(midi_file.ms_to_ticks(-10.0), 0);
+        for tick in [0, 1, 479, 481, 1000] {
+            assert_eq!(midi_file.ms_to_ticks(midi_file.tick_to_ms(tick)), tick);
+        }
+    }
+
+    #[test]
+    fn test_uniform_tempo_replaces_tempo_map() {
+        let messages = vec![(0, vec![0x90, 60, 100]), (500_000, vec![0x80, 60, 0])];
+        let mut midi_file = MidiFile::from_recording(&messages, 480, 250_000).unwrap();
+        assert_eq!(midi_file.get_initial_bpm(), 240.0);
+
+        midi_file.set_uniform_tempo(90.0).unwrap();
+        assert!((midi_file.get_initial_bpm() - 90.0).abs() < 0.001);
+
+        let tempo_events = midi_file.tracks[0].events.iter()
+            .filter(|event| event.event_type == EventType::MetaSetTempo)
+            .count();
+        assert_eq!(tempo_events, 1);
+
+        // Note timing is unchanged in ticks
+        let note_off = midi_file.tracks[0].events.iter()
+            .find(|event| event.event_type == EventType::NoteOff)
+            .unwrap();
+        assert_eq!(note_off.absolute_time, 960);
+
+        assert!(midi_file.set_uniform_tempo(0.0).is_err());
+    }
+
+    #[test]
+    fn test_validate_and_fix_dangling_notes() {
+        let messages = vec![
+            (0, vec![0x80, 62, 0]),       // orphan note-off
+            (100_000, vec![0x90, 60, 100]),
+            (200_000, vec![0x91, 64, 100]), // never released
+            (300_000, vec![0x90, 60, 0]),
+        ];
+        let mut midi_file = MidiFile::from_recording(&messages, 480, 500_000).unwrap();
+
+        let issues = midi_file.validate_notes();
+        assert_eq!(issues, vec![
+            NoteIssue { track: 0, tick: 0, channel: 0, note: 62, kind: NoteIssueKind::OrphanNoteOff },
+            NoteIssue { track: 0, tick: 192, channel: 1, note: 64, kind: NoteIssueKind::StuckNote },
+        ]);
+
+        assert_eq!(midi_file.fix_dangling_notes().unwrap(), 1);
+        let remaining = midi_file.validate_notes();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].kind, NoteIssueKind::OrphanNoteOff);
+
+        // The inserted note-off sits just before End of Track
+        let events = &midi_file.tracks[0].events;
+        let inserted = &events[events.len() - 2];
+        assert_eq!((inserted.event_type.clone(), inserted.channel, inserted.data1), (EventType::NoteOff, 1, 64));
+        assert_eq!(events.last().unwrap().event_type, EventType::MetaEndOfTrack);
+    }
+
+    #[test]
+    fn test_play_from_chases_controllers_and_skips_notes() {
+        let messages = vec![
+            (0, vec![0xB0, 0, 1]),          // bank select
+            (0, vec![0xC0, 40]),            // program
+            (0, vec![0xB0, 7, 80]),         // volume, overridden below
+            (250_000, vec![0x90, 60, 100]), // note already sounding at the seek point
+            (250_000, vec![0xB0, 7, 100]),
+            (400_000, vec![0xE0, 0x00, 0x50]),
+            (500_000, vec![0x80, 60, 0]),
+            (750_000, vec![0x90, 64, 100]),
+        ];
+        // 480 ticks per quarter at 120 BPM: 500 ms = 480 ticks
+        let midi_file = MidiFile::from_recording(&messages, 480, 500_000).unwrap();
+
+        assert_eq!(midi_file.chase_messages(480), vec![
+            vec![0xB0, 0, 1],
+            vec![0xC0, 40],
+            vec![0xB0, 7, 100],
+            vec![0xE0, 0x00, 0x50],
+        ]);
+
+        let events: Vec<(f64, Vec<u8>)> = midi_file.timed_events_from(480).collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], (0.0, vec![0x80, 60, 0]));
+        assert!((events[1].0 - 250.0).abs() < 1.0);
+        assert_eq!(events[1].1, vec![0x90, 64, 100]);
+
+        // Starting between events measures the first delay from the start tick
+        let events: Vec<(f64, Vec<u8>)> = midi_file.timed_events_from(192).collect();
+        assert!((events[0].0 - 50.0).abs() < 1e-9);
+        assert_eq!(events[0].1, vec![0x90, 60, 100]);
+    }
+
+    #[test]
+    fn test_events_of_type() {
+        let messages = vec![
+            (0, vec![0x90, 60, 100]),
+            (1_000, vec![0xB0, 7, 90]),
+            (2_000, vec![0x80, 60, 0]),
+            (3_000, vec![0x90, 62, 100]),
+        ];
+        let midi_file = MidiFile::from_recording(&messages, 480, 500_000).unwrap();
+
+        let note_ons = midi_file.events_of_type(0, EventType::NoteOn);
+        assert_eq!(note_ons.iter().map(|event| event.data1).collect::<Vec<_>>(), vec![60, 62]);
+        assert_eq!(midi_file.events_of_type(0, EventType::ControlChange).len(), 1);
+        assert!(midi_file.events_of_type(1, EventType::NoteOn).is_empty());
+
+        assert_eq!(EventType::MetaSetTempo.code(), 18);
+        assert_eq!(EventType::from_code(18), Some(EventType::MetaSetTempo));
+        assert_eq!(EventType::from_code(23), Some(EventType::Unknown));
+        assert_eq!(EventType::from_code(24), None);
+        assert_eq!(EventType::from_code(-1), None);
+    }
+
+    #[test]
+    fn test_find_text() {
+        let event = |delta: u32, kind| TrackEvent { delta: u28::new(delta), kind };
+        let meta = |delta, message| event(delta, TrackEventKind::Meta(message));
+        let tracks = vec![
+            vec![
+                meta(0, MetaMessage::TrackName(b"Vocals")),
+                meta(0, MetaMessage::InstrumentName(b"Voice Oohs")),
+                meta(100, MetaMessage::Lyric(b"Hello ")),
+                meta(100, MetaMessage::Lyric(b"world")),
+                meta(0, MetaMessage::EndOfTrack),
+            ],
+            vec![
+                meta(0, MetaMessage::Marker(b"Verse: hello again")),
+                meta(0, MetaMessage::EndOfTrack),
+            ],
+        ];
+        let midi_file = MidiFile::from_smf(Smf {
+            header: Header::new(Format::Parallel, Timing::Metrical(u15::new(480))),
+            tracks,
+        }).unwrap();
+
+        assert_eq!(midi_file.find_text("HELLO"), vec![(0, 2), (1, 0)]);
+        assert_eq!(midi_file.find_text("vocals"), vec![(0, 0)]);
+        assert!(midi_file.find_text("oohs").is_empty()); // Instrument names are not searched
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_note_spans_with_sustain() {
+        use midly::num::{u4, u7};
+
+        let event = |delta: u32, kind| TrackEvent { delta: u28::new(delta), kind };
+        let midi = |delta: u32, message| event(delta, TrackEventKind::Midi { channel: u4::new(0), message });
+        let on = |delta, key: u8| midi(delta, MidiMessage::NoteOn { key: u7::new(key), vel: u7::new(90) });
+        let off = |delta, key: u8| midi(delta, MidiMessage::NoteOff { key: u7::new(key), vel: u7::new(0) });
+        let pedal = |delta, value: u8| midi(delta, MidiMessage::Controller { controller: u7::new(64), value: u7::new(value) });
+
+        let track = vec![
+            on(0, 60),
+            pedal(100, 127),
+            off(100, 60),   // released at 200 under the pedal
+            on(0, 64),
+            off(100, 64),   // released at 300 under the pedal
+            pedal(200, 0),  // pedal up at 500
+            on(0, 67),
+            off(100, 67),   // released at 600, pedal up
+            on(0, 72),      // never released
+            event(200, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ];
+        let midi_file = MidiFile::from_smf(Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480))),
+            tracks: vec![track],
+        }).unwrap();
+
+        let ends = |apply_sustain| midi_file.note_spans(0, apply_sustain)
+            .iter()
+            .map(|span| (span.note, span.start_tick, span.end_tick))
+            .collect::<Vec<_>>();
+        assert_eq!(ends(false), vec![(60, 0, 200), (64, 200, 300), (67, 500, 600), (72, 600, 800)]);
+        assert_eq!(ends(true), vec![(60, 0, 500), (64, 200, 500), (67, 500, 600), (72, 600, 800)]);
+
+        let durations = midi_file.note_events(0)
+            .iter()
+            .map(|event| (event.note, event.start_tick, event.duration_ticks))
+            .collect::<Vec<_>>();
+        assert_eq!(durations, vec![(60, 0, 200), (64, 200, 100), (67, 500, 100), (72, 600, NOTE_UNMATCHED_DURATION)]);
+        assert_eq!(midi_file.note_events(0)[0].velocity, 90);
+    }
+
+    #[test]
+    fn test_insert_and_remove_events() {
+        use midly::num::{u4, u7};
+
+        let event = |delta: u32, kind| TrackEvent { delta: u28::new(delta), kind };
+        let midi = |delta: u32, message| event(delta, TrackEventKind::Midi { channel: u4::new(0), message });
+        let track = vec![
+            midi(0, MidiMessage::NoteOn { key: u7::new(60), vel: u7::new(90) }),
+            midi(480, MidiMessage::NoteOff { key: u7::new(60), vel: u7::new(0) }),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ];
+        let mut midi_file = MidiFile::from_smf(Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480))),
+            tracks: vec![track],
+        }).unwrap();
+
+        // Repeats the note right where the first one ends, and runs past End of Track
+        midi_file.insert_note(0, 480, 0, 60, 100, 480).unwrap();
+        midi_file.insert_note(0, 240, 1, 64, 80, 120).unwrap();
+        assert!(midi_file.insert_note(1, 0, 0, 60, 100, 10).is_err());
+        assert!(midi_file.insert_note(0, 100, 0, 62, 100, 0).is_err()); // Would leave a stuck note
+
+        let summary = |midi_file: &MidiFile| midi_file.tracks[0].events.iter()
+            .map(|event| (event.absolute_time, event.event_type.clone(), event.data1))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            (0, EventType::NoteOn, 60),
+            (240, EventType::NoteOn, 64),
+            (360, EventType::NoteOff, 64),
+            (480, EventType::NoteOff, 60),
+            (480, EventType::NoteOn, 60),
+            (960, EventType::NoteOff, 60),
+            (960, EventType::MetaEndOfTrack, 0),
+        ];
+        assert_eq!(summary(&midi_file), expected);
+
+        // The serialized deltas reproduce the same absolute times
+        let reloaded = MidiFile::from_bytes(&midi_file.to_bytes().unwrap()).unwrap();
+        assert_eq!(summary(&reloaded), expected);
+
+        midi_file.remove_event(0, 1).unwrap();
+        assert_eq!(midi_file.tracks[0].events[1].absolute_time, 360);
+        assert!(midi_file.remove_event(0, 42).is_err());
+    }
+
+    #[test]
+    fn test_recording_normalized_to_first_event() {
+        // 480 ticks per quarter at 500000 us per quarter: 1 tick = 1041.67 us
+        let messages = vec![
+            (10_000_000, vec![0x90, 60, 100]),
+            (10_250_000, vec![0xF8]), // Not a channel message, skipped
+            (10_500_000, vec![0x80, 60, 0]),
+        ];
+        let midi_file = MidiFile::from_recording(&messages, 480, 500_000).unwrap();
+
+        let events = &midi_file.tracks[0].events;
+        let notes: Vec<(u32, EventType)> = events.iter()
+            .filter(|event| matches!(event.event_type, EventType::NoteOn | EventType::NoteOff))
+            .map(|event| (event.absolute_time, event.event_type.clone()))
+            .collect();
+        assert_eq!(notes, vec![(0, EventType::NoteOn), (480, EventType::NoteOff)]);
+
+        assert!(MidiFile::from_recording(&messages, 0, 500_000).is_err());
+    }
+
+    #[test]
+    fn test_recording_ignores_leading_system_messages() {
+        let messages = vec![
+            (1_000_000, vec![0xFE]), // Active Sensing before the first note
+            (1_100_000, vec![0xF8]), // Clock
+            (1_500_000, vec![0x90, 60, 100]),
+            (2_000_000, vec![0x80, 60, 0]),
+        ];
+        let midi_file = MidiFile::from_recording(&messages, 480, 500_000).unwrap();
+
+        let notes: Vec<u32> = midi_file.tracks[0].events.iter()
+            .filter(|event| matches!(event.event_type, EventType::NoteOn | EventType::NoteOff))
+            .map(|event| event.absolute_time)
+            .collect();
+        assert_eq!(notes, vec![0, 480]);
+    }
+
+    #[test]
+    fn test_zero_velocity_note_on_processing() {
+        use midly::num::{u4, u7};
+
+        let track = vec![
+            TrackEvent { delta: u28::new(0), kind: TrackEventKind::Midi { channel: u4::new(0), message: MidiMessage::NoteOn { key: u7::new(60), vel: u7::new(100) } } },
+            TrackEvent { delta: u28::new(480), kind: TrackEventKind::Midi { channel: u4::new(0), message: MidiMessage::NoteOn { key: u7::new(60), vel: u7::new(0) } } },
+        ];
+        let timing = Timing::Metrical(u15::new(480));
+
+        let converted = MidiFile::process_track(&track, 0, timing, true).unwrap();
+        assert_eq!(converted.events[1].event_type, EventType::NoteOff);
+
+        let kept = MidiFile::process_track(&track, 0, timing, false).unwrap();
+        assert_eq!(kept.events[1].event_type, EventType::NoteOn);
+        assert!(kept.events[1].ends_note()); // Still ends the note for note pairing
+    }
+}
@@ -0,0 +1,73 @@
+//! OS scheduling priority for the threads that deliver MIDI input
+//!
+//! Priorities run from 0 (normal, time-shared scheduling) to `MAX_THREAD_PRIORITY` (the most
+//! urgent realtime level the OS offers). Raising a thread's priority is only safe for code
+//! that never blocks for long: a realtime thread that spins or waits on a lock held by a
+//! normal thread can starve the rest of the system, including LabVIEW's UI.
+
+use std::io;
+
+/// Highest priority accepted by `set_current_thread_priority`
+pub const MAX_THREAD_PRIORITY: i32 = 99;
+
+/// Whether this platform can change thread priorities at all
+pub const THREAD_PRIORITY_SUPPORTED: bool = cfg!(any(unix, windows));
+
+/// Set the calling thread's priority (0 to `MAX_THREAD_PRIORITY`).
+///
+/// On Unix, 0 selects `SCHED_OTHER` and anything higher `SCHED_FIFO` at that priority,
+/// clamped to the range the OS reports. Linux only grants `SCHED_FIFO` to processes with
+/// `CAP_SYS_NICE` or an `rtprio` limit, so this commonly fails with a permission error.
+#[cfg(unix)]
+pub fn set_current_thread_priority(priority: i32) -> io::Result<()> {
+    let (policy, priority) = if priority <= 0 {
+        (libc::SCHED_OTHER, 0)
+    } else {
+        let min = unsafe { libc::sched_get_priority_min(libc::SCHED_FIFO) };
+        let max = unsafe { libc::sched_get_priority_max(libc::SCHED_FIFO) };
+        (libc::SCHED_FIFO, priority.clamp(min, max))
+    };
+
+    let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+    param.sched_priority = priority;
+    match unsafe { libc::pthread_setschedparam(libc::pthread_self(), policy, &param) } {
+        0 => Ok(()),
+        error => Err(io::Error::from_raw_os_error(error)),
+    }
+}
+
+/// Set the calling thread's priority (0 to `MAX_THREAD_PRIORITY`).
+///
+/// Windows has a handful of levels rather than a numeric range: 0 is normal, 1-49 above
+/// normal, 50-89 highest and 90 and up time critical.
+#[cfg(windows)]
+pub fn set_current_thread_priority(priority: i32) -> io::Result<()> {
+    use std::ffi::c_void;
+
+    const THREAD_PRIORITY_NORMAL: i32 = 0;
+    const THREAD_PRIORITY_ABOVE_NORMAL: i32 = 1;
+    const THREAD_PRIORITY_HIGHEST: i32 = 2;
+    const THREAD_PRIORITY_TIME_CRITICAL: i32 = 15;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> *mut c_void;
+        fn SetThreadPriority(thread: *mut c_void, priority: i32) -> i32;
+    }
+
+    let level = match priority {
+        i32::MIN..=0 => THREAD_PRIORITY_NORMAL,
+        1..=49 => THREAD_PRIORITY_ABOVE_NORMAL,
+        50..=89 => THREAD_PRIORITY_HIGHEST,
+        _ => THREAD_PRIORITY_TIME_CRITICAL,
+    };
+    if unsafe { SetThreadPriority(GetCurrentThread(), level) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn set_current_thread_priority(_priority: i32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "thread priorities are not supported on this platform"))
+}